@@ -1,3 +1,9 @@
+use crate::tuple::Tuple;
+
+use rand::Rng;
+
+use std::f64::consts::PI;
+
 pub fn vector_subtract<const N: usize>(a: &[f64], b: &[f64]) -> [f64; N] {
     let mut c = [0.0; N];
     for ((a, b), c) in a.iter().zip(b).zip(&mut c) {
@@ -23,3 +29,116 @@ pub fn cross_product(a: &[f64], b: &[f64]) -> [f64; 4] {
         0.0,
     ]
 }
+
+/// Two unit vectors perpendicular to a unit normal and to each other, which
+/// with the normal make a right-handed basis: tangent × bitangent = normal.
+pub fn orthonormal_basis(normal: Tuple) -> (Tuple, Tuple) {
+    let helper = if normal.x().abs() > 0.9 {
+        Tuple::vector(0.0, 1.0, 0.0)
+    } else {
+        Tuple::vector(1.0, 0.0, 0.0)
+    };
+    let tangent = helper.cross(normal).normalize();
+    (tangent, normal.cross(tangent))
+}
+
+/// Random point in the unit disk, with every part of the disk equally
+/// likely.
+pub fn uniform_disk<R: Rng>(rng: &mut R) -> (f64, f64) {
+    let r = rng.gen::<f64>().sqrt();
+    let angle = 2.0 * PI * rng.gen::<f64>();
+    (r * angle.cos(), r * angle.sin())
+}
+
+/// Random unit vector, with every direction equally likely.
+pub fn uniform_sphere<R: Rng>(rng: &mut R) -> Tuple {
+    let z = 1.0 - 2.0 * rng.gen::<f64>();
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let angle = 2.0 * PI * rng.gen::<f64>();
+    Tuple::vector(r * angle.cos(), r * angle.sin(), z)
+}
+
+/// Random direction in the hemisphere around a unit normal, more likely the
+/// closer it is to the normal, in proportion to the cosine of the angle
+/// between them.
+pub fn cosine_hemisphere<R: Rng>(normal: Tuple, rng: &mut R) -> Tuple {
+    let (tangent, bitangent) = orthonormal_basis(normal);
+
+    // Project a uniformly sampled disk up onto the hemisphere.
+    let (x, y) = uniform_disk(rng);
+    let height = (1.0 - x * x - y * y).max(0.0).sqrt();
+    tangent * x + bitangent * y + normal * height
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Pcg32;
+    use crate::util::close_eq;
+
+    use rand::SeedableRng;
+
+    const SAMPLES: usize = 10_000;
+
+    #[test]
+    fn basis_is_orthonormal_and_right_handed_near_every_axis() {
+        let mut normals = Vec::new();
+        for axis in 0..3 {
+            for sign in [1.0, -1.0] {
+                // Each axis, and nearby normals either side of where the basis
+                // switches helpers.
+                for lean in [0.0, 0.01, 0.45, 0.5] {
+                    let mut n = [lean, lean, lean];
+                    n[axis] = sign;
+                    normals.push(Tuple::vector(n[0], n[1], n[2]).normalize());
+                }
+            }
+        }
+
+        for normal in normals {
+            let (tangent, bitangent) = orthonormal_basis(normal);
+            assert!(close_eq(tangent.magnitude(), 1.0), "{:?}", normal);
+            assert!(close_eq(bitangent.magnitude(), 1.0), "{:?}", normal);
+            assert!(close_eq(tangent.dot(normal), 0.0), "{:?}", normal);
+            assert!(close_eq(bitangent.dot(normal), 0.0), "{:?}", normal);
+            assert!(close_eq(tangent.dot(bitangent), 0.0), "{:?}", normal);
+            assert_eq!(tangent.cross(bitangent), normal);
+        }
+    }
+
+    #[test]
+    fn disk_samples_are_in_the_unit_disk() {
+        let mut rng = Pcg32::seed_from_u64(1);
+        for _ in 0..SAMPLES {
+            let (x, y) = uniform_disk(&mut rng);
+            assert!(x * x + y * y <= 1.0, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn sphere_samples_are_unit_vectors() {
+        let mut rng = Pcg32::seed_from_u64(2);
+        for _ in 0..SAMPLES {
+            let v = uniform_sphere(&mut rng);
+            assert!(close_eq(v.magnitude(), 1.0), "{:?}", v);
+            assert_eq!(v.w(), 0.0);
+        }
+    }
+
+    #[test]
+    fn hemisphere_samples_lean_toward_the_normal() {
+        let mut rng = Pcg32::seed_from_u64(3);
+        let normal = Tuple::vector(1.0, 2.0, -2.0).normalize();
+        let mut total = 0.0;
+        for _ in 0..SAMPLES {
+            let v = cosine_hemisphere(normal, &mut rng);
+            assert!(close_eq(v.magnitude(), 1.0), "{:?}", v);
+            let cosine = v.dot(normal);
+            assert!(cosine >= 0.0, "{:?}", v);
+            total += cosine;
+        }
+        // The mean of cos θ weighted by cos θ over the hemisphere is 2/3.
+        let mean = total / SAMPLES as f64;
+        assert!((mean - 2.0 / 3.0).abs() < 0.01, "mean cosine {}", mean);
+    }
+}
@@ -0,0 +1,672 @@
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::matrix::Matrix;
+use crate::object::Obj;
+use crate::post::PostProcess;
+use crate::rng::Pcg32;
+use crate::tuple::Tuple;
+use crate::video::VideoWriter;
+use crate::world::World;
+
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+/// Frame rate of animations that don't set their own.
+pub const DEFAULT_FPS: f64 = 24.0;
+
+/// Values that can be blended between keyframes.
+pub trait Interpolate {
+    /// Blends from `self` (t = 0.0) to `other` (t = 1.0).
+    fn lerp(&self, other: &Self, t: f64) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Tuple {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        *self + (*other - *self) * t
+    }
+}
+
+impl Interpolate for Color {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        *self + (*other - *self) * t
+    }
+}
+
+/// How a value moves from one keyframe to the next.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Easing {
+    /// Constant speed.
+    Linear,
+
+    /// Starts slowly and speeds up.
+    EaseIn,
+
+    /// Starts quickly and slows down.
+    EaseOut,
+
+    /// Starts and finishes slowly.
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps linear progress through a segment to eased progress.
+    pub fn apply(&self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A value at a point in time. The easing controls the motion from this
+/// keyframe to the next.
+#[derive(Copy, Clone, Debug)]
+pub struct Keyframe<T> {
+    pub time: f64,
+    pub value: T,
+    pub easing: Easing,
+}
+
+impl<T> Keyframe<T> {
+    pub fn new(time: f64, value: T, easing: Easing) -> Self {
+        Keyframe {
+            time,
+            value,
+            easing,
+        }
+    }
+}
+
+/// A value animated by keyframes. Before the first keyframe and after the last
+/// one the value holds still.
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T: Interpolate + Clone> Track<T> {
+    pub fn new() -> Self {
+        Track {
+            keyframes: Vec::new(),
+        }
+    }
+
+    /// Adds a keyframe, keeping the keyframes ordered by time.
+    pub fn key(mut self, time: f64, value: T, easing: Easing) -> Self {
+        let index = self.keyframes.partition_point(|k| k.time <= time);
+        self.keyframes
+            .insert(index, Keyframe::new(time, value, easing));
+        self
+    }
+
+    /// The value at a time, or `None` if the track has no keyframes.
+    pub fn sample(&self, time: f64) -> Option<T> {
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return self.keyframes.first().map(|k| k.value.clone());
+        }
+        let from = &self.keyframes[next - 1];
+        let to = match self.keyframes.get(next) {
+            Some(to) => to,
+            None => return Some(from.value.clone()),
+        };
+        let t = (time - from.time) / (to.time - from.time);
+        Some(from.value.lerp(&to.value, from.easing.apply(t)))
+    }
+}
+
+impl<T: Interpolate + Clone> Default for Track<T> {
+    fn default() -> Self {
+        Track::new()
+    }
+}
+
+/// An object's placement, decomposed so it can be interpolated.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pose {
+    pub translation: Tuple,
+
+    /// Rotations about the x, y, and z axes in radians, applied in that order.
+    pub rotation: Tuple,
+
+    pub scale: Tuple,
+}
+
+impl Pose {
+    pub fn new(translation: Tuple, rotation: Tuple, scale: Tuple) -> Self {
+        Pose {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    /// The pose that leaves an object where it is.
+    pub fn identity() -> Self {
+        Pose::new(
+            Tuple::vector(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 0.0, 0.0),
+            Tuple::vector(1.0, 1.0, 1.0),
+        )
+    }
+
+    pub fn to_matrix(&self) -> Matrix<4> {
+        let t = self.translation;
+        let r = self.rotation;
+        let s = self.scale;
+        Matrix::translation(t.x(), t.y(), t.z())
+            * Matrix::rotation_z(r.z())
+            * Matrix::rotation_y(r.y())
+            * Matrix::rotation_x(r.x())
+            * Matrix::scaling(s.x(), s.y(), s.z())
+    }
+}
+
+impl Interpolate for Pose {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Pose::new(
+            self.translation.lerp(&other.translation, t),
+            self.rotation.lerp(&other.rotation, t),
+            self.scale.lerp(&other.scale, t),
+        )
+    }
+}
+
+/// A camera's placement, as given to `Matrix::view_transform`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct View {
+    pub from: Tuple,
+    pub to: Tuple,
+    pub up: Tuple,
+}
+
+impl View {
+    pub fn new(from: Tuple, to: Tuple, up: Tuple) -> Self {
+        View { from, to, up }
+    }
+
+    pub fn to_matrix(&self) -> Matrix<4> {
+        Matrix::view_transform(self.from, self.to, self.up)
+    }
+}
+
+impl Interpolate for View {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        View::new(
+            self.from.lerp(&other.from, t),
+            self.to.lerp(&other.to, t),
+            self.up.lerp(&other.up, t),
+        )
+    }
+}
+
+/// Fluctuation in a light's brightness, as a factor from `1 - depth` to 1.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Flicker {
+    /// Regular pulsing at a frequency in hertz, like a failing fluorescent
+    /// tube.
+    Sine { frequency: f64, depth: f64 },
+
+    /// Smooth random wavering that changes about `frequency` times a second,
+    /// like a candle or fire. Lights with different seeds waver differently.
+    Noise {
+        frequency: f64,
+        depth: f64,
+        seed: u64,
+    },
+}
+
+impl Flicker {
+    /// Brightness factor at a time in seconds.
+    pub fn factor(&self, time: f64) -> f64 {
+        match *self {
+            Flicker::Sine { frequency, depth } => {
+                let wave = 0.5 + 0.5 * (2.0 * PI * frequency * time).cos();
+                1.0 - depth * (1.0 - wave)
+            }
+            Flicker::Noise {
+                frequency,
+                depth,
+                seed,
+            } => {
+                // Two octaves of value noise: slow swells with quick flutter.
+                let noise = 0.65 * value_noise(seed, frequency * time)
+                    + 0.35 * value_noise(seed.wrapping_add(1), 2.7 * frequency * time);
+                1.0 - depth * noise
+            }
+        }
+    }
+}
+
+/// Smoothly interpolated random values in [0, 1), changing once per unit.
+fn value_noise(seed: u64, x: f64) -> f64 {
+    let cell = x.floor();
+    let value = |cell: f64| -> f64 { Pcg32::for_key(seed, cell as i64).gen() };
+    let t = Easing::EaseInOut.apply(x - cell);
+    value(cell).lerp(&value(cell + 1.0), t)
+}
+
+/// A light's intensity and color over time.
+#[derive(Clone, Debug)]
+pub struct LightTrack {
+    /// Intensity before temperature and flicker.
+    pub intensity: Track<Color>,
+
+    /// Color temperature in kelvin, tinting the intensity by
+    /// `Color::blackbody`.
+    pub temperature: Option<Track<f64>>,
+
+    pub flicker: Option<Flicker>,
+}
+
+impl LightTrack {
+    /// A light holding one intensity, for temperature and flicker to vary.
+    pub fn steady(intensity: Color) -> Self {
+        LightTrack::keyed(Track::new().key(0.0, intensity, Easing::Linear))
+    }
+
+    pub fn keyed(intensity: Track<Color>) -> Self {
+        LightTrack {
+            intensity,
+            temperature: None,
+            flicker: None,
+        }
+    }
+
+    pub fn temperature(mut self, temperature: Track<f64>) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn flicker(mut self, flicker: Flicker) -> Self {
+        self.flicker = Some(flicker);
+        self
+    }
+
+    /// The light's intensity at a time, or `None` if the intensity track has
+    /// no keyframes.
+    pub fn sample(&self, time: f64) -> Option<Color> {
+        let mut intensity = self.intensity.sample(time)?;
+        if let Some(kelvin) = self.temperature.as_ref().and_then(|t| t.sample(time)) {
+            intensity = intensity * Color::blackbody(kelvin);
+        }
+        if let Some(flicker) = &self.flicker {
+            intensity = intensity * flicker.factor(time);
+        }
+        Some(intensity)
+    }
+}
+
+/// Whether an animation's frames share their random sampling.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FrameNoise {
+    /// Each frame is sampled with its own seed, derived from the camera's and
+    /// the frame number, so noise averages out over a sequence instead of
+    /// sticking to the screen.
+    Varying,
+
+    /// Every frame is sampled with the camera's seed, so noise stays put
+    /// instead of crawling from frame to frame. Steadier for previews.
+    Locked,
+}
+
+/// Object, light, and camera motion over a sequence of frames.
+pub struct Animation {
+    /// Frames per second, converting frame numbers to keyframe times.
+    pub fps: f64,
+
+    /// Number of frames in the sequence.
+    pub frames: usize,
+
+    pub noise: FrameNoise,
+
+    objects: Vec<(Obj, Track<Pose>)>,
+    lights: Vec<(usize, LightTrack)>,
+    camera: Option<Track<View>>,
+
+    /// Objects' transforms by frame number, as set by `bake_object`.
+    baked: BTreeMap<Obj, BTreeMap<usize, Matrix<4>>>,
+}
+
+impl Animation {
+    pub fn new(fps: f64, frames: usize) -> Self {
+        Animation {
+            fps,
+            frames,
+            noise: FrameNoise::Varying,
+            objects: Vec::new(),
+            lights: Vec::new(),
+            camera: None,
+            baked: BTreeMap::new(),
+        }
+    }
+
+    /// A full revolution of an object about its y axis, ending one frame
+    /// short of where it started so the sequence loops seamlessly.
+    pub fn turntable(obj: Obj, frames: usize) -> Self {
+        let fps = DEFAULT_FPS;
+        let duration = frames as f64 / fps;
+        let track = Track::new()
+            .key(0.0, Pose::identity(), Easing::Linear)
+            .key(
+                duration,
+                Pose {
+                    rotation: Tuple::vector(0.0, 2.0 * std::f64::consts::PI, 0.0),
+                    ..Pose::identity()
+                },
+                Easing::Linear,
+            );
+        let mut animation = Animation::new(fps, frames);
+        animation.animate_object(obj, track);
+        animation
+    }
+
+    /// Drives an object's transform with a track.
+    pub fn animate_object(&mut self, obj: Obj, track: Track<Pose>) {
+        self.objects.push((obj, track));
+    }
+
+    /// Drives the intensity of a light, by its index in the world.
+    pub fn animate_light(&mut self, light: usize, track: LightTrack) {
+        self.lights.push((light, track));
+    }
+
+    /// Drives the camera's transform with a track.
+    pub fn animate_camera(&mut self, track: Track<View>) {
+        self.camera = Some(track);
+    }
+
+    /// Sets an object's transform at a frame, such as one baked by a physics
+    /// simulation or another animation package. A baked object is placed
+    /// exactly as it was baked at the last frame at or before the one being
+    /// rendered, or at its first baked frame before that, rather than by its
+    /// track.
+    pub fn bake_object(&mut self, obj: Obj, frame: usize, transform: Matrix<4>) {
+        self.baked.entry(obj).or_default().insert(frame, transform);
+    }
+
+    /// Bakes object transforms read from CSV, one line per object per frame:
+    /// the frame number, from 1, the object's id, then the 16 entries of its
+    /// transform, row by row. A header line, blank lines, and lines starting
+    /// with `#` are skipped. Transforms must be invertible, as objects are
+    /// placed by their inverses.
+    pub fn read_baked<R: BufRead>(&mut self, reader: R) -> Result<(), Box<dyn Error>> {
+        let mut first = true;
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+            let header = first && fields[0].parse::<f64>().is_err();
+            first = false;
+            if header {
+                continue;
+            }
+            let line_error = |message: &str| format!("line {}: {}", number + 1, message);
+            if fields.len() != 18 {
+                return Err(line_error(&format!(
+                    "expected a frame, an object, and 16 matrix entries, found {} values",
+                    fields.len()
+                ))
+                .into());
+            }
+            let frame: usize = fields[0]
+                .parse()
+                .ok()
+                .filter(|&frame| frame > 0)
+                .ok_or_else(|| line_error(&format!("invalid frame '{}'", fields[0])))?;
+            let obj: Obj = fields[1]
+                .parse()
+                .map_err(|_| line_error(&format!("invalid object '{}'", fields[1])))?;
+            let mut rows = [[0.0; 4]; 4];
+            for (i, field) in fields[2..].iter().enumerate() {
+                rows[i / 4][i % 4] = field
+                    .parse()
+                    .map_err(|_| line_error(&format!("invalid matrix entry '{}'", field)))?;
+            }
+            let transform = Matrix::from_rows(rows);
+            if !transform.is_invertible() {
+                return Err(line_error("matrix isn't invertible").into());
+            }
+            self.bake_object(obj, frame, transform);
+        }
+        Ok(())
+    }
+
+    /// Objects with baked transforms.
+    pub fn baked_objects(&self) -> impl Iterator<Item = Obj> + '_ {
+        self.baked.keys().copied()
+    }
+
+    /// The last frame any object has a baked transform for.
+    pub fn last_baked_frame(&self) -> Option<usize> {
+        self.baked
+            .values()
+            .filter_map(|frames| frames.keys().next_back())
+            .max()
+            .copied()
+    }
+
+    /// Time of a frame in seconds. Frames are numbered from 1.
+    pub fn time(&self, frame: usize) -> f64 {
+        (frame.saturating_sub(1)) as f64 / self.fps
+    }
+
+    /// Seed for sampling a frame, given the camera's seed. The same seed and
+    /// frame always give the same frame seed.
+    pub fn frame_seed(&self, seed: u64, frame: usize) -> u64 {
+        match self.noise {
+            FrameNoise::Varying => Pcg32::for_key(seed, frame).gen(),
+            FrameNoise::Locked => seed,
+        }
+    }
+
+    /// Moves the world, its lights, and the camera to where they are at a
+    /// frame, and chooses the stand-ins traced from there.
+    pub fn apply(&self, frame: usize, world: &mut World, camera: &mut Camera) {
+        let time = self.time(frame);
+        for (obj, track) in self.objects.iter() {
+            if let Some(pose) = track.sample(time) {
+                world.obj_pool.set_transform(*obj, pose.to_matrix());
+            }
+        }
+        for (obj, frames) in self.baked.iter() {
+            let baked = frames
+                .range(..=frame)
+                .next_back()
+                .or_else(|| frames.iter().next());
+            if let Some((_, transform)) = baked {
+                world.obj_pool.set_transform(*obj, *transform);
+            }
+        }
+        for (light, track) in self.lights.iter() {
+            if let (Some(light), Some(intensity)) =
+                (world.lights.get_mut(*light), track.sample(time))
+            {
+                light.set_intensity(intensity);
+            }
+        }
+        if let Some(view) = self.camera.as_ref().and_then(|t| t.sample(time)) {
+            camera.set_transform(view.to_matrix());
+        }
+        world.scene_changed();
+        camera.select_lod(world);
+    }
+}
+
+/// Path of a frame in a sequence, numbering the output path's file name, e.g.
+/// `out.ppm` becomes `out_0001.ppm`.
+pub fn frame_path(output: &Path, frame: usize) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match output.extension() {
+        Some(extension) => format!("{}_{:04}.{}", stem, frame, extension.to_string_lossy()),
+        None => format!("{}_{:04}", stem, frame),
+    };
+    output.with_file_name(name)
+}
+
+/// Renders every frame of an animation to a numbered image sequence, post
+/// processing each frame the same way. Frames are seeded by
+/// `Animation::frame_seed` from the camera's seed.
+pub fn render_animation(
+    world: &mut World,
+    camera: &mut Camera,
+    animation: &Animation,
+    post: &PostProcess,
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    for frame in 1..=animation.frames {
+        render_frame(world, camera, animation, frame, post, output)?;
+    }
+    Ok(())
+}
+
+/// Renders one frame of an animation to its place in the numbered image
+/// sequence, exactly as `render_animation` would. Lets a render farm split a
+/// sequence's frames among machines.
+pub fn render_frame(
+    world: &mut World,
+    camera: &mut Camera,
+    animation: &Animation,
+    frame: usize,
+    post: &PostProcess,
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let image = frame_image(world, camera, animation, frame, post)?;
+    fs::write(frame_path(output, frame), image.to_ppm()?)?;
+    Ok(())
+}
+
+/// Renders every frame of an animation into a video, post processing each
+/// frame the same way and seeding it as `render_animation` would.
+pub fn encode_animation(
+    world: &mut World,
+    camera: &mut Camera,
+    animation: &Animation,
+    post: &PostProcess,
+    mut video: VideoWriter,
+) -> Result<(), Box<dyn Error>> {
+    for frame in 1..=animation.frames {
+        video.write_frame(&frame_image(world, camera, animation, frame, post)?)?;
+    }
+    video.finish()
+}
+
+/// Renders one frame of an animation, post processed.
+fn frame_image(
+    world: &mut World,
+    camera: &mut Camera,
+    animation: &Animation,
+    frame: usize,
+    post: &PostProcess,
+) -> Result<Canvas, Box<dyn Error>> {
+    if frame == 0 || frame > animation.frames {
+        return Err(format!("no frame {} in {} frames", frame, animation.frames).into());
+    }
+    let seed = camera.seed();
+    animation.apply(frame, world, camera);
+    camera.set_seed(animation.frame_seed(seed, frame));
+    world.seed = camera.seed();
+    let image = post.apply(&camera.render(world));
+    camera.set_seed(seed);
+    world.seed = seed;
+    Ok(image)
+}
+
+/// Describes the frames of an animation as JSON, for render farm schedulers:
+/// each frame's number, time, seed, and output path, along with a hash of the
+/// scene they are rendered from. A scheduler can render the frames in any
+/// order and on any machine, then check they all came from the same scene.
+/// The hash should cover the render and post-processing settings too, and be
+/// the same on every build, such as one from `rng::stable_hash`.
+pub fn manifest(animation: &Animation, seed: u64, scene: u64, output: &Path) -> String {
+    let mut json = String::new();
+    json += "{\n";
+    json += &format!("  \"scene\": \"{:016x}\",\n", scene);
+    json += &format!("  \"fps\": {},\n", animation.fps);
+    json += &format!("  \"seed\": {},\n", seed);
+    json += "  \"frames\": [";
+    for frame in 1..=animation.frames {
+        json += if frame == 1 { "\n" } else { ",\n" };
+        json += &format!(
+            "    {{\"frame\": {}, \"time\": {}, \"seed\": {}, \"output\": {}}}",
+            frame,
+            animation.time(frame),
+            animation.frame_seed(seed, frame),
+            json_string(&frame_path(output, frame).to_string_lossy())
+        );
+    }
+    json += "\n  ]\n}\n";
+    json
+}
+
+/// Quotes a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            c if (c as u32) < 0x20 => quoted += &format!("\\u{:04x}", c as u32),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY: &str = "1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1";
+
+    fn read(csv: &str) -> Result<Animation, Box<dyn Error>> {
+        let mut animation = Animation::new(DEFAULT_FPS, 0);
+        animation.read_baked(csv.as_bytes())?;
+        Ok(animation)
+    }
+
+    #[test]
+    fn reads_baked_transforms() {
+        let csv = format!(
+            "frame, object, m00\n# comment\n\n1, 2, {}\n4, 3, {}\n",
+            IDENTITY, IDENTITY
+        );
+        let animation = read(&csv).unwrap();
+        let mut objects: Vec<Obj> = animation.baked_objects().collect();
+        objects.sort();
+        assert_eq!(objects, vec![2, 3]);
+        assert_eq!(animation.last_baked_frame(), Some(4));
+    }
+
+    #[test]
+    fn rejects_singular_transforms() {
+        let flat = "1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1";
+        let csv = format!("1, 0, {}\n2, 0, {}\n", IDENTITY, flat);
+        let error = read(&csv).err().unwrap().to_string();
+        assert_eq!(error, "line 2: matrix isn't invertible");
+
+        let nan = "NaN, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1";
+        assert!(read(&format!("1, 0, {}\n", nan)).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::irradiance::Irradiance;
+use crate::matrix::Matrix;
+use crate::tuple::Tuple;
+
+use std::f64::consts::PI;
+
+/// What a ray sees when it misses every object.
+pub enum Background {
+    /// The same color in every direction.
+    Color(Color),
+
+    /// Blends from the horizon color at the horizon to the zenith color
+    /// straight up, and back to the horizon color straight down.
+    Gradient { horizon: Color, zenith: Color },
+
+    /// An image of the surroundings. See `Environment`.
+    Environment(Environment),
+}
+
+/// A 360 by 180 degree equirectangular image, laid out the way the camera's
+/// equirectangular projection renders one. Linear colors above 1.0 are kept,
+/// so environments can light reflections brighter than white.
+///
+/// The image can be turned and brightened to aim and balance its lighting
+/// without editing it.
+#[derive(Clone)]
+pub struct Environment {
+    pub image: Canvas,
+
+    /// Turns the environment about the y axis, in radians, the way
+    /// `Matrix::rotation_y` turns objects.
+    pub azimuth: f64,
+
+    /// Tilts the environment, in radians, raising the middle of the image
+    /// toward straight up. Applied before the azimuth.
+    pub elevation: f64,
+
+    /// Brightness adjustment in stops.
+    pub exposure: f64,
+}
+
+impl Environment {
+    pub fn new(image: Canvas) -> Self {
+        Environment {
+            image,
+            azimuth: 0.0,
+            elevation: 0.0,
+            exposure: 0.0,
+        }
+    }
+
+    /// Turns directions in the image into directions in the world.
+    pub fn rotation(&self) -> Matrix<4> {
+        Matrix::rotation_y(self.azimuth) * Matrix::rotation_x(self.elevation)
+    }
+
+    pub fn color_at(&self, direction: Tuple) -> Color {
+        // Rotations are orthogonal, so the transpose undoes one.
+        let direction = (self.rotation().transpose() * direction).normalize();
+        let longitude = (-direction.x()).atan2(-direction.z());
+        let latitude = direction.y().clamp(-1.0, 1.0).asin();
+        let u = longitude / (2.0 * PI) + 0.5;
+        let v = 0.5 - latitude / PI;
+        self.image.sample(u, v, true) * 2f64.powf(self.exposure)
+    }
+
+    /// Light arriving from the environment in every direction.
+    pub fn irradiance(&self) -> Irradiance {
+        let image = &self.image;
+        let rotation = self.rotation();
+        let scale = 2f64.powf(self.exposure);
+
+        // Each pixel covers a patch of the sphere that narrows toward the poles.
+        let pixel_angle = (2.0 * PI / image.width as f64) * (PI / image.height as f64);
+        let samples = (0..image.height).flat_map(|y| {
+            let latitude = PI * (0.5 - (y as f64 + 0.5) / image.height as f64);
+            (0..image.width).map(move |x| {
+                let longitude = 2.0 * PI * ((x as f64 + 0.5) / image.width as f64 - 0.5);
+                let direction = Tuple::vector(
+                    -latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                );
+                (
+                    rotation * direction,
+                    image.pixel_at(x, y) * scale,
+                    pixel_angle * latitude.cos(),
+                )
+            })
+        });
+        Irradiance::from_radiance(samples)
+    }
+}
+
+impl Background {
+    pub fn color_at(&self, direction: Tuple) -> Color {
+        let color = match self {
+            Background::Color(color) => *color,
+            Background::Gradient { horizon, zenith } => {
+                let t = direction.normalize().y().abs();
+                *horizon * (1.0 - t) + *zenith * t
+            }
+            Background::Environment(environment) => environment.color_at(direction),
+        };
+        color.checked("background")
+    }
+
+    /// Light arriving from the background in every direction, or `None` if
+    /// it isn't an environment image.
+    pub fn irradiance(&self) -> Option<Irradiance> {
+        match self {
+            Background::Environment(environment) => Some(environment.irradiance()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Color(Color::new(0.0, 0.0, 0.0))
+    }
+}
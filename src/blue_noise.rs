@@ -0,0 +1,223 @@
+use crate::rng::Pcg32;
+
+use rand::{Error, Rng, RngCore};
+use std::sync::OnceLock;
+
+/// Width and height of the mask, which tiles across the image.
+const SIZE: usize = 64;
+
+/// Spread, in pixels, of the Gaussian that measures how tightly packed the
+/// mask's points are while it's made.
+const SIGMA: f64 = 1.9;
+
+/// How far the mask is moved for each dimension of a sample, as fractions of
+/// its size. Successive multiples of these, wrapping around, cover the square
+/// as evenly as any sequence can (Roberts' R2 sequence).
+const DIMENSION_STEP: (f64, f64) = (0.754_877_666_246_692_7, 0.569_840_290_998_053_2);
+
+/// A blue noise mask: a value from 0.0 to 1.0 for every pixel, ranked so that
+/// pixels with similar values are spread out over the image with no clumps or
+/// gaps. Random choices shifted by it per pixel leave grain that's fine and
+/// even, instead of white noise's blotches.
+pub struct BlueNoise {
+    values: Vec<f64>,
+}
+
+impl BlueNoise {
+    /// The mask, made the first time it's needed and the same every time.
+    pub fn get() -> &'static BlueNoise {
+        static MASK: OnceLock<BlueNoise> = OnceLock::new();
+        MASK.get_or_init(void_and_cluster)
+    }
+
+    /// The mask's value at a pixel.
+    pub fn value(&self, x: usize, y: usize) -> f64 {
+        self.values[(y % SIZE) * SIZE + x % SIZE]
+    }
+
+    /// A random number generator for a pixel, drawing the numbers of `rng`
+    /// each shifted by the pixel's mask value. Give every pixel the same
+    /// stream, such as one keyed by the sample number alone: each pixel then
+    /// sees it offset differently, in a blue noise pattern.
+    ///
+    /// Each number drawn is shifted by the mask moved a little further over,
+    /// so that every dimension of a sample gets its own blue noise pattern.
+    pub fn decorrelate<R: RngCore>(&self, rng: R, x: usize, y: usize) -> Decorrelated<'_, R> {
+        Decorrelated {
+            mask: self,
+            rng,
+            x,
+            y,
+            dimension: 0,
+        }
+    }
+}
+
+/// See `BlueNoise::decorrelate`.
+pub struct Decorrelated<'a, R> {
+    mask: &'a BlueNoise,
+    rng: R,
+    x: usize,
+    y: usize,
+    dimension: usize,
+}
+
+impl<'a, R: RngCore> Decorrelated<'a, R> {
+    /// Shift of the next number drawn, in 64-bit fixed point.
+    fn next_shift(&mut self) -> u64 {
+        let d = self.dimension as f64;
+        let dx = ((d * DIMENSION_STEP.0).fract() * SIZE as f64) as usize;
+        let dy = ((d * DIMENSION_STEP.1).fract() * SIZE as f64) as usize;
+        self.dimension += 1;
+        let value = self.mask.value(self.x + dx, self.y + dy);
+        (value * 2.0_f64.powi(64)) as u64
+    }
+}
+
+impl<'a, R: RngCore> RngCore for Decorrelated<'a, R> {
+    fn next_u32(&mut self) -> u32 {
+        let shift = (self.next_shift() >> 32) as u32;
+        self.rng.next_u32().wrapping_add(shift)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let shift = self.next_shift();
+        self.rng.next_u64().wrapping_add(shift)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Ulichney's void and cluster method, which ranks pixels by adding them one
+/// at a time where the pattern so far is sparsest.
+fn void_and_cluster() -> BlueNoise {
+    let count = SIZE * SIZE;
+    let mut pattern = Pattern::new();
+
+    // Start from a tenth of the pixels at random, then even them out by moving
+    // the most crowded to the emptiest spot until that puts it back.
+    let mut rng = Pcg32::new(0, 0);
+    let initial = count / 10;
+    while pattern.ones.len() < initial {
+        let pixel = rng.gen_range(0..count);
+        if !pattern.set[pixel] {
+            pattern.add(pixel);
+        }
+    }
+    loop {
+        let cluster = pattern.tightest_cluster();
+        pattern.remove(cluster);
+        let void = pattern.largest_void();
+        pattern.add(void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    // Rank the starting pixels by taking them away most crowded first, then
+    // the rest by filling in the emptiest spots.
+    let mut rank = vec![0; count];
+    let mut taken = pattern.clone();
+    for r in (0..initial).rev() {
+        let cluster = taken.tightest_cluster();
+        taken.remove(cluster);
+        rank[cluster] = r;
+    }
+    for r in initial..count {
+        let void = pattern.largest_void();
+        pattern.add(void);
+        rank[void] = r;
+    }
+
+    let values = rank
+        .iter()
+        .map(|&r| (r as f64 + 0.5) / count as f64)
+        .collect();
+    BlueNoise { values }
+}
+
+/// Pixels chosen so far, and how crowded each pixel is by them.
+#[derive(Clone)]
+struct Pattern {
+    set: Vec<bool>,
+    ones: Vec<usize>,
+    energy: Vec<f64>,
+    kernel: Vec<f64>,
+}
+
+impl Pattern {
+    fn new() -> Self {
+        let mut kernel = vec![0.0; SIZE * SIZE];
+        for dy in 0..SIZE {
+            for dx in 0..SIZE {
+                // Distances wrap around, so the mask tiles seamlessly.
+                let x = dx.min(SIZE - dx) as f64;
+                let y = dy.min(SIZE - dy) as f64;
+                kernel[dy * SIZE + dx] = (-(x * x + y * y) / (2.0 * SIGMA * SIGMA)).exp();
+            }
+        }
+        Pattern {
+            set: vec![false; SIZE * SIZE],
+            ones: Vec::new(),
+            energy: vec![0.0; SIZE * SIZE],
+            kernel,
+        }
+    }
+
+    fn add(&mut self, pixel: usize) {
+        self.set[pixel] = true;
+        self.ones.push(pixel);
+        self.spread(pixel, 1.0);
+    }
+
+    fn remove(&mut self, pixel: usize) {
+        self.set[pixel] = false;
+        self.ones.retain(|&p| p != pixel);
+        self.spread(pixel, -1.0);
+    }
+
+    fn spread(&mut self, pixel: usize, sign: f64) {
+        let (px, py) = (pixel % SIZE, pixel / SIZE);
+        for y in 0..SIZE {
+            let dy = (y + SIZE - py) % SIZE;
+            for x in 0..SIZE {
+                let dx = (x + SIZE - px) % SIZE;
+                self.energy[y * SIZE + x] += sign * self.kernel[dy * SIZE + dx];
+            }
+        }
+    }
+
+    /// The chosen pixel most crowded by the others.
+    fn tightest_cluster(&self) -> usize {
+        let mut ones = self.ones.iter().copied();
+        let first = ones.next().unwrap();
+        ones.fold(first, |best, p| {
+            if self.energy[p] > self.energy[best] {
+                p
+            } else {
+                best
+            }
+        })
+    }
+
+    /// The unchosen pixel furthest from the chosen ones.
+    fn largest_void(&self) -> usize {
+        (0..SIZE * SIZE)
+            .filter(|&p| !self.set[p])
+            .fold(None, |best: Option<usize>, p| match best {
+                Some(b) if self.energy[b] <= self.energy[p] => Some(b),
+                _ => Some(p),
+            })
+            .unwrap()
+    }
+}
@@ -0,0 +1,325 @@
+use crate::matrix::Matrix;
+use crate::ray::Ray;
+use crate::tuple::Tuple;
+
+/// An axis aligned bounding box, given by its lowest and highest corners.
+///
+/// Boxes can be infinite along the axes something goes on forever, such as a
+/// plane's. The empty box, with its lowest corner above its highest, contains
+/// nothing, and merging it with another box leaves that box as it was.
+#[derive(Copy, Clone, Debug)]
+pub struct Bounds {
+    pub min: Tuple,
+    pub max: Tuple,
+}
+
+impl Bounds {
+    pub fn new(min: Tuple, max: Tuple) -> Self {
+        Bounds { min, max }
+    }
+
+    /// The box containing nothing.
+    pub fn empty() -> Self {
+        let inf = f64::INFINITY;
+        Bounds::new(Tuple::point(inf, inf, inf), Tuple::point(-inf, -inf, -inf))
+    }
+
+    /// The smallest box containing every one of some points, or the empty
+    /// box if there are none.
+    pub fn from_points(points: impl IntoIterator<Item = Tuple>) -> Self {
+        points
+            .into_iter()
+            .fold(Bounds::empty(), |bounds, point| bounds.include(point))
+    }
+
+    /// Whether the box contains nothing.
+    pub fn is_empty(&self) -> bool {
+        self.min.x() > self.max.x() || self.min.y() > self.max.y() || self.min.z() > self.max.z()
+    }
+
+    /// Whether the box is neither empty nor goes on forever along any axis.
+    pub fn is_finite(&self) -> bool {
+        !self.is_empty()
+            && self
+                .min
+                .iter()
+                .chain(self.max.iter())
+                .all(|v| v.is_finite())
+    }
+
+    /// The smallest box containing both boxes.
+    pub fn merge(self, other: Bounds) -> Self {
+        Bounds::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    /// The smallest box containing this box and a point.
+    pub fn include(self, point: Tuple) -> Self {
+        Bounds::new(self.min.min(point), self.max.max(point))
+    }
+
+    /// Whether a point is inside the box or on its surface.
+    pub fn contains(&self, point: Tuple) -> bool {
+        self.min.x() <= point.x()
+            && point.x() <= self.max.x()
+            && self.min.y() <= point.y()
+            && point.y() <= self.max.y()
+            && self.min.z() <= point.z()
+            && point.z() <= self.max.z()
+    }
+
+    /// How far the box reaches along each axis.
+    pub fn size(&self) -> Tuple {
+        self.max - self.min
+    }
+
+    pub fn center(&self) -> Tuple {
+        self.min + self.size() * 0.5
+    }
+
+    /// The box's eight corners. Bits 0, 1, and 2 of a corner's index pick
+    /// whether its x, y, and z come from the highest corner.
+    pub fn corners(&self) -> [Tuple; 8] {
+        let mut corners = [self.min; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let x = if i & 1 == 0 {
+                self.min.x()
+            } else {
+                self.max.x()
+            };
+            let y = if i & 2 == 0 {
+                self.min.y()
+            } else {
+                self.max.y()
+            };
+            let z = if i & 4 == 0 {
+                self.min.z()
+            } else {
+                self.max.z()
+            };
+            *corner = Tuple::point(x, y, z);
+        }
+        corners
+    }
+
+    /// Total area of the box's six faces. Zero for the empty box, and
+    /// infinite for boxes going on forever.
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        if !self.is_finite() {
+            // Even if flat, like a plane's, which would otherwise multiply
+            // infinity by zero.
+            return f64::INFINITY;
+        }
+        let size = self.size();
+        2.0 * (size.x() * size.y() + size.y() * size.z() + size.z() * size.x())
+    }
+
+    /// The smallest axis aligned box containing this box after transforming
+    /// it, or `None` if the box goes on forever, since which way it would go
+    /// on after the transform can't be known from its corners. The empty box
+    /// stays empty.
+    pub fn transform(&self, transform: Matrix<4>) -> Option<Self> {
+        if self.is_empty() {
+            return Some(*self);
+        }
+        if !self.is_finite() {
+            return None;
+        }
+        let corners = self.corners();
+        Some(Bounds::from_points(corners.iter().map(|c| transform * *c)))
+    }
+
+    /// Distances along a ray to where it enters and leaves the box, from the
+    /// slabs between each pair of opposite faces, or `None` if it misses. The
+    /// distances can be negative, where the box is behind the ray's origin.
+    pub fn intersect(&self, ray: &Ray) -> Option<(f64, f64)> {
+        if self.is_empty() {
+            return None;
+        }
+        let origin = [ray.origin.x(), ray.origin.y(), ray.origin.z()];
+        let direction = [ray.direction.x(), ray.direction.y(), ray.direction.z()];
+        let min = [self.min.x(), self.min.y(), self.min.z()];
+        let max = [self.max.x(), self.max.y(), self.max.z()];
+
+        let mut t_min = f64::NEG_INFINITY;
+        let mut t_max = f64::INFINITY;
+        for axis in 0..3 {
+            if direction[axis] == 0.0 {
+                // Parallel to the slab, so either always in it or never.
+                if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (min[axis] - origin[axis]) / direction[axis];
+            let t1 = (max[axis] - origin[axis]) / direction[axis];
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+        if t_min <= t_max {
+            Some((t_min, t_max))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::close_eq;
+
+    use std::f64::consts::PI;
+
+    fn unit_cube() -> Bounds {
+        Bounds::new(Tuple::point(-1.0, -1.0, -1.0), Tuple::point(1.0, 1.0, 1.0))
+    }
+
+    fn plane() -> Bounds {
+        let inf = f64::INFINITY;
+        Bounds::new(Tuple::point(-inf, 0.0, -inf), Tuple::point(inf, 0.0, inf))
+    }
+
+    fn assert_bounds(bounds: Bounds, min: Tuple, max: Tuple) {
+        assert_eq!(bounds.min, min, "{:?}", bounds);
+        assert_eq!(bounds.max, max, "{:?}", bounds);
+    }
+
+    #[test]
+    fn merging_with_the_empty_box_changes_nothing() {
+        assert!(Bounds::empty().is_empty());
+        assert!(!Bounds::empty().is_finite());
+        assert!(Bounds::empty().merge(Bounds::empty()).is_empty());
+
+        let cube = unit_cube();
+        for merged in [cube.merge(Bounds::empty()), Bounds::empty().merge(cube)] {
+            assert_bounds(merged, cube.min, cube.max);
+        }
+    }
+
+    #[test]
+    fn merging_boxes_contains_both() {
+        let a = Bounds::new(Tuple::point(-1.0, 0.0, 2.0), Tuple::point(1.0, 1.0, 3.0));
+        let b = Bounds::new(Tuple::point(0.0, -2.0, -1.0), Tuple::point(4.0, 0.5, 0.0));
+        assert_bounds(
+            a.merge(b),
+            Tuple::point(-1.0, -2.0, -1.0),
+            Tuple::point(4.0, 1.0, 3.0),
+        );
+    }
+
+    #[test]
+    fn includes_points() {
+        assert!(Bounds::from_points(Vec::new()).is_empty());
+
+        let one = Bounds::empty().include(Tuple::point(1.0, 2.0, 3.0));
+        assert!(!one.is_empty());
+        assert_bounds(
+            one,
+            Tuple::point(1.0, 2.0, 3.0),
+            Tuple::point(1.0, 2.0, 3.0),
+        );
+
+        let points = [
+            Tuple::point(-5.0, 2.0, 0.0),
+            Tuple::point(7.0, 0.0, -3.0),
+            Tuple::point(2.0, -1.0, 8.0),
+        ];
+        let bounds = Bounds::from_points(points);
+        assert_bounds(
+            bounds,
+            Tuple::point(-5.0, -1.0, -3.0),
+            Tuple::point(7.0, 2.0, 8.0),
+        );
+        for point in points {
+            assert!(bounds.contains(point));
+        }
+        assert!(!bounds.contains(Tuple::point(8.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn transforms_a_rotated_cube() {
+        let bounds = unit_cube()
+            .transform(Matrix::translation(1.0, 0.0, 0.0) * Matrix::rotation_y(PI / 4.0))
+            .unwrap();
+        let r = 2.0_f64.sqrt();
+        assert_bounds(
+            bounds,
+            Tuple::point(1.0 - r, -1.0, -r),
+            Tuple::point(1.0 + r, 1.0, r),
+        );
+    }
+
+    #[test]
+    fn transforms_the_empty_box_to_itself() {
+        let bounds = Bounds::empty().transform(Matrix::rotation_x(1.0));
+        assert!(bounds.unwrap().is_empty());
+    }
+
+    #[test]
+    fn cannot_transform_infinite_boxes() {
+        assert!(plane().transform(Matrix::identity()).is_none());
+        assert!(plane().transform(Matrix::rotation_z(PI / 4.0)).is_none());
+    }
+
+    #[test]
+    fn intersects_rays() {
+        let cube = unit_cube();
+        let ray = Ray::new(Tuple::point(-5.0, 0.5, 0.0), Tuple::vector(1.0, 0.0, 0.0));
+        assert_eq!(cube.intersect(&ray), Some((4.0, 6.0)));
+
+        let diagonal = Ray::new(Tuple::point(-3.0, -3.0, -3.0), Tuple::vector(1.0, 1.0, 1.0));
+        assert_eq!(cube.intersect(&diagonal), Some((2.0, 4.0)));
+
+        let miss = Ray::new(Tuple::point(-5.0, 0.0, 0.0), Tuple::vector(1.0, 1.0, 0.0));
+        assert_eq!(cube.intersect(&miss), None);
+
+        assert_eq!(Bounds::empty().intersect(&ray), None);
+    }
+
+    #[test]
+    fn intersects_rays_parallel_to_slabs() {
+        let cube = unit_cube();
+        // Parallel to the y and z slabs, inside them.
+        let inside = Ray::new(Tuple::point(-5.0, 0.9, -0.9), Tuple::vector(1.0, 0.0, 0.0));
+        assert_eq!(cube.intersect(&inside), Some((4.0, 6.0)));
+
+        // Parallel to the y slab, outside it.
+        let outside = Ray::new(Tuple::point(-5.0, 1.5, 0.0), Tuple::vector(1.0, 0.0, 0.0));
+        assert_eq!(cube.intersect(&outside), None);
+
+        // Along the plane's only finite axis.
+        let down = Ray::new(Tuple::point(3.0, 2.0, -7.0), Tuple::vector(0.0, -1.0, 0.0));
+        assert_eq!(plane().intersect(&down), Some((2.0, 2.0)));
+        let along = Ray::new(Tuple::point(3.0, 2.0, -7.0), Tuple::vector(1.0, 0.0, 0.0));
+        assert_eq!(plane().intersect(&along), None);
+    }
+
+    #[test]
+    fn intersects_rays_starting_inside() {
+        let ray = Ray::new(Tuple::point(0.0, 0.0, 0.5), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(unit_cube().intersect(&ray), Some((-1.5, 0.5)));
+    }
+
+    #[test]
+    fn measures_surface_area() {
+        assert_eq!(Bounds::empty().surface_area(), 0.0);
+        assert_eq!(unit_cube().surface_area(), 24.0);
+        let slab = Bounds::new(Tuple::point(0.0, 0.0, 0.0), Tuple::point(2.0, 3.0, 0.0));
+        assert_eq!(slab.surface_area(), 12.0);
+        assert_eq!(plane().surface_area(), f64::INFINITY);
+    }
+
+    #[test]
+    fn numbers_corners_by_axis_bits() {
+        let bounds = Bounds::new(Tuple::point(1.0, 2.0, 3.0), Tuple::point(4.0, 5.0, 6.0));
+        for (i, corner) in bounds.corners().iter().enumerate() {
+            let pick = |bit: usize, min: f64, max: f64| if i & bit == 0 { min } else { max };
+            let expected = Tuple::point(pick(1, 1.0, 4.0), pick(2, 2.0, 5.0), pick(4, 3.0, 6.0));
+            assert_eq!(*corner, expected, "corner {}", i);
+            assert!(close_eq(corner.w(), 1.0));
+        }
+    }
+}
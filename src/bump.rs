@@ -0,0 +1,162 @@
+use crate::matrix::*;
+use crate::tuple::*;
+
+use std::f64::consts::PI;
+
+/// Step used to estimate a height field's slope.
+const GRADIENT_STEP: f64 = 1e-4;
+
+/// A height field over pattern space. Surfaces bumped by it shade as if raised
+/// by the height at each point, without changing their geometry.
+pub trait HeightMap: Send + Sync {
+    fn height_at(&self, point: Tuple) -> f64;
+}
+
+pub struct Bump {
+    map: Box<dyn HeightMap>,
+    transform_inverse: Matrix<4>,
+    depth: f64,
+}
+
+impl Bump {
+    /// Tilts an object space normal by the slope of the height field at an
+    /// object space point.
+    pub fn perturb(&self, object_point: Tuple, object_normal: Tuple) -> Tuple {
+        let mut normal = object_normal;
+        normal.set_w(0.0);
+        let normal = normal.normalize();
+
+        let point = self.transform_inverse * object_point;
+        let slope = |axis: Tuple| {
+            let ahead = self.map.height_at(point + axis * GRADIENT_STEP);
+            let behind = self.map.height_at(point - axis * GRADIENT_STEP);
+            self.depth * (ahead - behind) / (2.0 * GRADIENT_STEP)
+        };
+        let gradient = Tuple::vector(
+            slope(Tuple::vector(1.0, 0.0, 0.0)),
+            slope(Tuple::vector(0.0, 1.0, 0.0)),
+            slope(Tuple::vector(0.0, 0.0, 1.0)),
+        );
+
+        // Gradients transform like normals from pattern to object space.
+        let mut gradient = self.transform_inverse.transpose() * gradient;
+        gradient.set_w(0.0);
+
+        // Only the slope along the surface tilts the normal.
+        let tangential = gradient - normal * gradient.dot(normal);
+        (normal - tangential).normalize()
+    }
+}
+
+pub struct BumpBuilder {
+    map: Option<Box<dyn HeightMap>>,
+    transform_inverse: Matrix<4>,
+    depth: f64,
+}
+
+impl BumpBuilder {
+    pub fn new() -> Self {
+        BumpBuilder {
+            map: None,
+            transform_inverse: Matrix::identity(),
+            depth: 1.0,
+        }
+    }
+
+    pub fn build(self) -> Option<Bump> {
+        let map = self.map?;
+        Some(Bump {
+            map,
+            transform_inverse: self.transform_inverse,
+            depth: self.depth,
+        })
+    }
+
+    pub fn transform(mut self, transform: Matrix<4>) -> Self {
+        self.transform_inverse = transform.inverse();
+        self
+    }
+
+    /// Scales the heights. Negative depths turn bumps into dents.
+    pub fn depth(mut self, depth: f64) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn map(mut self, map: impl HeightMap + 'static) -> Self {
+        self.map = Some(Box::new(map));
+        self
+    }
+
+    /// Concentric ripples spreading out from the y axis, like drops on water.
+    pub fn ripples(self, wavelength: f64) -> Self {
+        self.map(Ripples { wavelength })
+    }
+
+    /// Fine parallel grooves running along the x axis, like brushed metal.
+    pub fn brushed(self, spacing: f64) -> Self {
+        self.map(Brushed { spacing })
+    }
+
+    /// Rows of bricks in the xz plane, with each row offset by half a brick
+    /// and sunken mortar lines between them.
+    pub fn bricks(self, width: f64, height: f64, mortar: f64) -> Self {
+        self.map(Bricks {
+            width,
+            height,
+            mortar,
+        })
+    }
+}
+
+impl Default for BumpBuilder {
+    fn default() -> Self {
+        BumpBuilder::new()
+    }
+}
+
+struct Ripples {
+    wavelength: f64,
+}
+
+impl HeightMap for Ripples {
+    fn height_at(&self, point: Tuple) -> f64 {
+        let r = (point.x() * point.x() + point.z() * point.z()).sqrt();
+        (2.0 * PI * r / self.wavelength).sin() * self.wavelength / (2.0 * PI)
+    }
+}
+
+struct Brushed {
+    spacing: f64,
+}
+
+impl HeightMap for Brushed {
+    fn height_at(&self, point: Tuple) -> f64 {
+        // Waves of unrelated frequencies add up to irregular grooves.
+        let z = 2.0 * PI * point.z() / self.spacing;
+        let grooves = 0.5 * z.sin() + 0.3 * (2.3 * z + 1.7).sin() + 0.2 * (5.1 * z + 0.4).sin();
+        grooves * self.spacing / (2.0 * PI)
+    }
+}
+
+struct Bricks {
+    width: f64,
+    height: f64,
+    mortar: f64,
+}
+
+impl HeightMap for Bricks {
+    fn height_at(&self, point: Tuple) -> f64 {
+        let row = (point.z() / self.height).floor();
+        let x = point.x() / self.width + row * 0.5;
+
+        // Distance to the nearest mortar line, along each axis.
+        let dx = (x - x.round()).abs() * self.width;
+        let dz = (point.z() / self.height - (point.z() / self.height).round()).abs() * self.height;
+        let edge = dx.min(dz);
+
+        // Flat mortar, then a bevel up to the brick's face.
+        let bevel = self.mortar / 2.0;
+        ((edge - bevel) / bevel).clamp(0.0, 1.0) * bevel
+    }
+}
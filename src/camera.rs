@@ -1,45 +1,405 @@
+use crate::blue_noise::BlueNoise;
+use crate::bounds::Bounds;
+use crate::cancel::CancelToken;
 use crate::canvas::*;
+use crate::checkpoint::Checkpoint;
 use crate::color::*;
+use crate::film::*;
+use crate::filter::*;
+use crate::invariants;
+use crate::light::{Light, LightKind};
 use crate::matrix::*;
+use crate::object::{Intersection, Obj};
 use crate::ray::*;
+use crate::rng::{stable_hash, Pcg32};
+use crate::sampler::Sampler;
+use crate::settings::RenderSettings;
 use crate::tuple::*;
 use crate::world::*;
-pub struct Camera {
 
+use rand::Rng;
+
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Smallest and largest tiles picked automatically, in pixels on a side.
+/// Smaller tiles cost more in scheduling and in filter overlap between
+/// neighbors, and larger ones leave threads idle at the end of a render.
+const MIN_TILE_SIZE: usize = 16;
+const MAX_TILE_SIZE: usize = 64;
+
+/// Tiles per render thread aimed for when picking a tile size, so that
+/// threads finishing unevenly still have work to take.
+const TILES_PER_THREAD: usize = 16;
+
+/// How camera rays are cast through the image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    /// A pinhole camera with the camera's field of view.
+    Perspective,
+
+    /// Parallel rays through a view plane of the given size in world units.
+    Orthographic { width: f64, height: f64 },
+
+    /// Equidistant fisheye lens covering `angle` radians across the image's
+    /// shorter side. Pixels outside the lens circle are black.
+    Fisheye { angle: f64 },
+
+    /// Full 360 by 180 degree panorama, as used for environment maps.
+    Equirectangular,
+}
+
+/// How the color seen along each camera ray is computed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RenderMode {
+    /// Whitted style ray tracing: direct lighting with sharp reflections and
+    /// refractions, and a flat ambient term for everything else.
+    Whitted,
+
+    /// Monte Carlo path tracing with diffuse interreflection and emissive
+    /// surfaces. Needs many samples per pixel to converge.
+    PathTraced,
+}
+
+impl FromStr for RenderMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "whitted" => Ok(RenderMode::Whitted),
+            "path" => Ok(RenderMode::PathTraced),
+            _ => Err(format!(
+                "unknown render mode '{}', expected whitted or path",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RenderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RenderMode::Whitted => "whitted",
+            RenderMode::PathTraced => "path",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Order the tiles of an image are handed to render threads in. The order
+/// doesn't change the finished image, only how it fills in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TileOrder {
+    /// Row by row from the top, so a render stopped early is complete down
+    /// to where it stopped.
+    Scanline,
+
+    /// Along a Z-order curve, which finishes each quarter of the image, and
+    /// each quarter of those, before moving on to the next. Tiles rendered at
+    /// about the same time are near each other, so they share more of the
+    /// scene's cached data, and a render in progress fills in evenly across
+    /// the image rather than from the top down.
+    Morton,
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scanline" => Ok(TileOrder::Scanline),
+            "morton" => Ok(TileOrder::Morton),
+            _ => Err(format!(
+                "unknown tile order '{}', expected scanline or morton",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TileOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TileOrder::Scanline => "scanline",
+            TileOrder::Morton => "morton",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Limits after which a render stops early, keeping what it rendered so far.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Budget {
+    /// Wall-clock time allowed for the render.
+    pub time: Option<Duration>,
+
+    /// Number of rays allowed to be cast from the camera. Each pixel casts one
+    /// per sample, so this bounds the work independently of the machine.
+    pub camera_rays: Option<u64>,
+}
+
+/// One sample of a pixel, as shown to `Camera::set_on_sample`.
+#[derive(Copy, Clone, Debug)]
+pub struct SampleRecord {
+    pub x: usize,
+    pub y: usize,
+
+    /// Which of the pixel's samples this is.
+    pub sample: usize,
+
+    /// Surfaces the sample's path hit when path tracing. Whitted style rays
+    /// branch instead of following one path, so have no length, and neither
+    /// do samples the projection doesn't cover.
+    pub path_length: Option<usize>,
+
+    /// Light carried back along the sample's ray, before filtering.
+    pub radiance: Color,
+}
+
+/// A path traced sample's light path, as shown to `Camera::set_on_path`.
+#[derive(Clone, Debug)]
+pub struct PathRecord {
+    pub x: usize,
+    pub y: usize,
+    pub sample: usize,
+
+    /// Where the path went, from the camera on.
+    pub vertices: Vec<PathVertex>,
+
+    /// Light carried back along the path, before filtering.
+    pub radiance: Color,
+}
+
+/// An image from a render that may have stopped early.
+pub struct Rendering {
+    pub image: Canvas,
+
+    /// Whether the render stopped before every pixel was rendered. Pixels
+    /// that were never reached are black.
+    pub partial: bool,
+}
+
+pub struct Camera {
     hsize: usize,
     vsize: usize,
     field_of_view: f64,
+    projection: Projection,
     transform: Matrix<4>,
     transform_inverse: Matrix<4>,
     half_width: f64,
     half_height: f64,
     pixel_size: f64,
+    settings: RenderSettings,
+    on_tile: Option<Box<TileCallback>>,
+    on_sample: Option<Box<SampleCallback>>,
+
+    /// Fraction of path traced samples whose paths are shown, and the
+    /// callback showing them.
+    on_path: Option<(f64, Box<PathCallback>)>,
 }
 
+/// Called with each tile as it's rendered, along with where the tile's top left
+/// pixel is in the image.
+pub type TileCallback = dyn Fn(usize, usize, Canvas) + Send + Sync;
+
+/// Called with every sample as it's traced.
+pub type SampleCallback = dyn Fn(&SampleRecord) + Send + Sync;
+
+/// Called with the light paths of chosen samples as they're traced.
+pub type PathCallback = dyn Fn(&PathRecord) + Send + Sync;
+
 impl Camera {
     pub fn new(hsize: usize, vsize: usize, field_of_view: f64) -> Self {
         let transform = Matrix::identity();
         let transform_inverse = transform;
 
-        let half_view = (field_of_view / 2.0).tan();
-        let aspect_ratio = (hsize as f64) / (vsize as f64);
+        let mut camera = Camera {
+            hsize,
+            vsize,
+            field_of_view,
+            projection: Projection::Perspective,
+            transform,
+            transform_inverse,
+            half_width: 0.0,
+            half_height: 0.0,
+            pixel_size: 0.0,
+            settings: RenderSettings::new(),
+            on_tile: None,
+            on_sample: None,
+            on_path: None,
+        };
+        camera.update_view();
+        camera
+    }
+
+    /// Recomputes the view plane from the image size and field of view.
+    fn update_view(&mut self) {
+        let half_view = (self.field_of_view / 2.0).tan();
+        let aspect_ratio = (self.hsize as f64) / (self.vsize as f64);
         let (half_width, half_height) = if aspect_ratio >= 1.0 {
             (half_view, half_view / aspect_ratio)
         } else {
             (half_view * aspect_ratio, half_view)
         };
-        let pixel_size = (2.0 * half_width) / (hsize as f64);
+        self.half_width = half_width;
+        self.half_height = half_height;
+        self.pixel_size = (2.0 * half_width) / (self.hsize as f64);
+    }
 
-        Camera {
-            hsize,
-            vsize,
-            field_of_view,
-            transform,
-            transform_inverse,
-            half_width,
-            half_height,
-            pixel_size,
-        }
+    pub fn hsize(&self) -> usize {
+        self.hsize
+    }
+
+    pub fn vsize(&self) -> usize {
+        self.vsize
+    }
+
+    pub fn set_size(&mut self, hsize: usize, vsize: usize) {
+        self.hsize = hsize;
+        self.vsize = vsize;
+        self.update_view();
+    }
+
+    pub fn set_field_of_view(&mut self, field_of_view: f64) {
+        self.field_of_view = field_of_view;
+        self.update_view();
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    /// Replaces all the render settings at once. The setters below each change
+    /// one of them.
+    pub fn set_settings(&mut self, settings: RenderSettings) {
+        self.settings = settings;
+        self.set_samples(settings.samples);
+    }
+
+    /// Sets the number of rays traced per pixel.
+    pub fn set_samples(&mut self, samples: usize) {
+        self.settings.samples = samples.max(1);
+    }
+
+    /// Sets the filter used to weight samples onto the pixels around them.
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.settings.filter = filter;
+    }
+
+    /// Sets where in each pixel its samples are taken.
+    pub fn set_sampler(&mut self, sampler: Sampler) {
+        self.settings.sampler = sampler;
+    }
+
+    /// Shifts each pixel's random numbers by a blue noise mask when path
+    /// tracing, so that noise at low sample counts shows as fine, even grain
+    /// instead of blotches. Every pixel then draws from the same random
+    /// streams, which the mask spreads apart.
+    pub fn set_blue_noise(&mut self, blue_noise: bool) {
+        self.settings.blue_noise = blue_noise;
+    }
+
+    /// Skips objects out of reach of each tile's camera rays when finding
+    /// what the rays hit first, by testing the objects' bounds against the
+    /// pyramid the tile's rays fan out in. Each ray then tests only objects
+    /// near its tile, which speeds up renders of scenes of many objects
+    /// without changing the image. Only perspective cameras cull, and
+    /// reflected, refracted, and shadow rays always test every object.
+    /// Compiled scenes don't cull, as their own bounds do the same job.
+    pub fn set_frustum_culling(&mut self, frustum_culling: bool) {
+        self.settings.frustum_culling = frustum_culling;
+    }
+
+    /// Sets how many times a ray may be reflected or refracted.
+    pub fn set_max_depth(&mut self, max_depth: u8) {
+        self.settings.max_depth = max_depth;
+    }
+
+    /// Stops following reflections and refractions that carry less than this
+    /// fraction of their light to the camera, before reaching the maximum
+    /// depth. Zero follows them to the maximum depth.
+    pub fn set_min_throughput(&mut self, min_throughput: f64) {
+        self.settings.min_throughput = min_throughput;
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.settings.mode = mode;
+    }
+
+    /// Sets the seed for the render's random sampling. Renders with the same
+    /// seed and settings come out identical.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.settings.seed = seed;
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.settings.seed
+    }
+
+    /// Sets the number of render threads. Zero uses every available core.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.settings.threads = threads;
+    }
+
+    /// Runs render threads at the lowest scheduling priority, so the machine
+    /// stays responsive while rendering on every core. Only has an effect on
+    /// Unix systems, where on Linux only the render threads are lowered and
+    /// elsewhere the whole process is, for good.
+    pub fn set_low_priority(&mut self, low_priority: bool) {
+        self.settings.low_priority = low_priority;
+    }
+
+    /// Sets the width and height, in pixels, of the tiles the image is
+    /// rendered in. Zero picks a size from the image size and thread count.
+    ///
+    /// Pixels shared by neighboring tiles sum their samples in a different
+    /// order with different tiles, which can round them differently. Set the
+    /// size to render identically on machines with different core counts.
+    pub fn set_tile_size(&mut self, tile_size: usize) {
+        self.settings.tile_size = tile_size;
+    }
+
+    /// Sets the order tiles are rendered in.
+    pub fn set_tile_order(&mut self, tile_order: TileOrder) {
+        self.settings.tile_order = tile_order;
+    }
+
+    /// Shows each tile to a callback as soon as it's rendered, such as to
+    /// display a render in progress. Tiles are passed with the neighboring
+    /// pixels their samples reach, resolved from those samples alone, so
+    /// pixels along tile edges settle once the next tile over is done.
+    pub fn set_on_tile(&mut self, on_tile: impl Fn(usize, usize, Canvas) + Send + Sync + 'static) {
+        self.on_tile = Some(Box::new(on_tile));
+    }
+
+    /// Shows every sample to a callback as it's traced, for studying how a
+    /// render converges, such as the variance of pixels or the lengths of
+    /// paths. Samples come from every render thread at once, in no particular
+    /// order, and slow the render by however long the callback takes.
+    pub fn set_on_sample(&mut self, on_sample: impl Fn(&SampleRecord) + Send + Sync + 'static) {
+        self.on_sample = Some(Box::new(on_sample));
+    }
+
+    /// Shows the light paths of a fraction of path traced samples to a
+    /// callback, for seeing how light gets through a scene. Which samples are
+    /// chosen depends only on the seed and the sample, and recording a path
+    /// doesn't change it, so the image is the same as without the callback.
+    pub fn set_on_path(
+        &mut self,
+        fraction: f64,
+        on_path: impl Fn(&PathRecord) + Send + Sync + 'static,
+    ) {
+        self.on_path = Some((fraction, Box::new(on_path)));
     }
 
     pub fn set_transform(&mut self, transform: Matrix<4>) {
@@ -47,73 +407,893 @@ impl Camera {
         self.transform_inverse = transform.inverse();
     }
 
-    pub fn ray_for_pixel(&self, x: usize, y: usize, u: f64, v: f64) -> Ray {
-        let px = x as f64;
-        let py = y as f64;
+    /// Ray through the point (u, v) within a pixel, where (0.5, 0.5) is the
+    /// pixel's center. Returns `None` if the projection doesn't cover the point.
+    pub fn ray_for_pixel(&self, x: usize, y: usize, u: f64, v: f64) -> Option<Ray> {
+        let px = x as f64 + u;
+        let py = y as f64 + v;
+        let hsize = self.hsize as f64;
+        let vsize = self.vsize as f64;
+
+        // Rays are built in camera space, looking down -z with +x on the left
+        // of the image, then moved into world space.
+        let (origin, direction) = match self.projection {
+            Projection::Perspective => {
+                let world_x = self.half_width - px * self.pixel_size;
+                let world_y = self.half_height - py * self.pixel_size;
+                let origin = Tuple::point(0.0, 0.0, 0.0);
+                let pixel = Tuple::point(world_x, world_y, -1.0);
+                (origin, pixel - origin)
+            }
+            Projection::Orthographic { width, height } => {
+                let world_x = width * (0.5 - px / hsize);
+                let world_y = height * (0.5 - py / vsize);
+                let origin = Tuple::point(world_x, world_y, 0.0);
+                (origin, Tuple::vector(0.0, 0.0, -1.0))
+            }
+            Projection::Fisheye { angle } => {
+                let radius = hsize.min(vsize) / 2.0;
+                let nx = (hsize / 2.0 - px) / radius;
+                let ny = (vsize / 2.0 - py) / radius;
+                let r = (nx * nx + ny * ny).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let theta = r * angle / 2.0;
+                let phi = ny.atan2(nx);
+                let direction = Tuple::vector(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    -theta.cos(),
+                );
+                (Tuple::point(0.0, 0.0, 0.0), direction)
+            }
+            Projection::Equirectangular => {
+                let longitude = 2.0 * PI * (px / hsize - 0.5);
+                let latitude = PI * (0.5 - py / vsize);
+                let direction = Tuple::vector(
+                    -latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                );
+                (Tuple::point(0.0, 0.0, 0.0), direction)
+            }
+        };
 
-        let xoffset = (px + u) * self.pixel_size;
-        let yoffset = (py + v) * self.pixel_size;
+        let origin = self.transform_inverse * origin;
+        let direction = (self.transform_inverse * direction).normalize();
+        invariants::check_point(origin, "camera ray origin");
+        invariants::check_unit(direction, "camera ray direction");
+        Some(Ray::new(origin, direction))
+    }
 
-        let world_x = self.half_width - xoffset;
-        let world_y = self.half_height - yoffset;
+    pub fn render(&self, world: &World) -> Canvas {
+        let stop = Stop::new(CancelToken::new(), Budget::default());
+        self.render_film(world, None, &stop, None).to_canvas()
+    }
 
-        let pixel = self.transform_inverse * Tuple::point(world_x, world_y, -1.0);
-        let origin = self.transform_inverse * Tuple::point(0.0, 0.0, 0.0);
-        let direction = (pixel - origin).normalize();
+    /// Renders an image unless the token is cancelled first, in which case the
+    /// render stops early and returns nothing.
+    pub fn render_cancellable(&self, world: &World, cancel: &CancelToken) -> Option<Canvas> {
+        let stop = Stop::new(cancel.clone(), Budget::default());
+        let film = self.render_film(world, None, &stop, None);
+        if stop.interrupted() {
+            None
+        } else {
+            Some(film.to_canvas())
+        }
+    }
 
-        Ray::new(origin, direction)
+    /// Renders an image, stopping early if the budget runs out. With tiles
+    /// rendered in scanline order, a partial image is complete down to where
+    /// the render stopped.
+    pub fn render_within(&self, world: &World, budget: Budget) -> Rendering {
+        let stop = Stop::new(CancelToken::new(), budget);
+        let image = self.render_film(world, None, &stop, None).to_canvas();
+        Rendering {
+            image,
+            partial: stop.interrupted(),
+        }
     }
 
-    pub fn render(&self, world: &World) -> Canvas {
-        let sample_count = 9;
-        let mut image = Canvas::new(self.hsize, self.vsize);
+    /// Renders an image like `render_within`, saving the finished tiles to a
+    /// checkpoint as it goes. A render restarted with the same checkpoint,
+    /// scene, and camera settings skips the tiles already saved, and comes out
+    /// identical to one that was never interrupted. The checkpoint is saved
+    /// when the budget runs out, so a long render can also be done in several
+    /// sittings, and removed once the image is complete.
+    pub fn render_checkpointed(
+        &self,
+        world: &World,
+        budget: Budget,
+        checkpoint: &Checkpoint,
+    ) -> Result<Rendering, Box<dyn Error>> {
+        let key = self.settings_key(checkpoint.scene);
+        let tiles: Vec<Tile> = Tiles::new(self.hsize, self.vsize, self.tile_size()).collect();
+        let tile_count = tiles.len();
+        let finished = checkpoint.load(key, tile_count)?;
+        for (index, film) in &finished {
+            let expected = self.tile_film(&tiles[*index]);
+            if film.origin() != expected.origin() || film.size() != expected.size() {
+                return Err(format!("checkpoint has tile {} of the wrong size", index).into());
+            }
+        }
+        let mut run = CheckpointRun {
+            checkpoint,
+            key,
+            tile_count,
+            finished,
+            error: None,
+        };
+        if !run.finished.is_empty() {
+            log::info!(
+                "resuming from {} tiles of {} in the checkpoint",
+                run.finished.len(),
+                tile_count
+            );
+        }
+
+        let stop = Stop::new(CancelToken::new(), budget);
+        let image = self
+            .render_film(world, None, &stop, Some(&mut run))
+            .to_canvas();
+        if let Some(error) = run.error {
+            return Err(format!("cannot save checkpoint: {}", error).into());
+        }
+        if !stop.interrupted() {
+            checkpoint.remove()?;
+        }
+        Ok(Rendering {
+            image,
+            partial: stop.interrupted(),
+        })
+    }
+
+    /// Hash of everything about the camera that affects the image, combined
+    /// with a hash of the scene. The same on every build, as checkpoints and
+    /// render farms compare it between runs and machines.
+    pub fn settings_key(&self, scene: u64) -> u64 {
+        let s = &self.settings;
+        let settings = format!(
+            "{:?}",
+            (
+                self.hsize,
+                self.vsize,
+                self.field_of_view,
+                self.projection,
+                self.transform,
+                (s.samples, s.filter, s.sampler, s.blue_noise),
+                (s.max_depth, s.min_throughput),
+                s.mode,
+                s.seed,
+                self.tile_size(),
+            )
+        );
+        stable_hash(&(scene, settings))
+    }
+
+    /// Renders only the pixels selected by a mask, leaving the rest black. The
+    /// mask holds one entry per pixel in scanline order.
+    ///
+    /// Selected pixels match a full render, since their neighbors within the
+    /// filter's reach are sampled too. Use `Canvas::patch` to copy them into an
+    /// existing image.
+    ///
+    /// Panics if the mask doesn't have an entry for every pixel.
+    pub fn render_masked(&self, world: &World, mask: &[bool]) -> Canvas {
+        assert_eq!(
+            mask.len(),
+            self.hsize * self.vsize,
+            "mask must have one entry per pixel"
+        );
+        // Grow the mask by the filter's reach.
+        let margin = self.settings.filter.radius().ceil() as usize;
+        let mut sampled = vec![false; mask.len()];
         for y in 0..self.vsize {
             for x in 0..self.hsize {
-                let mut color = Color::new(0.0, 0.0, 0.0);
-                for (u, v) in RayOffsets::new(sample_count) {
-                    if x == 0 && y == 0 {
-                        println!("({}, {})", u, v);
+                if mask[y * self.hsize + x] {
+                    let y_max = (y + margin).min(self.vsize - 1);
+                    let x_max = (x + margin).min(self.hsize - 1);
+                    for sy in y.saturating_sub(margin)..=y_max {
+                        for sx in x.saturating_sub(margin)..=x_max {
+                            sampled[sy * self.hsize + sx] = true;
+                        }
                     }
-                    let ray = self.ray_for_pixel(x, y, u, v);
-                    color = color + world.color_at(&ray, 5);
                 }
-                color = color * (1.0 / (sample_count as f64));
-                image.write_pixel(x, y, color);
+            }
+        }
+
+        let stop = Stop::new(CancelToken::new(), Budget::default());
+        let mut image = self
+            .render_film(world, Some(&sampled), &stop, None)
+            .to_canvas();
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                if !mask[y * self.hsize + x] {
+                    image.write_pixel(x, y, Color::new(0.0, 0.0, 0.0));
+                }
             }
         }
         image
     }
+
+    /// Mask selecting a rectangle of pixels.
+    pub fn region_mask(&self, x: usize, y: usize, width: usize, height: usize) -> Vec<bool> {
+        let mut mask = vec![false; self.hsize * self.vsize];
+        for py in y..(y + height).min(self.vsize) {
+            for px in x..(x + width).min(self.hsize) {
+                mask[py * self.hsize + px] = true;
+            }
+        }
+        mask
+    }
+
+    /// Mask selecting the pixels whose center sees one of the given objects,
+    /// or any shape inside them.
+    pub fn object_mask(&self, world: &World, objects: &[Obj]) -> Vec<bool> {
+        let mut mask = vec![false; self.hsize * self.vsize];
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let hit = self
+                    .ray_for_pixel(x, y, 0.5, 0.5)
+                    .and_then(|ray| world.object_at(&ray));
+                if let Some(hit) = hit {
+                    mask[y * self.hsize + x] =
+                        objects.iter().any(|obj| world.obj_pool.includes(hit, *obj));
+                }
+            }
+        }
+        mask
+    }
+
+    /// A copy of the camera, without callbacks, making the same image at most
+    /// `size` pixels across, for quick test renders.
+    pub fn probe(&self, size: usize) -> Camera {
+        let scale = (size as f64 / self.hsize.max(self.vsize) as f64).min(1.0);
+        let hsize = ((self.hsize as f64 * scale).round() as usize).max(1);
+        let vsize = ((self.vsize as f64 * scale).round() as usize).max(1);
+        let mut probe = Camera::new(hsize, vsize, self.field_of_view);
+        probe.projection = self.projection;
+        probe.set_transform(self.transform);
+        probe.settings = self.settings;
+        probe
+    }
+
+    /// Scales every direct light's intensity by the same factor so the
+    /// camera's image averages `target` luminance after exposure, before tone
+    /// mapping. Adding or removing a light then leaves the image as bright as
+    /// before, with the lights keeping their balance with each other. Ambient
+    /// and dome lights fill in the scene's overall light, so they're left as
+    /// they are.
+    ///
+    /// The brightness is measured from renders at most `probe_size` pixels
+    /// across, one with the direct lights and one without, since ambient and
+    /// dome lights, emission, and the background don't scale with them.
+    /// Returns the factor, or `None`, leaving the lights alone, if they don't
+    /// brighten the image.
+    pub fn normalize_lights(
+        &self,
+        world: &mut World,
+        target: f64,
+        probe_size: usize,
+    ) -> Option<f64> {
+        let probe = self.probe(probe_size);
+        let exposure = 2f64.powf(self.settings.exposure);
+        let luminance = |world: &World| {
+            let image = probe.render(world);
+            let mut total = 0.0;
+            for y in 0..image.height {
+                for x in 0..image.width {
+                    total += image.pixel_at(x, y).luminance();
+                }
+            }
+            exposure * total / (image.width * image.height) as f64
+        };
+
+        let direct = |light: &Light| light.kind() == LightKind::Direct;
+        let lit = luminance(world);
+        let intensities: Vec<Color> = world.lights.iter().map(|l| l.intensity()).collect();
+        for light in world.lights.iter_mut().filter(|light| direct(light)) {
+            light.set_intensity(Color::new(0.0, 0.0, 0.0));
+        }
+        let unlit = luminance(world);
+
+        let gain = lit - unlit;
+        let factor = if gain > 0.0 {
+            Some(((target - unlit) / gain).max(0.0))
+        } else {
+            None
+        };
+        for (light, intensity) in world.lights.iter_mut().zip(intensities) {
+            if direct(light) {
+                light.set_intensity(intensity * factor.unwrap_or(1.0));
+            }
+        }
+        factor
+    }
+
+    /// How many pixels across the image a box in world space covers, roughly:
+    /// the size of the sphere around it, as seen from the camera. Infinite
+    /// when the camera is inside the sphere.
+    pub fn screen_size(&self, bounds: &Bounds) -> f64 {
+        let radius = bounds.size().magnitude() / 2.0;
+        let center = self.transform * bounds.center();
+        let distance = Tuple::vector(center.x(), center.y(), center.z()).magnitude();
+        if distance <= radius {
+            return f64::INFINITY;
+        }
+        let angle = 2.0 * (radius / distance).asin();
+        match self.projection {
+            Projection::Perspective => {
+                let across = 2.0 * radius / (distance * distance - radius * radius).sqrt();
+                across / self.pixel_size
+            }
+            Projection::Orthographic { width, .. } => 2.0 * radius * self.hsize as f64 / width,
+            Projection::Fisheye { angle: lens } => angle * self.hsize.min(self.vsize) as f64 / lens,
+            Projection::Equirectangular => angle * self.hsize as f64 / (2.0 * PI),
+        }
+    }
+
+    /// Chooses between objects and their stand-ins for this camera's view,
+    /// tracing the stand-ins of objects too small on screen to need their
+    /// detail. See `ObjPool::set_proxy`. Choose again after moving the camera
+    /// or the objects.
+    pub fn select_lod(&self, world: &mut World) {
+        if world.obj_pool.select_lod(|bounds| self.screen_size(bounds)) {
+            world.scene_changed();
+        }
+    }
+
+    /// Renders the image's samples in parallel, optionally only for the pixels
+    /// selected by a mask. Once stopped, the film is left incomplete.
+    ///
+    /// Tiles are merged in scanline order once every thread is done, so the
+    /// floating point sums, and the image, don't depend on the thread count or
+    /// on which thread finished first. For the same reason, a checkpointed
+    /// render keeps each finished tile's film separate rather than merging
+    /// them into one.
+    fn render_film(
+        &self,
+        world: &World,
+        mask: Option<&[bool]>,
+        stop: &Stop,
+        mut checkpoint: Option<&mut CheckpointRun>,
+    ) -> Film {
+        let selected = |tile: &Tile| match mask {
+            Some(mask) => tile.pixels().any(|(x, y)| mask[y * self.hsize + x]),
+            None => true,
+        };
+        let tiles: Vec<Tile> = Tiles::new(self.hsize, self.vsize, self.tile_size())
+            .filter(selected)
+            .collect();
+        // Tiles keep their scanline index, so they're merged the same way
+        // whatever order they're rendered in.
+        let mut order: Vec<usize> = (0..tiles.len()).collect();
+        if self.settings.tile_order == TileOrder::Morton {
+            let size = self.tile_size();
+            order.sort_by_key(|&i| morton_code(tiles[i].x / size, tiles[i].y / size));
+        }
+        let offsets = self.settings.sampler.offsets(self.settings.samples);
+        let bounds = if self.settings.frustum_culling
+            && self.projection == Projection::Perspective
+            && world.compiled.is_none()
+        {
+            world.obj_pool.world_bounds()
+        } else {
+            Vec::new()
+        };
+        log::debug!(
+            "rendering {} tiles of {} pixels on {} threads",
+            tiles.len(),
+            self.tile_size(),
+            self.thread_count()
+        );
+        log::trace!("sample offsets {:?}", offsets);
+
+        // Films of the tiles rendered so far, by index, and whether each was
+        // finished before the render stopped.
+        let mut resumed = vec![false; tiles.len()];
+        let mut tile_films = Vec::with_capacity(tiles.len());
+        if let Some(run) = &mut checkpoint {
+            for (index, film) in run.finished.drain(..) {
+                resumed[index] = true;
+                tile_films.push((index, film, true));
+            }
+        }
+        let tile_films = Mutex::new(tile_films);
+        let next_tile = AtomicUsize::new(0);
+        let last_save = Mutex::new(Instant::now());
+        let save_error = Mutex::new(None);
+        let save = |tile_films: &[(usize, Film, bool)]| {
+            if let Some(run) = &checkpoint {
+                let finished: Vec<(usize, &Film)> = tile_films
+                    .iter()
+                    .filter(|(_, _, finished)| *finished)
+                    .map(|(index, film, _)| (*index, film))
+                    .collect();
+                if let Err(e) = run.checkpoint.save(run.key, run.tile_count, &finished) {
+                    // Rendering on would risk losing more work than has been
+                    // saved. The token is the render's own, made for the run.
+                    *save_error.lock().unwrap() = Some(e.to_string());
+                    stop.cancel.cancel();
+                }
+            }
+        };
+
+        thread::scope(|s| {
+            for _ in 0..self.thread_count() {
+                s.spawn(|| {
+                    if self.settings.low_priority {
+                        lower_priority();
+                    }
+                    let mut xs = Vec::new();
+                    loop {
+                        let next = next_tile.fetch_add(1, Ordering::Relaxed);
+                        let index = match order.get(next) {
+                            Some(&index) if resumed[index] => continue,
+                            Some(&index) if !stop.stopped() => index,
+                            _ => break,
+                        };
+                        let tile = &tiles[index];
+                        let (tile_film, finished) =
+                            self.render_tile(world, tile, mask, &offsets, &bounds, stop, &mut xs);
+                        if let Some(on_tile) = &self.on_tile {
+                            let (x, y) = tile_film.origin();
+                            on_tile(x, y, tile_film.to_canvas());
+                        }
+                        let mut tile_films = tile_films.lock().unwrap();
+                        tile_films.push((index, tile_film, finished));
+
+                        if let Some(run) = &checkpoint {
+                            let mut last_save = last_save.lock().unwrap();
+                            if last_save.elapsed() >= run.checkpoint.interval {
+                                save(&tile_films);
+                                *last_save = Instant::now();
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut tile_films = tile_films.into_inner().unwrap();
+        if stop.interrupted() && save_error.lock().unwrap().is_none() {
+            save(&tile_films);
+        }
+        if let Some(run) = checkpoint {
+            run.error = save_error.into_inner().unwrap();
+        }
+
+        tile_films.sort_by_key(|(index, _, _)| *index);
+        let mut film = Film::new(self.hsize, self.vsize);
+        for (_, tile_film, _) in &tile_films {
+            film.merge(tile_film);
+        }
+        film
+    }
+
+    fn tile_size(&self) -> usize {
+        if self.settings.tile_size > 0 {
+            return self.settings.tile_size;
+        }
+
+        // The power of two closest to the side of a square tile that splits
+        // the image into enough tiles for every thread.
+        let tiles = self.thread_count() * TILES_PER_THREAD;
+        let side = ((self.hsize * self.vsize) as f64 / tiles as f64).sqrt();
+        let size = 2.0_f64.powf(side.max(1.0).log2().round()) as usize;
+        size.clamp(MIN_TILE_SIZE, MAX_TILE_SIZE)
+    }
+
+    fn thread_count(&self) -> usize {
+        if self.settings.threads > 0 {
+            self.settings.threads
+        } else {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        }
+    }
+
+    /// Empty film for a tile, also covering the neighboring pixels within
+    /// reach of the filter.
+    fn tile_film(&self, tile: &Tile) -> Film {
+        let margin = self.settings.filter.radius().ceil() as usize;
+        let x = tile.x.saturating_sub(margin);
+        let y = tile.y.saturating_sub(margin);
+        let width = (tile.x + tile.width + margin).min(self.hsize) - x;
+        let height = (tile.y + tile.height + margin).min(self.vsize) - y;
+        Film::region(x, y, width, height)
+    }
+
+    /// Renders a tile's samples onto a film that also covers the neighboring
+    /// pixels within reach of the filter. Returns the film, and whether every
+    /// row was rendered before the render stopped.
+    #[allow(clippy::too_many_arguments)]
+    fn render_tile(
+        &self,
+        world: &World,
+        tile: &Tile,
+        mask: Option<&[bool]>,
+        offsets: &[(f64, f64)],
+        bounds: &[Option<Bounds>],
+        stop: &Stop,
+        xs: &mut Vec<Intersection>,
+    ) -> (Film, bool) {
+        let settings = &self.settings;
+        let culled = self.cull_tile(world, bounds, tile, offsets);
+        let mut film = self.tile_film(tile);
+
+        for (x, y) in tile.pixels() {
+            if x == tile.x {
+                if stop.stopped() {
+                    return (film, false);
+                }
+                stop.count_rays((tile.width * settings.samples) as u64);
+            }
+            if mask.is_some_and(|mask| !mask[y * self.hsize + x]) {
+                continue;
+            }
+            for (sample, &(u, v)) in offsets.iter().enumerate() {
+                let (color, path_length) = match self.ray_for_pixel(x, y, u, v) {
+                    Some(ray) => match settings.mode {
+                        RenderMode::Whitted => {
+                            let depth =
+                                Depth::adaptive(settings.max_depth, settings.min_throughput);
+                            (world.color_at_primary(&ray, depth, xs, &culled), None)
+                        }
+                        RenderMode::PathTraced => {
+                            // Each sample gets its own stream, so renders don't
+                            // depend on the order samples are taken in.
+                            let (color, length) = if settings.blue_noise {
+                                let rng = Pcg32::for_key(settings.seed, sample);
+                                let mut rng = BlueNoise::get().decorrelate(rng, x, y);
+                                let key = (x, y, sample);
+                                self.trace_path(world, &ray, key, &mut rng, xs, &culled)
+                            } else {
+                                let mut rng = Pcg32::for_key(settings.seed, (x, y, sample));
+                                let key = (x, y, sample);
+                                self.trace_path(world, &ray, key, &mut rng, xs, &culled)
+                            };
+                            (color, Some(length))
+                        }
+                    },
+                    None => (Color::new(0.0, 0.0, 0.0), None),
+                };
+                if let Some(on_sample) = &self.on_sample {
+                    on_sample(&SampleRecord {
+                        x,
+                        y,
+                        sample,
+                        path_length,
+                        radiance: color,
+                    });
+                }
+                film.splat(x as f64 + u, y as f64 + v, color, settings.filter);
+            }
+        }
+        (film, true)
+    }
+
+    /// Traces a sample's path, showing it to the path callback if the sample
+    /// is one of those chosen.
+    fn trace_path<R: Rng>(
+        &self,
+        world: &World,
+        ray: &Ray,
+        (x, y, sample): (usize, usize, usize),
+        rng: &mut R,
+        xs: &mut Vec<Intersection>,
+        culled: &[bool],
+    ) -> (Color, usize) {
+        let settings = &self.settings;
+        // Chosen by a stream of its own, so choosing doesn't change the path.
+        let chosen = |fraction: f64| {
+            Pcg32::for_key(settings.seed, ("path", x, y, sample)).gen::<f64>() < fraction
+        };
+        match &self.on_path {
+            Some((fraction, on_path)) if chosen(*fraction) => {
+                let mut vertices = Vec::new();
+                let (radiance, length) = world.trace_primary_path(
+                    ray,
+                    settings.max_depth,
+                    rng,
+                    xs,
+                    culled,
+                    Some(&mut vertices),
+                );
+                on_path(&PathRecord {
+                    x,
+                    y,
+                    sample,
+                    vertices,
+                    radiance,
+                });
+                (radiance, length)
+            }
+            _ => world.trace_primary_path(ray, settings.max_depth, rng, xs, culled, None),
+        }
+    }
+
+    /// Objects a tile's camera rays can't reach, by id, from every object's
+    /// world space bounds, or none when there are no bounds to cull by.
+    fn cull_tile(
+        &self,
+        world: &World,
+        bounds: &[Option<Bounds>],
+        tile: &Tile,
+        offsets: &[(f64, f64)],
+    ) -> Vec<bool> {
+        if bounds.is_empty() || offsets.is_empty() {
+            return Vec::new();
+        }
+
+        // The rays pass through the view plane between the lowest sample
+        // offset in the tile's first pixels and the highest in its last,
+        // widened a little against rounding.
+        const PAD: f64 = 1e-3;
+        let span = |values: &mut dyn Iterator<Item = f64>| {
+            values.fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), v| {
+                (low.min(v), high.max(v))
+            })
+        };
+        let (min_u, max_u) = span(&mut offsets.iter().map(|o| o.0));
+        let (min_v, max_v) = span(&mut offsets.iter().map(|o| o.1));
+        let left = tile.x as f64 + min_u - PAD;
+        let right = (tile.x + tile.width - 1) as f64 + max_u + PAD;
+        let top = tile.y as f64 + min_v - PAD;
+        let bottom = (tile.y + tile.height - 1) as f64 + max_v + PAD;
+
+        // Planes through the camera bounding the pyramid, facing in.
+        let direction = |px: f64, py: f64| {
+            let x = self.half_width - px * self.pixel_size;
+            let y = self.half_height - py * self.pixel_size;
+            self.transform_inverse * Tuple::vector(x, y, -1.0)
+        };
+        let edges = [
+            direction(left, top),
+            direction(right, top),
+            direction(right, bottom),
+            direction(left, bottom),
+        ];
+        let middle = edges[0] + edges[1] + edges[2] + edges[3];
+        let normals: Vec<Tuple> = (0..4)
+            .map(|i| {
+                let normal = edges[i].cross(edges[(i + 1) % 4]);
+                if normal.dot(middle) < 0.0 {
+                    -normal
+                } else {
+                    normal
+                }
+            })
+            .collect();
+        let origin = self.transform_inverse * Tuple::point(0.0, 0.0, 0.0);
+
+        world.obj_pool.cull(bounds, |bounds| {
+            let corners = bounds.corners();
+            let beyond = |side: f64| {
+                normals
+                    .iter()
+                    .any(|n| corners.iter().all(|&c| side * n.dot(c - origin) < 0.0))
+            };
+            // Intersections behind the camera count too, such as for which
+            // object a ray starts inside, so a box must be clear of the
+            // pyramid mirrored behind the camera as well.
+            beyond(1.0) && beyond(-1.0)
+        })
+    }
+}
+
+/// A checkpointed render in progress.
+struct CheckpointRun<'a> {
+    checkpoint: &'a Checkpoint,
+
+    /// Identifies the scene and camera settings.
+    key: u64,
+
+    tile_count: usize,
+
+    /// Films of the tiles finished before the render resumed, by index.
+    finished: Vec<(usize, Film)>,
+
+    /// Why saving the checkpoint failed, if it did.
+    error: Option<String>,
+}
+
+/// Decides when a render in progress should stop, either because it was
+/// cancelled or because its budget ran out.
+struct Stop {
+    cancel: CancelToken,
+    deadline: Option<Instant>,
+    max_rays: Option<u64>,
+    rays: AtomicU64,
+    interrupted: AtomicBool,
+}
+
+impl Stop {
+    fn new(cancel: CancelToken, budget: Budget) -> Self {
+        Stop {
+            cancel,
+            deadline: budget.time.map(|time| Instant::now() + time),
+            max_rays: budget.camera_rays,
+            rays: AtomicU64::new(0),
+            interrupted: AtomicBool::new(false),
+        }
+    }
+
+    fn count_rays(&self, rays: u64) {
+        self.rays.fetch_add(rays, Ordering::Relaxed);
+    }
+
+    /// Whether to abandon the remaining work. Only ask when there is work
+    /// left, since a yes marks the render as interrupted.
+    fn stopped(&self) -> bool {
+        let stopped = self.cancel.is_cancelled()
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            || self
+                .max_rays
+                .is_some_and(|max| self.rays.load(Ordering::Relaxed) >= max);
+        if stopped {
+            self.interrupted.store(true, Ordering::Relaxed);
+        }
+        stopped
+    }
+
+    fn interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
 }
 
+/// A rectangular region of an image.
+#[derive(Copy, Clone, Debug)]
+struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
 
+impl Tile {
+    /// Iterates through the tile's pixels in scanline order.
+    fn pixels(&self) -> impl Iterator<Item = (usize, usize)> {
+        let Tile {
+            x,
+            y,
+            width,
+            height,
+        } = *self;
+        (y..y + height).flat_map(move |py| (x..x + width).map(move |px| (px, py)))
+    }
+}
 
-/// Iterates through rays generated for a given pixel.
-struct RayOffsets {
-    count: i64,
-    current: i64,
+/// Iterates through the tiles covering an image in scanline order.
+struct Tiles {
+    hsize: usize,
+    vsize: usize,
+    size: usize,
+    x: usize,
+    y: usize,
 }
 
-impl RayOffsets {
-    fn new(count: i64) -> Self {
-        let current = count;
-        RayOffsets { count, current }
+impl Tiles {
+    fn new(hsize: usize, vsize: usize, size: usize) -> Self {
+        Tiles {
+            hsize,
+            vsize,
+            size,
+            x: 0,
+            y: 0,
+        }
     }
 }
 
-impl Iterator for RayOffsets {
-    type Item = (f64, f64);
+impl Iterator for Tiles {
+    type Item = Tile;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.current -= 1;
-        match self.current {
-            x if x < 0 => None,
-            0 => Some((0.5, 0.5)),
-            x => {
-                let angle = 2.0 * std::f64::consts::PI * ((x as f64) / (self.count as f64));
-                let u = angle.sin() / 4.0 + 0.5;
-                let v = angle.cos() / 4.0 + 0.5;
-                Some((u, v))
+        if self.x >= self.hsize {
+            self.x = 0;
+            self.y += self.size;
+        }
+        if self.y >= self.vsize || self.hsize == 0 {
+            return None;
+        }
+
+        let tile = Tile {
+            x: self.x,
+            y: self.y,
+            width: self.size.min(self.hsize - self.x),
+            height: self.size.min(self.vsize - self.y),
+        };
+        self.x += self.size;
+        Some(tile)
+    }
+}
+
+/// Position of a cell of a grid along a Z-order curve, from interleaving the
+/// bits of its column and row.
+fn morton_code(x: usize, y: usize) -> u64 {
+    let mut code = 0;
+    for bit in 0..32 {
+        code |= ((x as u64 >> bit) & 1) << (2 * bit);
+        code |= ((y as u64 >> bit) & 1) << (2 * bit + 1);
+    }
+    code
+}
+
+/// Lowers the calling thread's scheduling priority as far as it goes.
+#[cfg(unix)]
+fn lower_priority() {
+    // Failing leaves the thread at its priority, which only matters to how
+    // responsive the machine stays.
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+    }
+}
+
+#[cfg(not(unix))]
+fn lower_priority() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_color_near, default_world, fixture_camera};
+
+    #[test]
+    fn renders_the_same_pixels_on_any_number_of_threads() {
+        let world = default_world();
+        let mut camera = fixture_camera(24);
+        camera.set_samples(9);
+        camera.set_filter(Filter::Mitchell);
+        camera.set_tile_size(5);
+
+        camera.set_threads(1);
+        let serial = camera.render(&world);
+        camera.set_threads(4);
+        let parallel = camera.render(&world);
+
+        for y in 0..serial.height {
+            for x in 0..serial.width {
+                let (a, b) = (serial.pixel_at(x, y), parallel.pixel_at(x, y));
+                let bits = |c: Color| [c.red.to_bits(), c.green.to_bits(), c.blue.to_bits()];
+                assert_eq!(bits(a), bits(b), "pixel ({}, {}) differs", x, y);
             }
         }
     }
+
+    #[test]
+    fn checkpoint_keys_are_the_same_on_every_build() {
+        let camera = fixture_camera(11);
+        assert_eq!(camera.settings_key(7), 16_278_604_537_073_971_315);
+
+        let mut other = fixture_camera(11);
+        other.set_samples(4);
+        assert_ne!(other.settings_key(7), camera.settings_key(7));
+        assert_ne!(camera.settings_key(8), camera.settings_key(7));
+    }
+
+    #[test]
+    fn normalizing_leaves_ambient_lights_alone() {
+        let mut world = default_world();
+        let fill = Color::new(0.3, 0.3, 0.3);
+        world.lights = vec![Light::new_ambient(fill)];
+        let camera = fixture_camera(11);
+
+        assert_eq!(camera.normalize_lights(&mut world, 0.5, 11), None);
+        assert_color_near(world.lights[0].intensity(), fill, 0.0);
+    }
+
+    #[test]
+    fn normalizes_direct_lights_around_the_ambient_light() {
+        let mut world = default_world();
+        let fill = Color::new(0.3, 0.3, 0.3);
+        world.lights.push(Light::new_ambient(fill));
+        let camera = fixture_camera(11);
+
+        let factor = camera.normalize_lights(&mut world, 0.5, 11).unwrap();
+        assert!(factor > 0.0 && factor != 1.0, "factor {}", factor);
+        let white = Color::new(1.0, 1.0, 1.0);
+        assert_color_near(world.lights[0].intensity(), white * factor, 1e-12);
+        assert_color_near(world.lights[1].intensity(), fill, 0.0);
+    }
 }
@@ -1,8 +1,23 @@
 use crate::color::Color;
+use crate::filter::mitchell;
 use crate::util::*;
 use std::error::Error;
 use std::fmt::Write;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
 
+/// Reconstruction filters used when shrinking a canvas.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Downsample {
+    /// Averages each block of pixels.
+    Box,
+
+    /// Mitchell-Netravali cubic (B = C = 1/3), sharper than a box.
+    Mitchell,
+}
+
+#[derive(Clone)]
 pub struct Canvas {
     pub width: usize,
     pub height: usize,
@@ -21,6 +36,113 @@ impl Canvas {
         }
     }
 
+    /// Reads a PPM image, either plain (P3) or raw (P6), scaling channels by
+    /// its maximum color value. Comments are allowed wherever the format allows
+    /// whitespace before the pixel data.
+    pub fn from_ppm<R: Read>(mut reader: R) -> Result<Canvas, Box<dyn Error>> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut tokens = PpmTokens {
+            bytes: &bytes,
+            position: 0,
+        };
+
+        let magic = tokens.next().ok_or("empty PPM image")?;
+        let raw = match magic {
+            "P3" => false,
+            "P6" => true,
+            _ => return Err(format!("unsupported PPM format '{}'", magic).into()),
+        };
+        let width = tokens.number()?;
+        let height = tokens.number()?;
+        let max = tokens.number()?;
+        if max == 0 || max > 65535 {
+            return Err(format!("invalid PPM maximum color value {}", max).into());
+        }
+
+        // A single whitespace byte separates the header from raw samples,
+        // which take two bytes, most significant first, above 255. Plain
+        // samples take at least a digit each. Check there's enough data for
+        // the image before allocating it, so that a short file can't claim an
+        // enormous one.
+        let sample_size = if raw && max > 255 { 2 } else { 1 };
+        let start = tokens.position + 1;
+        let length = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3 * sample_size))
+            .ok_or("PPM image is too large")?;
+        if length > bytes.len().saturating_sub(start) {
+            return Err("truncated PPM image".into());
+        }
+
+        let mut canvas = Canvas::new(width, height);
+        if raw {
+            let data = &bytes[start..start + length];
+            let sample = |i: usize| {
+                let bytes = &data[i * sample_size..(i + 1) * sample_size];
+                let value = bytes.iter().fold(0, |value, &b| value << 8 | b as usize);
+                value as f64 / max as f64
+            };
+            for (i, pixel) in canvas.pixels.iter_mut().enumerate() {
+                *pixel = Color::new(sample(3 * i), sample(3 * i + 1), sample(3 * i + 2));
+            }
+        } else {
+            for pixel in canvas.pixels.iter_mut() {
+                let red = tokens.number()? as f64 / max as f64;
+                let green = tokens.number()? as f64 / max as f64;
+                let blue = tokens.number()? as f64 / max as f64;
+                *pixel = Color::new(red, green, blue);
+            }
+        }
+        Ok(canvas)
+    }
+
+    /// Reads a PNG image. Channels are scaled to [0, 1] and alpha is ignored.
+    #[cfg(feature = "png")]
+    pub fn from_png<R: Read>(reader: R) -> Result<Canvas, Box<dyn Error>> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+
+        let channels = info.color_type.samples();
+        let (sample_size, max) = match info.bit_depth {
+            png::BitDepth::Sixteen => (2, 65535.0),
+            _ => (1, 255.0),
+        };
+        let sample = |i: usize| {
+            let bytes = &buffer[i * sample_size..(i + 1) * sample_size];
+            let value = bytes.iter().fold(0, |value, &b| value << 8 | b as usize);
+            value as f64 / max
+        };
+
+        let mut canvas = Canvas::new(info.width as usize, info.height as usize);
+        for (i, pixel) in canvas.pixels.iter_mut().enumerate() {
+            let first = i * channels;
+            *pixel = if channels >= 3 {
+                Color::new(sample(first), sample(first + 1), sample(first + 2))
+            } else {
+                let gray = sample(first);
+                Color::new(gray, gray, gray)
+            };
+        }
+        Ok(canvas)
+    }
+
+    /// Reads an image file, choosing the format by the file's extension.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Canvas, Box<dyn Error>> {
+        let path = path.as_ref();
+        let file = BufReader::new(File::open(path)?);
+        match path.extension().and_then(|e| e.to_str()) {
+            #[cfg(feature = "png")]
+            Some("png") => Canvas::from_png(file),
+            #[cfg(not(feature = "png"))]
+            Some("png") => Err("PNG support requires the png feature".into()),
+            _ => Canvas::from_ppm(file),
+        }
+    }
+
     pub fn write_pixel(&mut self, x: usize, y: usize, color: Color) {
         self.pixels[pixel_index(x, y, self.width)] = color;
     }
@@ -29,23 +151,227 @@ impl Canvas {
         self.pixels[pixel_index(x, y, self.width)]
     }
 
+    /// Bilinearly samples the canvas at texture coordinates in [0, 1], with
+    /// (0, 0) at the top left. Coordinates outside wrap around horizontally
+    /// when `wrap` is set, and otherwise clamp to the edge.
+    pub fn sample(&self, u: f64, v: f64, wrap: bool) -> Color {
+        if self.width == 0 || self.height == 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let x = u * self.width as f64 - 0.5;
+        let x = if wrap {
+            x
+        } else {
+            x.clamp(0.0, (self.width - 1) as f64)
+        };
+        let y = (v * self.height as f64 - 0.5).clamp(0.0, (self.height - 1) as f64);
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+
+        let column = |x: f64| (x as i64).rem_euclid(self.width as i64) as usize;
+        let (x0, x1) = if wrap {
+            (column(x0), column(x0 + 1.0))
+        } else {
+            (x0 as usize, (x0 as usize + 1).min(self.width - 1))
+        };
+        let y0 = y0 as usize;
+        let y1 = (y0 + 1).min(self.height - 1);
+
+        let top = self.pixel_at(x0, y0) * (1.0 - fx) + self.pixel_at(x1, y0) * fx;
+        let bottom = self.pixel_at(x0, y1) * (1.0 - fx) + self.pixel_at(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    /// Copies the pixels selected by a mask, one entry per pixel in scanline
+    /// order, from another canvas of the same size.
+    pub fn patch(&mut self, other: &Canvas, mask: &[bool]) {
+        for (i, selected) in mask.iter().enumerate() {
+            if *selected {
+                self.pixels[i] = other.pixels[i];
+            }
+        }
+    }
+
+    /// Shrinks the canvas by an integer factor. Pixels are filtered in linear
+    /// space with normalized weights, so the overall brightness is preserved.
+    pub fn downsample(&self, factor: usize, filter: Downsample) -> Canvas {
+        let factor = factor.max(1);
+        let width = self.width.div_ceil(factor);
+        let height = self.height.div_ceil(factor);
+
+        // Filter rows, then columns.
+        let columns = downsample_weights(self.width, width, factor, filter);
+        let rows = downsample_weights(self.height, height, factor, filter);
+
+        let mut horizontal = Canvas::new(width, self.height);
+        for y in 0..self.height {
+            for (x, (start, weights)) in columns.iter().enumerate() {
+                let mut color = Color::new(0.0, 0.0, 0.0);
+                for (i, w) in weights.iter().enumerate() {
+                    color = color + self.pixel_at(start + i, y) * *w;
+                }
+                horizontal.write_pixel(x, y, color);
+            }
+        }
+
+        let mut image = Canvas::new(width, height);
+        for (y, (start, weights)) in rows.iter().enumerate() {
+            for x in 0..width {
+                let mut color = Color::new(0.0, 0.0, 0.0);
+                for (i, w) in weights.iter().enumerate() {
+                    color = color + horizontal.pixel_at(x, start + i) * *w;
+                }
+                image.write_pixel(x, y, color);
+            }
+        }
+        image
+    }
+
     pub fn to_ppm(&self) -> Result<String, Box<dyn Error>> {
         let mut ppm = String::new();
         write!(ppm, "P3\n{} {}\n255\n", self.width, self.height)?;
         for color in self.pixels.iter() {
-            let red = clamp(color.red, 0.0, 1.0);
-            let green = clamp(color.green, 0.0, 1.0);
-            let blue = clamp(color.blue, 0.0, 1.0);
-            let red = (255.0 * red) as u8;
-            let green = (255.0 * green) as u8;
-            let blue = (255.0 * blue) as u8;
-            write!(ppm, "{} {} {}\n", red, green, blue)?;
-        }
-        write!(ppm, "\n")?;
+            let [red, green, blue] = rgb8(*color);
+            writeln!(ppm, "{} {} {}", red, green, blue)?;
+        }
+        writeln!(ppm)?;
         Ok(ppm)
     }
+
+    /// Pixels as 8 bit red, green, and blue bytes in scanline order, clamped
+    /// and quantized the same way as `to_ppm`.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|color| rgb8(*color)).collect()
+    }
+}
+
+fn rgb8(color: Color) -> [u8; 3] {
+    let red = clamp(color.red, 0.0, 1.0);
+    let green = clamp(color.green, 0.0, 1.0);
+    let blue = clamp(color.blue, 0.0, 1.0);
+    [
+        (255.0 * red) as u8,
+        (255.0 * green) as u8,
+        (255.0 * blue) as u8,
+    ]
+}
+
+/// Splits the text of a PPM image into whitespace separated tokens, skipping
+/// comments, which run from a '#' to the end of the line.
+struct PpmTokens<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PpmTokens<'a> {
+    fn number(&mut self) -> Result<usize, Box<dyn Error>> {
+        let token = self.next().ok_or("truncated PPM image")?;
+        Ok(token.parse()?)
+    }
+}
+
+impl<'a> Iterator for PpmTokens<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.bytes;
+        loop {
+            match bytes.get(self.position)? {
+                b'#' => {
+                    while bytes.get(self.position).is_some_and(|&b| b != b'\n') {
+                        self.position += 1;
+                    }
+                }
+                b if b.is_ascii_whitespace() => self.position += 1,
+                _ => break,
+            }
+        }
+
+        let start = self.position;
+        while bytes
+            .get(self.position)
+            .is_some_and(|&b| !b.is_ascii_whitespace() && b != b'#')
+        {
+            self.position += 1;
+        }
+        std::str::from_utf8(&bytes[start..self.position]).ok()
+    }
 }
 
 fn pixel_index(x: usize, y: usize, width: usize) -> usize {
     y * width + x
 }
+
+/// Computes, for each destination pixel along one axis, the first source pixel
+/// it draws from and the normalized weights of the source pixels.
+fn downsample_weights(
+    source: usize,
+    destination: usize,
+    factor: usize,
+    filter: Downsample,
+) -> Vec<(usize, Vec<f64>)> {
+    let scale = factor as f64;
+    let radius = match filter {
+        Downsample::Box => 0.5,
+        Downsample::Mitchell => 2.0,
+    };
+
+    (0..destination)
+        .map(|d| {
+            let center = (d as f64 + 0.5) * scale;
+            let start = (center - radius * scale).floor().max(0.0) as usize;
+            let end = ((center + radius * scale).ceil() as usize).min(source);
+
+            let mut weights: Vec<f64> = (start..end)
+                .map(|s| {
+                    let x = (s as f64 + 0.5 - center) / scale;
+                    match filter {
+                        Downsample::Box if x.abs() < 0.5 => 1.0,
+                        Downsample::Box => 0.0,
+                        Downsample::Mitchell => mitchell(x),
+                    }
+                })
+                .collect();
+
+            let total: f64 = weights.iter().sum();
+            if total != 0.0 {
+                for w in weights.iter_mut() {
+                    *w /= total;
+                }
+            }
+            (start, weights)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_pixel_near, COLOR_TOLERANCE};
+
+    #[test]
+    fn reads_plain_and_raw_ppm() {
+        let plain = Canvas::from_ppm(&b"P3\n# comment\n2 1\n255\n255 0 0 0 51 255\n"[..]).unwrap();
+        let raw = Canvas::from_ppm(&b"P6 2 1 255\n\xff\x00\x00\x00\x33\xff"[..]).unwrap();
+        for canvas in [plain, raw] {
+            assert_eq!((canvas.width, canvas.height), (2, 1));
+            assert_pixel_near(&canvas, 0, 0, Color::new(1.0, 0.0, 0.0), COLOR_TOLERANCE);
+            assert_pixel_near(&canvas, 1, 0, Color::new(0.0, 0.2, 1.0), COLOR_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn rejects_images_larger_than_their_data() {
+        for ppm in [
+            &b"P6 100000 100000 255"[..],
+            b"P3 100000 100000 255\n0 0 0\n",
+            b"P6 2 1 65535\n\x00\x00\x00\x00\x00\x00",
+            b"P6 4294967296 4294967296 255\n",
+        ] {
+            assert!(Canvas::from_ppm(ppm).is_err());
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::light::Light;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::object::{CsgOp, Obj, ObjPool};
+use crate::shape::Shape;
+use crate::tuple::Tuple;
+
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// The table the glass stands on, the first object the scene adds, where its
+/// caustics fall.
+pub const TABLE: Obj = 0;
+
+/// Radius of the glass, which stands on the origin.
+const GLASS_RADIUS: f64 = 1.0;
+
+/// Thickness of the glass's wall. Its base is twice as thick.
+const GLASS_WALL: f64 = 0.08;
+
+const GLASS_HEIGHT: f64 = 2.5;
+
+/// Height of the water's surface above the table.
+const WATER_LEVEL: f64 = 1.8;
+
+/// Gap left between the water and the inside of the glass, so that their
+/// surfaces don't coincide.
+const WATER_GAP: f64 = 0.001;
+
+/// A glass of water standing on a table, lit only by a small bright lamp
+/// above and to one side of it, for checking caustics: the glass and water
+/// focus the lamp's light into a bright crescent on the table, on the far
+/// side of the glass's shadow.
+///
+/// The lamp is an emissive sphere and there are no point lights, so the
+/// scene is meant to be path traced. Light through the glass only reaches
+/// the table along paths bouncing off the table, through the glass, and onto
+/// the lamp, which takes many samples per pixel to find. Compare a render
+/// with one of many more samples using [`measure`].
+pub fn scene() -> (ObjPool, Camera, Vec<Light>) {
+    let mut obj_pool = ObjPool::new();
+
+    let table = obj_pool.add_shape(Shape::Plane, Matrix::identity(), table_material());
+    assert_eq!(table, TABLE);
+
+    let outside = cylinder(&mut obj_pool, 0.0, GLASS_HEIGHT, GLASS_RADIUS);
+    let inside = cylinder(
+        &mut obj_pool,
+        2.0 * GLASS_WALL,
+        GLASS_HEIGHT + 1.0,
+        GLASS_RADIUS - GLASS_WALL,
+    );
+    let glass = obj_pool.add_csg(CsgOp::Difference, Matrix::identity(), outside, inside);
+    obj_pool.set_material(glass, clear(1.5));
+
+    let water = cylinder(
+        &mut obj_pool,
+        2.0 * GLASS_WALL + WATER_GAP,
+        WATER_LEVEL,
+        GLASS_RADIUS - GLASS_WALL - WATER_GAP,
+    );
+    obj_pool.set_material(water, clear(1.33));
+
+    {
+        let mut material = Material::new();
+        material.emission = Color::new(60.0, 57.0, 51.0);
+        material.ambient = 0.0;
+        material.diffuse = 0.0;
+        material.specular = 0.0;
+        material.casts_shadow = false;
+        let transform = Matrix::translation(-3.0, 8.0, 2.0) * Matrix::scaling(1.0, 1.0, 1.0);
+        obj_pool.add_shape(Shape::Sphere, transform, material);
+    }
+
+    let (h, w) = (480, 640);
+    let mut camera = Camera::new(w, h, 40.0 * PI / 180.0);
+    let from = Tuple::point(0.5, 5.0, -7.0);
+    let to = Tuple::point(0.8, 0.6, 0.5);
+    let up = Tuple::vector(0.0, 1.0, 0.0);
+    camera.set_transform(Matrix::view_transform(from, to, up));
+
+    (obj_pool, camera, Vec::new())
+}
+
+/// A closed cylinder standing upright on the y axis.
+fn cylinder(obj_pool: &mut ObjPool, bottom: f64, top: f64, radius: f64) -> Obj {
+    let shape = Shape::Cylinder {
+        y_min: bottom,
+        y_max: top,
+        closed: true,
+    };
+    let transform = Matrix::scaling(radius, 1.0, radius);
+    obj_pool.add_shape(shape, transform, None)
+}
+
+fn table_material() -> Material {
+    let mut material = Material::new();
+    material.color = Color::new(0.8, 0.75, 0.7);
+    material.ambient = 0.0;
+    material.specular = 0.0;
+    material
+}
+
+/// A clear, colorless material bending light by a refractive index.
+fn clear(refractive_index: f64) -> Material {
+    let mut material = Material::new();
+    material.color = Color::new(0.0, 0.0, 0.0);
+    material.ambient = 0.0;
+    material.diffuse = 0.0;
+    material.specular = 1.0;
+    material.shininess = 300.0;
+    material.reflective = 1.0;
+    material.transparency = 1.0;
+    material.refractive_index = refractive_index;
+    material
+}
+
+/// How a render's caustics compare with a reference's, such as one with many
+/// more samples.
+///
+/// Only light brighter than the reference's median over the masked pixels
+/// counts: with the mask covering the table, that's its ordinary lighting,
+/// and what's left over is light the glass focused onto it.
+#[derive(Copy, Clone, Debug)]
+pub struct CausticMetrics {
+    /// The render's total focused light over the reference's, 1.0 when the
+    /// caustics carry as much light, and less when paths carrying it were
+    /// missed.
+    pub energy_ratio: f64,
+
+    /// Total variation distance between where the two images' focused light
+    /// falls, each as a share of its own total: 0.0 when it's spread the
+    /// same way, and 1.0 when none of it falls in the same pixels.
+    pub distribution_error: f64,
+
+    /// Distance in pixels between the centers of the two images' focused
+    /// light.
+    pub centroid_offset: f64,
+
+    /// The render's brightest masked pixel over the reference's, which is
+    /// far from 1.0 when noise puts fireflies into the caustics, or blurs
+    /// out their sharp peaks.
+    pub peak_ratio: f64,
+}
+
+/// Measures a render's caustics against a reference render of the same
+/// size, over the masked pixels. Images read back from PPM files are clamped
+/// to their displayable range, which flattens the brightest caustics, so
+/// lower the exposure of both renders to keep their peaks.
+pub fn measure(
+    image: &Canvas,
+    reference: &Canvas,
+    mask: &[bool],
+) -> Result<CausticMetrics, Box<dyn Error>> {
+    if image.width != reference.width || image.height != reference.height {
+        return Err(format!(
+            "cannot compare a {}x{} image with a {}x{} reference",
+            image.width, image.height, reference.width, reference.height
+        )
+        .into());
+    }
+    if mask.len() != image.width * image.height {
+        return Err("mask doesn't cover the image".into());
+    }
+
+    let pixels: Vec<(usize, usize)> = (0..image.height)
+        .flat_map(|y| (0..image.width).map(move |x| (x, y)))
+        .filter(|(x, y)| mask[y * image.width + x])
+        .collect();
+    if pixels.is_empty() {
+        return Err("mask selects no pixels".into());
+    }
+    let luminance = |canvas: &Canvas| -> Vec<f64> {
+        pixels
+            .iter()
+            .map(|&(x, y)| canvas.pixel_at(x, y).luminance().max(0.0))
+            .collect()
+    };
+    let ours = luminance(image);
+    let theirs = luminance(reference);
+
+    let mut sorted = theirs.clone();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median = sorted[sorted.len() / 2];
+    let focused =
+        |values: &[f64]| -> Vec<f64> { values.iter().map(|v| (v - median).max(0.0)).collect() };
+    let ours = focused(&ours);
+    let theirs = focused(&theirs);
+    let our_total: f64 = ours.iter().sum();
+    let their_total: f64 = theirs.iter().sum();
+    if their_total <= 0.0 {
+        return Err("reference has no caustics over the masked pixels".into());
+    }
+
+    let distribution_error = if our_total > 0.0 {
+        0.5 * ours
+            .iter()
+            .zip(&theirs)
+            .map(|(a, b)| (a / our_total - b / their_total).abs())
+            .sum::<f64>()
+    } else {
+        1.0
+    };
+
+    let centroid = |values: &[f64], total: f64| -> (f64, f64) {
+        values
+            .iter()
+            .zip(&pixels)
+            .fold((0.0, 0.0), |(cx, cy), (v, &(x, y))| {
+                (cx + v * x as f64 / total, cy + v * y as f64 / total)
+            })
+    };
+    let centroid_offset = if our_total > 0.0 {
+        let (ax, ay) = centroid(&ours, our_total);
+        let (bx, by) = centroid(&theirs, their_total);
+        (ax - bx).hypot(ay - by)
+    } else {
+        f64::INFINITY
+    };
+
+    let peak = |values: &[f64]| values.iter().cloned().fold(0.0, f64::max);
+    Ok(CausticMetrics {
+        energy_ratio: our_total / their_total,
+        distribution_error,
+        centroid_offset,
+        peak_ratio: peak(&ours) / peak(&theirs),
+    })
+}
+
+impl fmt::Display for CausticMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "caustic energy ratio: {:.3}", self.energy_ratio)?;
+        writeln!(f, "distribution error:   {:.3}", self.distribution_error)?;
+        writeln!(
+            f,
+            "centroid offset:      {:.1} pixels",
+            self.centroid_offset
+        )?;
+        write!(f, "peak ratio:           {:.3}", self.peak_ratio)
+    }
+}
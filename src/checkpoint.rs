@@ -0,0 +1,112 @@
+use crate::film::Film;
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Marks a checkpoint file, and its format's version.
+const MAGIC: &[u8; 8] = b"RTCKPT1\n";
+
+/// Where and how often a render saves its progress, so that it can resume
+/// after a crash instead of starting over. See `Camera::render_checkpointed`.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub path: PathBuf,
+
+    /// Time between saves.
+    pub interval: Duration,
+
+    /// Identifies the scene, so that a checkpoint of another scene isn't
+    /// resumed. Any hash of whatever the scene is built from will do. The
+    /// camera's settings are checked separately.
+    pub scene: u64,
+}
+
+impl Checkpoint {
+    pub fn new(path: impl Into<PathBuf>, interval: Duration, scene: u64) -> Self {
+        Checkpoint {
+            path: path.into(),
+            interval,
+            scene,
+        }
+    }
+
+    /// Loads the finished tiles saved for a render, by their index. Returns
+    /// none if there is no checkpoint yet, and an error if the checkpoint
+    /// belongs to a different render.
+    pub fn load(&self, key: u64, tile_count: usize) -> Result<Vec<(usize, Film)>, Box<dyn Error>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut reader = BufReader::new(file);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(format!("{} is not a render checkpoint", self.path.display()).into());
+        }
+
+        if read_u64(&mut reader)? != key || read_u64(&mut reader)? != tile_count as u64 {
+            return Err(format!(
+                "checkpoint {} is of a different scene or settings",
+                self.path.display()
+            )
+            .into());
+        }
+        let finished = read_u64(&mut reader)?;
+
+        let mut tiles = Vec::new();
+        for _ in 0..finished {
+            let index = read_u64(&mut reader)? as usize;
+            if index >= tile_count {
+                return Err(format!("checkpoint has tile {} out of {}", index, tile_count).into());
+            }
+            tiles.push((index, Film::read_from(&mut reader)?));
+        }
+        Ok(tiles)
+    }
+
+    /// Saves the finished tiles of a render. The checkpoint is written next to
+    /// its path first and then moved into place, so a crash while saving
+    /// leaves the previous checkpoint intact.
+    pub fn save(
+        &self,
+        key: u64,
+        tile_count: usize,
+        tiles: &[(usize, &Film)],
+    ) -> Result<(), Box<dyn Error>> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        writer.write_all(MAGIC)?;
+        for n in [key, tile_count as u64, tiles.len() as u64] {
+            writer.write_all(&n.to_le_bytes())?;
+        }
+        for (index, film) in tiles {
+            writer.write_all(&(*index as u64).to_le_bytes())?;
+            film.write_to(&mut writer)?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+
+    /// Deletes the checkpoint once the render it was for has finished.
+    pub fn remove(&self) -> Result<(), Box<dyn Error>> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Box<dyn Error>> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
@@ -0,0 +1,362 @@
+use crate::algorithm::uniform_disk;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::light::Light;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::object::{CsgOp, Obj, ObjPool};
+use crate::pattern::PatternBuilder;
+use crate::rng::Pcg32;
+use crate::shape::Shape;
+use crate::tuple::Tuple;
+
+use rand::SeedableRng;
+
+use std::f64::consts::PI;
+
+/// Point lights standing in for the area light, which softens the pieces'
+/// shadows. More samples give smoother penumbras and take longer to render.
+const LIGHT_SAMPLES: usize = 8;
+
+/// Radius of the area light.
+const LIGHT_RADIUS: f64 = 1.5;
+
+/// Thickness of the board, whose top is at y = 0 and whose squares are one
+/// unit across.
+const BOARD_THICKNESS: f64 = 0.2;
+
+/// Width of the frame around the board's squares.
+const BOARD_BORDER: f64 = 0.4;
+
+/// The pieces, in the order they stand on the back rank from the a file.
+const BACK_RANK: [Piece; 8] = [
+    Piece::Rook,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Queen,
+    Piece::King,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Rook,
+];
+
+/// A kind of chess piece.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Piece {
+    Pawn,
+    Rook,
+    Knight,
+    Bishop,
+    Queen,
+    King,
+}
+
+/// A chess set on its board, set up to start a game, under a soft area light.
+///
+/// Pieces are turned from stacked discs and tapers like a lathe would, and
+/// carved with CSG: the rook's crenellations, the bishop's mitre, and the
+/// queen's crown. Each piece is a group marked as an instance. White pieces
+/// are glossy ivory and black ones polished ebony, on a reflective board.
+/// The seed places the area light's samples.
+pub fn scene(seed: u64) -> (ObjPool, Camera, Vec<Light>) {
+    let mut obj_pool = ObjPool::new();
+    add_board(&mut obj_pool);
+
+    for (file, back) in BACK_RANK.iter().enumerate() {
+        for (rank, piece, white) in [
+            (0, *back, true),
+            (1, Piece::Pawn, true),
+            (6, Piece::Pawn, false),
+            (7, *back, false),
+        ] {
+            let obj = add_piece(&mut obj_pool, piece);
+            let material = if white { ivory() } else { ebony() };
+            obj_pool.set_material(obj, material);
+
+            // White plays up the board, away from the camera, and black down
+            // it, so each side's knights face the other side.
+            let facing = if white { 0.0 } else { PI };
+            let transform = Matrix::translation(file as f64 - 3.5, 0.0, rank as f64 - 3.5)
+                * Matrix::rotation_y(facing);
+            obj_pool.set_transform(obj, transform);
+        }
+    }
+
+    let (h, w) = (768, 1024);
+    let mut camera = Camera::new(w, h, 45.0 * PI / 180.0);
+    let from = Tuple::point(5.0, 7.5, -9.0);
+    let to = Tuple::point(0.0, 0.0, -0.5);
+    let up = Tuple::vector(0.0, 1.0, 0.0);
+    camera.set_transform(Matrix::view_transform(from, to, up));
+
+    (obj_pool, camera, lights(seed))
+}
+
+/// Adds one piece, standing on the origin in a square one unit across and
+/// facing +z, as a group marked as an instance.
+pub fn add_piece(obj_pool: &mut ObjPool, piece: Piece) -> Obj {
+    let mut parts = vec![
+        disc(obj_pool, 0.0, 0.06, 0.36),
+        taper(obj_pool, 0.06, 0.14, 0.34, 0.26),
+    ];
+    match piece {
+        Piece::Pawn => {
+            parts.push(taper(obj_pool, 0.14, 0.5, 0.2, 0.09));
+            parts.push(disc(obj_pool, 0.48, 0.53, 0.16));
+            parts.push(ball(obj_pool, 0.65, 0.14));
+        }
+        Piece::Rook => {
+            parts.push(taper(obj_pool, 0.14, 0.62, 0.22, 0.17));
+            parts.push(disc(obj_pool, 0.6, 0.65, 0.23));
+            parts.push(turret(obj_pool));
+        }
+        Piece::Knight => {
+            parts.push(taper(obj_pool, 0.14, 0.42, 0.22, 0.16));
+            parts.push(disc(obj_pool, 0.4, 0.45, 0.2));
+            parts.push(horse_head(obj_pool));
+        }
+        Piece::Bishop => {
+            parts.push(taper(obj_pool, 0.14, 0.72, 0.22, 0.08));
+            parts.push(disc(obj_pool, 0.7, 0.75, 0.16));
+            parts.push(mitre(obj_pool));
+            parts.push(ball(obj_pool, 1.1, 0.045));
+        }
+        Piece::Queen => {
+            parts.push(taper(obj_pool, 0.14, 0.88, 0.24, 0.09));
+            parts.push(disc(obj_pool, 0.84, 0.9, 0.18));
+            parts.push(crown(obj_pool));
+            parts.push(ball(obj_pool, 1.14, 0.07));
+        }
+        Piece::King => {
+            parts.push(taper(obj_pool, 0.14, 0.95, 0.25, 0.1));
+            parts.push(disc(obj_pool, 0.9, 0.96, 0.19));
+            parts.push(taper(obj_pool, 0.96, 1.15, 0.1, 0.2));
+            parts.push(disc(obj_pool, 1.15, 1.19, 0.21));
+            parts.push(block(obj_pool, (0.0, 1.32, 0.0), (0.04, 0.13, 0.04)));
+            parts.push(block(obj_pool, (0.0, 1.35, 0.0), (0.1, 0.035, 0.04)));
+        }
+    }
+
+    let group = obj_pool.add_group(Matrix::identity());
+    obj_pool.add_children(group, &parts);
+    obj_pool.mark_instance(group);
+    group
+}
+
+/// A flat disc from one height to another, like the rings a lathe leaves.
+fn disc(obj_pool: &mut ObjPool, bottom: f64, top: f64, radius: f64) -> Obj {
+    taper(obj_pool, bottom, top, radius, radius)
+}
+
+/// A solid of revolution narrowing or widening straight from one radius at
+/// its bottom to another at its top.
+fn taper(obj_pool: &mut ObjPool, bottom: f64, top: f64, r_bottom: f64, r_top: f64) -> Obj {
+    let height = top - bottom;
+    if r_bottom == r_top {
+        let shape = Shape::Cylinder {
+            y_min: 0.0,
+            y_max: 1.0,
+            closed: true,
+        };
+        let transform =
+            Matrix::translation(0.0, bottom, 0.0) * Matrix::scaling(r_bottom, height, r_bottom);
+        return obj_pool.add_shape(shape, transform, None);
+    }
+
+    // A cone's radius is its height, so the part of it between the two radii
+    // is stretched along y to fit between the two heights.
+    let shape = Shape::Cone {
+        y_min: r_bottom.min(r_top),
+        y_max: r_bottom.max(r_top),
+        closed: true,
+    };
+    let stretch = height / (r_top - r_bottom);
+    let transform = Matrix::translation(0.0, bottom - r_bottom * stretch, 0.0)
+        * Matrix::scaling(1.0, stretch, 1.0);
+    obj_pool.add_shape(shape, transform, None)
+}
+
+fn ball(obj_pool: &mut ObjPool, height: f64, radius: f64) -> Obj {
+    let transform = Matrix::translation(0.0, height, 0.0) * Matrix::scaling(radius, radius, radius);
+    obj_pool.add_shape(Shape::Sphere, transform, None)
+}
+
+/// A box with its center and half its size along each axis.
+fn block(obj_pool: &mut ObjPool, center: (f64, f64, f64), half: (f64, f64, f64)) -> Obj {
+    let transform =
+        Matrix::translation(center.0, center.1, center.2) * Matrix::scaling(half.0, half.1, half.2);
+    obj_pool.add_shape(Shape::Cube, transform, None)
+}
+
+/// The rook's top: a drum hollowed out from above, with slots cut across it
+/// to leave four merlons.
+fn turret(obj_pool: &mut ObjPool) -> Obj {
+    let drum = disc(obj_pool, 0.65, 0.85, 0.25);
+    let hollow = disc(obj_pool, 0.75, 0.9, 0.17);
+    let slot_x = block(obj_pool, (0.0, 0.85, 0.0), (0.3, 0.07, 0.04));
+    let slot_z = block(obj_pool, (0.0, 0.85, 0.0), (0.04, 0.07, 0.3));
+    obj_pool.add_csg_many(
+        CsgOp::Difference,
+        Matrix::identity(),
+        &[drum, hollow, slot_x, slot_z],
+    )
+}
+
+/// The bishop's head: an egg with a slanted notch cut into it.
+fn mitre(obj_pool: &mut ObjPool) -> Obj {
+    let egg = {
+        let transform = Matrix::translation(0.0, 0.88, 0.0) * Matrix::scaling(0.12, 0.18, 0.12);
+        obj_pool.add_shape(Shape::Sphere, transform, None)
+    };
+    let notch = {
+        let transform = Matrix::translation(0.06, 0.96, 0.0)
+            * Matrix::rotation_z(-PI / 4.0)
+            * Matrix::scaling(0.02, 0.12, 0.2);
+        obj_pool.add_shape(Shape::Cube, transform, None)
+    };
+    obj_pool.add_csg(CsgOp::Difference, Matrix::identity(), egg, notch)
+}
+
+/// The queen's crown: a cup flaring upward, with a ring of balls scalloping
+/// its rim.
+fn crown(obj_pool: &mut ObjPool) -> Obj {
+    let cup = taper(obj_pool, 0.9, 1.08, 0.1, 0.19);
+    let mut scallops = vec![cup];
+    for i in 0..8 {
+        let angle = 2.0 * PI * i as f64 / 8.0;
+        let transform = Matrix::translation(0.2 * angle.cos(), 1.1, 0.2 * angle.sin())
+            * Matrix::scaling(0.06, 0.06, 0.06);
+        scallops.push(obj_pool.add_shape(Shape::Sphere, transform, None));
+    }
+    obj_pool.add_csg_many(CsgOp::Difference, Matrix::identity(), &scallops)
+}
+
+/// The knight's head: a neck leaning forward, a muzzle, and ears, with the
+/// underside of the jaw carved away by a cylinder.
+fn horse_head(obj_pool: &mut ObjPool) -> Obj {
+    let neck = {
+        let transform = Matrix::translation(0.0, 0.68, -0.02)
+            * Matrix::rotation_x(0.3)
+            * Matrix::scaling(0.11, 0.26, 0.15);
+        obj_pool.add_shape(Shape::Cube, transform, None)
+    };
+    let muzzle = {
+        let transform = Matrix::translation(0.0, 0.82, 0.14)
+            * Matrix::rotation_x(-0.5)
+            * Matrix::scaling(0.09, 0.08, 0.17);
+        obj_pool.add_shape(Shape::Cube, transform, None)
+    };
+    let ears = block(obj_pool, (0.0, 0.97, -0.04), (0.07, 0.05, 0.03));
+    let head = obj_pool.add_csg_many(CsgOp::Union, Matrix::identity(), &[neck, muzzle, ears]);
+
+    let jaw = {
+        let shape = Shape::Cylinder {
+            y_min: -1.0,
+            y_max: 1.0,
+            closed: true,
+        };
+        let transform = Matrix::translation(0.0, 0.6, 0.26)
+            * Matrix::rotation_z(PI / 2.0)
+            * Matrix::scaling(0.14, 0.2, 0.14);
+        obj_pool.add_shape(shape, transform, None)
+    };
+    obj_pool.add_csg(CsgOp::Difference, Matrix::identity(), head, jaw)
+}
+
+/// The board, its frame, and the table it stands on.
+fn add_board(obj_pool: &mut ObjPool) {
+    let light_square = Color::new(0.85, 0.78, 0.62);
+    let dark_square = Color::new(0.3, 0.18, 0.1);
+
+    {
+        let transform = Matrix::translation(0.0, -BOARD_THICKNESS / 2.0, 0.0)
+            * Matrix::scaling(4.0, BOARD_THICKNESS / 2.0, 4.0);
+        let mut material = Material::new();
+        // Checkers are a unit across in pattern space, so scaled back up to a
+        // square's size from the cube's, and shifted so a dark square is at
+        // the a1 corner.
+        material.pattern = PatternBuilder::new()
+            .checkers(dark_square, light_square)
+            .transform(Matrix::scaling(0.25, 1.0, 0.25) * Matrix::translation(0.0, 0.5, 0.0))
+            .build();
+        material.specular = 0.6;
+        material.shininess = 150.0;
+        material.reflective = 0.15;
+        obj_pool.add_shape(Shape::Cube, transform, material);
+    }
+
+    {
+        let half = 4.0 + BOARD_BORDER;
+        let outer = {
+            let transform = Matrix::translation(0.0, -BOARD_THICKNESS / 2.0 - 0.01, 0.0)
+                * Matrix::scaling(half, BOARD_THICKNESS / 2.0, half);
+            obj_pool.add_shape(Shape::Cube, transform, None)
+        };
+        let inner = {
+            let transform = Matrix::scaling(4.0, BOARD_THICKNESS, 4.0);
+            obj_pool.add_shape(Shape::Cube, transform, None)
+        };
+        let frame = obj_pool.add_csg(CsgOp::Difference, Matrix::identity(), outer, inner);
+        let mut material = Material::new();
+        material.color = Color::new(0.22, 0.12, 0.07);
+        material.specular = 0.5;
+        material.shininess = 80.0;
+        material.reflective = 0.05;
+        obj_pool.set_material(frame, material);
+    }
+
+    {
+        let transform = Matrix::translation(0.0, -BOARD_THICKNESS, 0.0);
+        let mut material = Material::new();
+        material.color = Color::new(0.45, 0.47, 0.5);
+        material.specular = 0.1;
+        obj_pool.add_shape(Shape::Plane, transform, material);
+    }
+}
+
+fn ivory() -> Material {
+    let mut material = Material::new();
+    material.color = Color::new(0.93, 0.89, 0.8);
+    material.ambient = 0.12;
+    material.diffuse = 0.8;
+    material.specular = 0.6;
+    material.shininess = 120.0;
+    material.reflective = 0.08;
+    material
+}
+
+fn ebony() -> Material {
+    let mut material = Material::new();
+    material.color = Color::new(0.06, 0.05, 0.05);
+    material.ambient = 0.05;
+    material.diffuse = 0.6;
+    material.specular = 0.9;
+    material.shininess = 300.0;
+    material.reflective = 0.2;
+    material
+}
+
+/// A soft key light up and to the left of the board, as points spread over a
+/// disc facing the board's center, and a dim fill from the other side.
+fn lights(seed: u64) -> Vec<Light> {
+    let center = Tuple::point(-6.0, 10.0, -5.0);
+    let axis = (Tuple::point(0.0, 0.0, 0.0) - center).normalize();
+    let (tangent, bitangent) = axis.tangents();
+    let key = 0.85 / LIGHT_SAMPLES as f64;
+
+    let mut rng = Pcg32::seed_from_u64(seed);
+    let mut lights: Vec<Light> = (0..LIGHT_SAMPLES)
+        .map(|_| {
+            let (x, y) = uniform_disk(&mut rng);
+            let position = center + (tangent * x + bitangent * y) * LIGHT_RADIUS;
+            Light::new_point(position, Color::new(key, key, 0.96 * key))
+        })
+        .collect();
+
+    let fill = 0.25;
+    lights.push(Light::new_directional(
+        Tuple::vector(1.0, -1.0, -0.5),
+        Color::new(0.9 * fill, 0.95 * fill, fill),
+    ));
+    lights
+}
@@ -18,6 +18,60 @@ impl Color {
         let blue = blue as f64;
         Color::new(red / 255.0, green / 255.0, blue / 255.0)
     }
+
+    /// Color of light glowing at a temperature in kelvin, from candle flames
+    /// around 1900 K through daylight around 6500 K to blue sky above 10000 K.
+    /// The brightest channel is 1.0. Uses Tanner Helland's curve fit, which is
+    /// close enough for lighting.
+    pub fn blackbody(kelvin: f64) -> Self {
+        let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+        let red = if t <= 66.0 {
+            255.0
+        } else {
+            329.698727446 * (t - 60.0).powf(-0.1332047592)
+        };
+        let green = if t <= 66.0 {
+            99.4708025861 * t.ln() - 161.1195681661
+        } else {
+            288.1221695283 * (t - 60.0).powf(-0.0755148492)
+        };
+        let blue = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.5177312231 * (t - 10.0).ln() - 305.0447927307
+        };
+        let channel = |v: f64| v.clamp(0.0, 255.0) / 255.0;
+        Color::new(channel(red), channel(green), channel(blue))
+    }
+
+    /// Relative luminance using Rec. 709 primaries.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
+
+    /// Whether the color could be light arriving somewhere: finite, and not
+    /// negative in any channel. Colors as a whole can be anything, such as the
+    /// difference of two colors or a filter's negative lobes.
+    pub fn is_radiance(&self) -> bool {
+        [self.red, self.green, self.blue]
+            .iter()
+            .all(|c| c.is_finite() && *c >= 0.0)
+    }
+
+    /// Passes the color through, panicking in debug builds unless it
+    /// `is_radiance`. Shading checks light where it's computed, naming where in
+    /// `origin`, so that a NaN from a degenerate normal or a negative lighting
+    /// term is caught where it starts, rather than showing up later as black
+    /// or speckled pixels. Release builds skip the check.
+    #[track_caller]
+    pub fn checked(self, origin: &str) -> Self {
+        if cfg!(debug_assertions) && !self.is_radiance() {
+            panic!("{} produced invalid radiance {:?}", origin, self);
+        }
+        self
+    }
 }
 
 impl Add for Color {
@@ -0,0 +1,157 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::util::*;
+use std::error::Error;
+
+/// Width and height of the windows SSIM is computed over.
+const SSIM_WINDOW: usize = 8;
+
+/// Distance between neighboring SSIM windows.
+const SSIM_STRIDE: usize = 4;
+
+/// Similarity metrics between two images.
+#[derive(Copy, Clone, Debug)]
+pub struct Comparison {
+    /// Root mean squared error of the display channel values.
+    pub rmse: f64,
+
+    /// Peak signal to noise ratio in decibels. Infinite for identical images.
+    pub psnr: f64,
+
+    /// Mean structural similarity of the luminance, 1.0 for identical images.
+    pub ssim: f64,
+
+    /// Largest difference in any channel of any pixel, for checking a render
+    /// matches a reference pixel by pixel within a tolerance.
+    pub max_error: f64,
+}
+
+/// Compares two images of the same size. Colors are clamped to their
+/// displayable range first, so differences in blown out highlights are ignored.
+pub fn compare(a: &Canvas, b: &Canvas) -> Result<Comparison, Box<dyn Error>> {
+    check_size(a, b)?;
+
+    let mut squared_error = 0.0;
+    let mut max_error: f64 = 0.0;
+    for y in 0..a.height {
+        for x in 0..a.width {
+            let ca = display(a.pixel_at(x, y));
+            let cb = display(b.pixel_at(x, y));
+            let d = ca - cb;
+            squared_error += d.red * d.red + d.green * d.green + d.blue * d.blue;
+            max_error = max_error
+                .max(d.red.abs())
+                .max(d.green.abs())
+                .max(d.blue.abs());
+        }
+    }
+    let samples = (3 * a.width * a.height).max(1) as f64;
+    let rmse = (squared_error / samples).sqrt();
+    let psnr = if rmse > 0.0 {
+        -20.0 * rmse.log10()
+    } else {
+        f64::INFINITY
+    };
+
+    Ok(Comparison {
+        rmse,
+        psnr,
+        ssim: ssim(a, b),
+        max_error,
+    })
+}
+
+/// Renders the per-pixel error between two images as a heat map running from
+/// black (identical) through red to yellow (largest difference).
+pub fn difference_heatmap(a: &Canvas, b: &Canvas) -> Result<Canvas, Box<dyn Error>> {
+    check_size(a, b)?;
+
+    let mut errors = Vec::with_capacity(a.width * a.height);
+    for y in 0..a.height {
+        for x in 0..a.width {
+            let d = display(a.pixel_at(x, y)) - display(b.pixel_at(x, y));
+            errors.push((d.red * d.red + d.green * d.green + d.blue * d.blue).sqrt());
+        }
+    }
+    let max_error = errors.iter().cloned().fold(0.0, f64::max);
+
+    let mut heatmap = Canvas::new(a.width, a.height);
+    for (i, error) in errors.into_iter().enumerate() {
+        let t = if max_error > 0.0 { error / max_error } else { 0.0 };
+        let color = if t < 0.5 {
+            Color::new(2.0 * t, 0.0, 0.0)
+        } else {
+            Color::new(1.0, 2.0 * t - 1.0, 0.0)
+        };
+        heatmap.write_pixel(i % a.width, i / a.width, color);
+    }
+    Ok(heatmap)
+}
+
+fn check_size(a: &Canvas, b: &Canvas) -> Result<(), Box<dyn Error>> {
+    if a.width != b.width || a.height != b.height {
+        return Err(format!(
+            "cannot compare a {}x{} image with a {}x{} image",
+            a.width, a.height, b.width, b.height
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn display(color: Color) -> Color {
+    Color::new(
+        clamp(color.red, 0.0, 1.0),
+        clamp(color.green, 0.0, 1.0),
+        clamp(color.blue, 0.0, 1.0),
+    )
+}
+
+/// Mean SSIM of the luminance over overlapping windows.
+fn ssim(a: &Canvas, b: &Canvas) -> f64 {
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+
+    let window_w = SSIM_WINDOW.min(a.width);
+    let window_h = SSIM_WINDOW.min(a.height);
+    if window_w == 0 || window_h == 0 {
+        return 1.0;
+    }
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    let mut y0 = 0;
+    while y0 + window_h <= a.height {
+        let mut x0 = 0;
+        while x0 + window_w <= a.width {
+            let n = (window_w * window_h) as f64;
+            let (mut sum_a, mut sum_b) = (0.0, 0.0);
+            let (mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0);
+            for y in y0..y0 + window_h {
+                for x in x0..x0 + window_w {
+                    let la = display(a.pixel_at(x, y)).luminance();
+                    let lb = display(b.pixel_at(x, y)).luminance();
+                    sum_a += la;
+                    sum_b += lb;
+                    sum_aa += la * la;
+                    sum_bb += lb * lb;
+                    sum_ab += la * lb;
+                }
+            }
+            let mean_a = sum_a / n;
+            let mean_b = sum_b / n;
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+
+            x0 += SSIM_STRIDE;
+        }
+        y0 += SSIM_STRIDE;
+    }
+
+    total / (windows as f64)
+}
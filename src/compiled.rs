@@ -0,0 +1,267 @@
+use crate::bounds::Bounds;
+use crate::light::{Light, LightKind};
+use crate::object::*;
+use crate::ray::Ray;
+use crate::tuple::Tuple;
+use crate::util::EPSILON;
+
+/// Most objects in a leaf of the bounding volume hierarchy.
+const LEAF_SIZE: usize = 4;
+
+/// Deepest a bounding volume hierarchy of halved nodes can get before its
+/// objects outnumber anything addressable.
+const MAX_DEPTH: usize = 64;
+
+/// A scene flattened into a form fixed for rendering, separate from the object
+/// pool it's compiled from, which stays free to edit.
+///
+/// The objects rays can hit are listed with the transforms down to them
+/// composed, as `ObjPool::flatten` gives them, and sorted into a bounding
+/// volume hierarchy, so that rays test only the objects whose bounds they
+/// pass through, instead of every object in every group. Each object's
+/// material is resolved up front, and the lights are listed by kind.
+///
+/// The pool is still needed to intersect CSG objects, which are kept whole,
+/// and for everything about a hit besides where it is. Compile again after
+/// objects or lights are added or moved, as `World::scene_changed` does.
+pub struct CompiledScene {
+    /// The bounded objects in the order the hierarchy's leaves list them,
+    /// then the ones going on forever, which every ray is tested with.
+    objects: Vec<FlatObject>,
+    bounded: usize,
+    nodes: Vec<Node>,
+
+    /// Where each object comes in the flattened objects' original order, by
+    /// id, to sort hits at the same distance as the object pool would. Shapes
+    /// in CSG objects come where their CSG object does.
+    ranks: Vec<usize>,
+
+    /// Where each object's material is kept, by id.
+    materials: Vec<MaterialSlot>,
+
+    /// Indices of the lights of each kind: direct, ambient, and dome lights.
+    lights: [Vec<usize>; 3],
+}
+
+/// A node of the bounding volume hierarchy.
+struct Node {
+    bounds: Bounds,
+
+    /// For a leaf, where its objects start; otherwise its second child, the
+    /// first coming right after the node itself.
+    index: usize,
+
+    /// Objects in a leaf, or zero for other nodes.
+    count: usize,
+}
+
+impl CompiledScene {
+    pub fn new(obj_pool: &ObjPool, lights: &[Light]) -> Self {
+        let flat = obj_pool.flatten();
+        let mut ranks = vec![None; obj_pool.len()];
+        for (rank, object) in flat.iter().enumerate() {
+            ranks[object.obj].get_or_insert(rank);
+        }
+        let ranks = (0..obj_pool.len())
+            .map(|obj| {
+                let mut node = Some(obj);
+                while let Some(o) = node {
+                    if let Some(rank) = ranks[o] {
+                        return rank;
+                    }
+                    node = obj_pool.parent(o);
+                }
+                usize::MAX
+            })
+            .collect();
+
+        let (mut objects, unbounded): (Vec<_>, Vec<_>) = flat
+            .into_iter()
+            .partition(|object| object.bounds.is_some_and(|b| b.is_finite()));
+
+        // Pad the bounds, so that rounding in the composed transforms can't
+        // leave a grazing ray outside a box holding what it hits.
+        let pad = Tuple::vector(EPSILON, EPSILON, EPSILON);
+        for object in objects.iter_mut() {
+            object.bounds = object
+                .bounds
+                .map(|bounds| Bounds::new(bounds.min - pad, bounds.max + pad));
+        }
+
+        let mut nodes = Vec::new();
+        if !objects.is_empty() {
+            build(&mut objects, 0, 0, &mut nodes);
+        }
+        let bounded = objects.len();
+        objects.extend(unbounded);
+
+        let materials = (0..obj_pool.len())
+            .map(|obj| obj_pool.material_slot(obj))
+            .collect();
+
+        let mut lists = [Vec::new(), Vec::new(), Vec::new()];
+        for (index, light) in lights.iter().enumerate() {
+            lists[kind_index(light.kind())].push(index);
+        }
+
+        CompiledScene {
+            objects,
+            bounded,
+            nodes,
+            ranks,
+            materials,
+            lights: lists,
+        }
+    }
+
+    /// Number of objects rays can hit.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Fills a buffer with a ray's intersections sorted by distance, the same
+    /// as `ObjPool::intersect_into` gives them.
+    pub fn intersect_into(&self, obj_pool: &ObjPool, ray: &Ray, xs: &mut Vec<Intersection>) {
+        xs.clear();
+        self.find(ray, f64::NEG_INFINITY, f64::INFINITY, |object| {
+            intersect(obj_pool, object, ray, xs);
+            false
+        });
+        xs.sort_by(|x1, x2| {
+            let order = x1.t.partial_cmp(&x2.t).unwrap();
+            order.then(self.ranks[x1.obj].cmp(&self.ranks[x2.obj]))
+        });
+    }
+
+    /// Whether a ray hits anything accepted by `blocks` at a distance in
+    /// (0, max_t), like `ObjPool::any_hit`.
+    pub fn any_hit<F>(
+        &self,
+        obj_pool: &ObjPool,
+        ray: &Ray,
+        max_t: f64,
+        xs: &mut Vec<Intersection>,
+        mut blocks: F,
+    ) -> bool
+    where
+        F: FnMut(&Intersection) -> bool,
+    {
+        self.find(ray, 0.0, max_t, |object| {
+            xs.clear();
+            intersect(obj_pool, object, ray, xs);
+            xs.iter().any(|x| x.t > 0.0 && x.t < max_t && blocks(x))
+        })
+    }
+
+    /// Where the material an object is shaded with is kept.
+    pub fn material(&self, obj: Obj) -> MaterialSlot {
+        self.materials[obj]
+    }
+
+    /// Indices of the lights of a kind.
+    pub fn lights(&self, kind: LightKind) -> &[usize] {
+        &self.lights[kind_index(kind)]
+    }
+
+    /// Shows `found` each object whose bounds a ray passes through between
+    /// two distances along it, until `found` returns true. Returns whether it
+    /// did.
+    fn find<F>(&self, ray: &Ray, min_t: f64, max_t: f64, mut found: F) -> bool
+    where
+        F: FnMut(&FlatObject) -> bool,
+    {
+        if self.objects[self.bounded..].iter().any(&mut found) {
+            return true;
+        }
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let mut stack = [0; MAX_DEPTH];
+        let mut len = 1;
+        while len > 0 {
+            len -= 1;
+            let index = stack[len];
+            let node = &self.nodes[index];
+            match node.bounds.intersect(ray) {
+                Some((t0, t1)) if t0 < max_t && t1 > min_t => {}
+                _ => continue,
+            }
+            if node.count > 0 {
+                let leaf = &self.objects[node.index..node.index + node.count];
+                if leaf.iter().any(&mut found) {
+                    return true;
+                }
+            } else {
+                stack[len] = node.index;
+                stack[len + 1] = index + 1;
+                len += 2;
+            }
+        }
+        false
+    }
+}
+
+/// Appends a ray's intersections with a flattened object, unsorted.
+fn intersect(obj_pool: &ObjPool, object: &FlatObject, ray: &Ray, xs: &mut Vec<Intersection>) {
+    let ray = ray.transform(object.transform_inverse);
+    match &object.shape {
+        Some(shape) => shape.intersects(ray, object.obj, xs),
+        None => obj_pool.intersect_in_parent(object.obj, &ray, xs),
+    }
+}
+
+/// Adds a node for some objects, which must have finite bounds, splitting them
+/// in half along the axis their centers spread furthest on until few enough
+/// are left for a leaf. `start` is where the objects start in the scene's.
+/// Returns the node's index.
+fn build(objects: &mut [FlatObject], start: usize, depth: usize, nodes: &mut Vec<Node>) -> usize {
+    let bounds = objects
+        .iter()
+        .filter_map(|object| object.bounds)
+        .fold(Bounds::empty(), Bounds::merge);
+    let index = nodes.len();
+    nodes.push(Node {
+        bounds,
+        index: start,
+        count: objects.len(),
+    });
+    // Each level takes one more place on the stack searching it.
+    if objects.len() <= LEAF_SIZE || depth + 2 >= MAX_DEPTH {
+        return index;
+    }
+
+    let centers = Bounds::from_points(objects.iter().filter_map(|o| o.bounds.map(|b| b.center())));
+    let size = centers.size();
+    let axis = if size.x() >= size.y() && size.x() >= size.z() {
+        0
+    } else if size.y() >= size.z() {
+        1
+    } else {
+        2
+    };
+    let center = |object: &FlatObject| {
+        let center = object.bounds.unwrap().center();
+        [center.x(), center.y(), center.z()][axis]
+    };
+    let middle = objects.len() / 2;
+    objects.select_nth_unstable_by(middle, |a, b| center(a).partial_cmp(&center(b)).unwrap());
+
+    let (first, second) = objects.split_at_mut(middle);
+    build(first, start, depth + 1, nodes);
+    nodes[index].index = build(second, start + middle, depth + 1, nodes);
+    nodes[index].count = 0;
+    index
+}
+
+fn kind_index(kind: LightKind) -> usize {
+    match kind {
+        LightKind::Direct => 0,
+        LightKind::Ambient => 1,
+        LightKind::Dome => 2,
+    }
+}
@@ -0,0 +1,143 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::filter::Filter;
+
+use std::error::Error;
+use std::io::{Read, Write};
+
+/// Accumulates filtered samples over a rectangular region of an image.
+///
+/// Each sample is splatted onto every pixel within the filter's radius, so a
+/// pixel's final color is the weighted average of all nearby samples.
+pub struct Film {
+    /// Column of the film's first pixel within the image.
+    x: usize,
+
+    /// Row of the film's first pixel within the image.
+    y: usize,
+
+    width: usize,
+    height: usize,
+    colors: Vec<Color>,
+    weights: Vec<f64>,
+}
+
+impl Film {
+    /// Film covering a whole image.
+    pub fn new(width: usize, height: usize) -> Self {
+        Film::region(0, 0, width, height)
+    }
+
+    /// Film covering part of an image, starting at pixel (x, y).
+    pub fn region(x: usize, y: usize, width: usize, height: usize) -> Self {
+        let length = width * height;
+        Film {
+            x,
+            y,
+            width,
+            height,
+            colors: vec![Color::new(0.0, 0.0, 0.0); length],
+            weights: vec![0.0; length],
+        }
+    }
+
+    /// Column and row of the film's first pixel within the image.
+    pub fn origin(&self) -> (usize, usize) {
+        (self.x, self.y)
+    }
+
+    /// Width and height of the film in pixels.
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Adds a sample taken at image position (sx, sy) to the pixels the filter
+    /// covers.
+    pub fn splat(&mut self, sx: f64, sy: f64, color: Color, filter: Filter) {
+        let radius = filter.radius();
+        let x_min = ((sx - radius).floor().max(0.0) as usize).max(self.x);
+        let y_min = ((sy - radius).floor().max(0.0) as usize).max(self.y);
+        let x_max = ((sx + radius).ceil().max(0.0) as usize).min(self.x + self.width);
+        let y_max = ((sy + radius).ceil().max(0.0) as usize).min(self.y + self.height);
+
+        for py in y_min..y_max {
+            for px in x_min..x_max {
+                let weight = filter.weight(px as f64 + 0.5 - sx, py as f64 + 0.5 - sy);
+                if weight != 0.0 {
+                    let i = self.index(px, py);
+                    self.colors[i] = self.colors[i] + color * weight;
+                    self.weights[i] += weight;
+                }
+            }
+        }
+    }
+
+    /// Adds the samples accumulated by another film, such as a tile's.
+    pub fn merge(&mut self, other: &Film) {
+        for y in other.y..other.y + other.height {
+            for x in other.x..other.x + other.width {
+                let from = other.index(x, y);
+                let to = self.index(x, y);
+                self.colors[to] = self.colors[to] + other.colors[from];
+                self.weights[to] += other.weights[from];
+            }
+        }
+    }
+
+    /// Resolves the weighted averages into a canvas.
+    pub fn to_canvas(&self) -> Canvas {
+        let mut canvas = Canvas::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = y * self.width + x;
+                if self.weights[i] != 0.0 {
+                    canvas.write_pixel(x, y, self.colors[i] * (1.0 / self.weights[i]));
+                }
+            }
+        }
+        canvas
+    }
+
+    /// Writes the film's region and raw sums in a little-endian binary form
+    /// that `read_from` loads back exactly.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        for n in [self.x, self.y, self.width, self.height] {
+            writer.write_all(&(n as u64).to_le_bytes())?;
+        }
+        for (color, weight) in self.colors.iter().zip(&self.weights) {
+            for v in [color.red, color.green, color.blue, *weight] {
+                writer.write_all(&v.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Film, Box<dyn Error>> {
+        let mut bytes = [0; 8];
+        let mut next = || -> Result<[u8; 8], Box<dyn Error>> {
+            reader.read_exact(&mut bytes)?;
+            Ok(bytes)
+        };
+        let mut film = Film::region(0, 0, 0, 0);
+        film.x = u64::from_le_bytes(next()?) as usize;
+        film.y = u64::from_le_bytes(next()?) as usize;
+        film.width = u64::from_le_bytes(next()?) as usize;
+        film.height = u64::from_le_bytes(next()?) as usize;
+        let length = film
+            .width
+            .checked_mul(film.height)
+            .ok_or("film is too large")?;
+        for _ in 0..length {
+            let red = f64::from_le_bytes(next()?);
+            let green = f64::from_le_bytes(next()?);
+            let blue = f64::from_le_bytes(next()?);
+            film.colors.push(Color::new(red, green, blue));
+            film.weights.push(f64::from_le_bytes(next()?));
+        }
+        Ok(film)
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y - self.y) * self.width + (x - self.x)
+    }
+}
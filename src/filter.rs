@@ -0,0 +1,101 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Pixel reconstruction filters used to weight samples.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// Every sample within half a pixel counts equally.
+    Box,
+
+    /// Linear falloff reaching zero one pixel away.
+    Tent,
+
+    /// Truncated Gaussian with a radius of 1.5 pixels.
+    Gaussian,
+
+    /// Mitchell-Netravali cubic (B = C = 1/3) with a radius of 2 pixels.
+    Mitchell,
+}
+
+impl Filter {
+    /// Distance, in pixels, beyond which the filter is zero.
+    pub fn radius(&self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.0,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell => 2.0,
+        }
+    }
+
+    /// Weight of a sample offset by (dx, dy) pixels from the pixel center.
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x >= self.radius() {
+            return 0.0;
+        }
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => 1.0 - x,
+            Filter::Gaussian => {
+                const ALPHA: f64 = 2.0;
+                let r = self.radius();
+                (-ALPHA * x * x).exp() - (-ALPHA * r * r).exp()
+            }
+            Filter::Mitchell => mitchell(x),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(Filter::Box),
+            "tent" => Ok(Filter::Tent),
+            "gaussian" => Ok(Filter::Gaussian),
+            "mitchell" => Ok(Filter::Mitchell),
+            _ => Err(format!(
+                "unknown filter '{}', expected box, tent, gaussian, or mitchell",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Filter::Box => "box",
+            Filter::Tent => "tent",
+            Filter::Gaussian => "gaussian",
+            Filter::Mitchell => "mitchell",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Mitchell-Netravali cubic with B = C = 1/3, nonzero over (-2, 2).
+pub fn mitchell(x: f64) -> f64 {
+    const B: f64 = 1.0 / 3.0;
+    const C: f64 = 1.0 / 3.0;
+    let x = x.abs();
+    let y = if x < 1.0 {
+        (12.0 - 9.0 * B - 6.0 * C) * x * x * x
+            + (-18.0 + 12.0 * B + 6.0 * C) * x * x
+            + (6.0 - 2.0 * B)
+    } else if x < 2.0 {
+        (-B - 6.0 * C) * x * x * x
+            + (6.0 * B + 30.0 * C) * x * x
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C)
+    } else {
+        0.0
+    };
+    y / 6.0
+}
@@ -0,0 +1,135 @@
+use crate::algorithm::uniform_sphere;
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::light::Light;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::object::{Obj, ObjPool};
+use crate::pattern::PatternBuilder;
+use crate::rng::Pcg32;
+use crate::shape::Shape;
+use crate::tuple::Tuple;
+use crate::util::*;
+
+use rand::{Rng, SeedableRng};
+
+use std::f64::consts::PI;
+
+/// Leaves in the shrub's crown.
+const LEAVES: usize = 200;
+
+/// Radius of the shrub's roughly round crown.
+const CROWN_RADIUS: f64 = 1.1;
+
+/// Length of a leaf, from its stem to its tip.
+const LEAF_LENGTH: f64 = 0.5;
+
+/// Width of the soft edge of the leaves' outline, as a fraction of a card's
+/// width, over which they're partly cut away.
+const LEAF_EDGE: f64 = 0.15;
+
+/// Width and height of the mask cutting the leaves out of their cards.
+const MASK_SIZE: usize = 64;
+
+/// A shrub of leaves, each a flat card cut out by a mask with soft edges, on
+/// a lawn under the sun and a dimmer fill light from the sky, for trying out cutouts. Rays toward the middle of
+/// the crown pass the edges of many leaves stacked one behind another, which
+/// is where `CutoutMode::Stochastic` saves the most over blending. The seed
+/// places the leaves.
+pub fn scene(seed: u64) -> (ObjPool, Camera, Vec<Light>) {
+    let mut obj_pool = ObjPool::new();
+
+    let mut lawn = Material::new();
+    lawn.color = Color::new(0.35, 0.45, 0.2);
+    lawn.specular = 0.0;
+    obj_pool.add_shape(Shape::Plane, Matrix::identity(), lawn);
+
+    let mut bark = Material::new();
+    bark.color = Color::new(0.35, 0.25, 0.15);
+    bark.specular = 0.1;
+    let trunk = Shape::Cylinder {
+        y_min: 0.0,
+        y_max: 1.4,
+        closed: true,
+    };
+    obj_pool.add_shape(trunk, Matrix::scaling(0.07, 1.0, 0.07), bark);
+
+    let mask = leaf_mask();
+    let mut rng = Pcg32::seed_from_u64(seed);
+    for _ in 0..LEAVES {
+        // Spread the leaves through the crown, thinning toward its middle,
+        // where they would get no light.
+        let direction = uniform_sphere(&mut rng);
+        let distance = CROWN_RADIUS * rng.gen::<f64>().powf(0.3);
+        let position = Tuple::point(0.0, 1.7, 0.0) + direction * distance;
+        let transform = Matrix::translation(position.x(), position.y(), position.z())
+            * Matrix::rotation_y(rng.gen::<f64>() * 2.0 * PI)
+            * Matrix::rotation_x(rng.gen::<f64>() * PI - PI / 2.0)
+            * Matrix::rotation_z(rng.gen::<f64>() * PI - PI / 2.0)
+            * Matrix::scaling(LEAF_LENGTH, LEAF_LENGTH, 1.0)
+            * Matrix::translation(0.0, -0.5, 0.0);
+        let leaf = add_card(&mut obj_pool, transform);
+        obj_pool.set_material(leaf, leaf_material(mask.clone()));
+        obj_pool.mark_instance(leaf);
+    }
+
+    let (h, w) = (300, 400);
+    let mut camera = Camera::new(w, h, 50.0 * PI / 180.0);
+    let from = Tuple::point(0.0, 1.8, -4.5);
+    let to = Tuple::point(0.0, 1.3, 0.0);
+    let up = Tuple::vector(0.0, 1.0, 0.0);
+    camera.set_transform(Matrix::view_transform(from, to, up));
+
+    let sun = Light::new_point(Tuple::point(-8.0, 12.0, -6.0), Color::new(1.0, 0.97, 0.9));
+    let sky = Light::new_point(Tuple::point(6.0, 4.0, -10.0), Color::new(0.3, 0.35, 0.45));
+    (obj_pool, camera, vec![sun, sky])
+}
+
+/// A unit square card in the XY plane, from the origin to (1, 1, 0), made of
+/// two triangles, so that its object space points are its texture
+/// coordinates.
+fn add_card(obj_pool: &mut ObjPool, transform: Matrix<4>) -> Obj {
+    let corners = [
+        Tuple::point(0.0, 0.0, 0.0),
+        Tuple::point(1.0, 0.0, 0.0),
+        Tuple::point(1.0, 1.0, 0.0),
+        Tuple::point(0.0, 1.0, 0.0),
+    ];
+    let lower = Shape::triangle(corners[0], corners[1], corners[2]);
+    let upper = Shape::triangle(corners[0], corners[2], corners[3]);
+    let card = obj_pool.add_group(transform);
+    let lower = obj_pool.add_shape(lower, Matrix::identity(), None);
+    let upper = obj_pool.add_shape(upper, Matrix::identity(), None);
+    obj_pool.add_children(card, &[lower, upper]);
+    card
+}
+
+/// A leaf's outline on its card, white where the leaf is and black where
+/// it's cut away, running along the card from its stem on the left edge to
+/// its tip on the right, and fading out over a soft edge.
+fn leaf_mask() -> Canvas {
+    let mut mask = Canvas::new(MASK_SIZE, MASK_SIZE);
+    for y in 0..MASK_SIZE {
+        for x in 0..MASK_SIZE {
+            let u = (x as f64 + 0.5) / MASK_SIZE as f64;
+            let v = (y as f64 + 0.5) / MASK_SIZE as f64;
+            let half_width = 0.3 * (PI * u).sin();
+            let coverage = clamp((half_width - (v - 0.5).abs()) / LEAF_EDGE + 0.5, 0.0, 1.0);
+            mask.write_pixel(x, y, Color::new(coverage, coverage, coverage));
+        }
+    }
+    mask
+}
+
+fn leaf_material(mask: Canvas) -> Material {
+    let mut material = Material::new();
+    material.pattern = PatternBuilder::new()
+        .variation(Color::new(0.15, 0.4, 0.1), Color::new(0.35, 0.55, 0.12))
+        .build();
+    material.cutout = PatternBuilder::new().image(mask).build();
+    material.diffuse = 0.8;
+    material.specular = 0.2;
+    material.shininess = 20.0;
+    material
+}
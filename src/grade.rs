@@ -0,0 +1,196 @@
+use crate::color::Color;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+
+/// Largest `.cube` LUT size read, well past the 65 that grading tools export.
+const MAX_LUT_SIZE: usize = 256;
+
+/// A color grade applied to display referred colors, normally in [0, 1].
+pub enum Grade {
+    /// Raises the blacks by `lift`, bends the midtones by `gamma`, and scales
+    /// the whites by `gain`, per channel.
+    LiftGammaGain { lift: Color, gamma: Color, gain: Color },
+
+    /// A 3D lookup table, such as one loaded from a `.cube` file.
+    Lut(Lut),
+}
+
+impl Grade {
+    pub fn apply(&self, color: Color) -> Color {
+        match self {
+            Grade::LiftGammaGain { lift, gamma, gain } => {
+                let channel = |x: f64, lift: f64, gamma: f64, gain: f64| {
+                    let x = (x + lift * (1.0 - x)).max(0.0);
+                    gain * x.powf(1.0 / gamma)
+                };
+                Color::new(
+                    channel(color.red, lift.red, gamma.red, gain.red),
+                    channel(color.green, lift.green, gamma.green, gain.green),
+                    channel(color.blue, lift.blue, gamma.blue, gain.blue),
+                )
+            }
+            Grade::Lut(lut) => lut.apply(color),
+        }
+    }
+}
+
+/// Hashes every value of the grade, bit for bit, for keys such as the one a
+/// render farm checks frames against.
+impl Hash for Grade {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Grade::LiftGammaGain { lift, gamma, gain } => {
+                0_u8.hash(state);
+                for color in [lift, gamma, gain] {
+                    color_bits(*color).hash(state);
+                }
+            }
+            Grade::Lut(lut) => {
+                1_u8.hash(state);
+                lut.size.hash(state);
+                color_bits(lut.domain_min).hash(state);
+                color_bits(lut.domain_max).hash(state);
+                for color in &lut.table {
+                    color_bits(*color).hash(state);
+                }
+            }
+        }
+    }
+}
+
+fn color_bits(color: Color) -> [u64; 3] {
+    [
+        color.red.to_bits(),
+        color.green.to_bits(),
+        color.blue.to_bits(),
+    ]
+}
+
+/// A 3D color lookup table sampled with trilinear interpolation.
+pub struct Lut {
+    size: usize,
+    domain_min: Color,
+    domain_max: Color,
+
+    /// Output colors with red changing fastest, then green, then blue.
+    table: Vec<Color>,
+}
+
+impl Lut {
+    /// Reads a 3D LUT in the Adobe/Resolve `.cube` format.
+    pub fn from_cube<R: Read>(mut reader: R) -> Result<Lut, Box<dyn Error>> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+
+        let mut size = None;
+        let mut domain_min = Color::new(0.0, 0.0, 0.0);
+        let mut domain_max = Color::new(1.0, 1.0, 1.0);
+        let mut table = Vec::new();
+
+        let parse_color = |values: &[&str]| -> Result<Color, Box<dyn Error>> {
+            match values {
+                [r, g, b] => Ok(Color::new(r.parse()?, g.parse()?, b.parse()?)),
+                _ => Err(format!("expected three values, found '{}'", values.join(" ")).into()),
+            }
+        };
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.first() {
+                None => continue,
+                Some(&"TITLE") => continue,
+                Some(&"LUT_3D_SIZE") => size = Some(words.get(1).ok_or("missing size")?.parse()?),
+                Some(&"LUT_1D_SIZE") => return Err("1D LUTs are not supported".into()),
+                Some(&"DOMAIN_MIN") => domain_min = parse_color(&words[1..])?,
+                Some(&"DOMAIN_MAX") => domain_max = parse_color(&words[1..])?,
+                Some(_) => table.push(parse_color(&words)?),
+            }
+        }
+
+        let size: usize = size.ok_or("missing LUT_3D_SIZE")?;
+        let length = size
+            .checked_pow(3)
+            .filter(|_| (2..=MAX_LUT_SIZE).contains(&size))
+            .ok_or_else(|| format!("LUT_3D_SIZE {} is not between 2 and {}", size, MAX_LUT_SIZE))?;
+        if table.len() != length {
+            return Err(format!("expected {} LUT entries, found {}", length, table.len()).into());
+        }
+
+        Ok(Lut {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        let last = (self.size - 1) as f64;
+        let coordinate = |x: f64, min: f64, max: f64| {
+            let x = ((x - min) / (max - min)).clamp(0.0, 1.0) * last;
+            let i = (x.floor() as usize).min(self.size - 2);
+            (i, x - i as f64)
+        };
+        let (r, fr) = coordinate(color.red, self.domain_min.red, self.domain_max.red);
+        let (g, fg) = coordinate(color.green, self.domain_min.green, self.domain_max.green);
+        let (b, fb) = coordinate(color.blue, self.domain_min.blue, self.domain_max.blue);
+
+        let at = |r: usize, g: usize, b: usize| self.table[(b * self.size + g) * self.size + r];
+        let lerp = |a: Color, b: Color, t: f64| a + (b - a) * t;
+
+        let c00 = lerp(at(r, g, b), at(r + 1, g, b), fr);
+        let c10 = lerp(at(r, g + 1, b), at(r + 1, g + 1, b), fr);
+        let c01 = lerp(at(r, g, b + 1), at(r + 1, g, b + 1), fr);
+        let c11 = lerp(at(r, g + 1, b + 1), at(r + 1, g + 1, b + 1), fr);
+        lerp(lerp(c00, c10, fg), lerp(c01, c11, fg), fb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_color_near, COLOR_TOLERANCE};
+
+    /// An identity `.cube` LUT of a size.
+    fn identity_cube(size: usize) -> String {
+        let mut cube = format!("TITLE \"identity\"\nLUT_3D_SIZE {}\n", size);
+        let step = |i: usize| i as f64 / (size - 1) as f64;
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    cube += &format!("{} {} {}\n", step(r), step(g), step(b));
+                }
+            }
+        }
+        cube
+    }
+
+    #[test]
+    fn identity_lut_leaves_colors_unchanged() {
+        let lut = Lut::from_cube(identity_cube(5).as_bytes()).unwrap();
+        for color in [
+            Color::new(0.0, 0.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(0.2, 0.55, 0.9),
+        ] {
+            assert_color_near(lut.apply(color), color, COLOR_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_sizes() {
+        for cube in [
+            "LUT_3D_SIZE 3000000\n0 0 0\n",
+            "LUT_3D_SIZE 18446744073709551615\n0 0 0\n",
+            "LUT_3D_SIZE 257\n0 0 0\n",
+            "LUT_3D_SIZE 1\n0 0 0\n",
+            "0 0 0\n",
+        ] {
+            assert!(Lut::from_cube(cube.as_bytes()).is_err(), "{}", cube);
+        }
+        let short = identity_cube(3).replacen("1 1 1\n", "", 1);
+        assert!(Lut::from_cube(short.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,53 @@
+use crate::tuple::Tuple;
+
+/// Whether rendering checks its geometric invariants, which the `invariants`
+/// feature turns on. A broken invariant panics, naming what broke, so that a
+/// math regression is caught where it happens rather than showing up as a
+/// corrupted image. Without the feature the checks compile to nothing.
+pub const ENABLED: bool = cfg!(feature = "invariants");
+
+/// How far a w component, or a unit vector's length, may stray from what it
+/// should be through rounding.
+const TOLERANCE: f64 = 1e-6;
+
+/// Checks that a tuple is a point: finite, with w = 1.
+#[track_caller]
+pub fn check_point(point: Tuple, what: &str) {
+    if ENABLED && !(finite(point) && (point.w() - 1.0).abs() <= TOLERANCE) {
+        panic!("{} is not a point: {:?}", what, point);
+    }
+}
+
+/// Checks that a tuple is a vector: finite, with w = 0.
+#[track_caller]
+pub fn check_vector(vector: Tuple, what: &str) {
+    if ENABLED && !(finite(vector) && vector.w().abs() <= TOLERANCE) {
+        panic!("{} is not a vector: {:?}", what, vector);
+    }
+}
+
+/// Checks that a tuple is a vector of length 1.
+#[track_caller]
+pub fn check_unit(vector: Tuple, what: &str) {
+    check_vector(vector, what);
+    if ENABLED && (vector.magnitude() - 1.0).abs() > TOLERANCE {
+        panic!(
+            "{} is not normalized, its length is {}: {:?}",
+            what,
+            vector.magnitude(),
+            vector
+        );
+    }
+}
+
+/// Checks that a distance along a ray is a number, and not infinite.
+#[track_caller]
+pub fn check_t(t: f64, what: &str) {
+    if ENABLED && !t.is_finite() {
+        panic!("{} is at t = {}", what, t);
+    }
+}
+
+fn finite(tuple: Tuple) -> bool {
+    tuple.iter().all(|c| c.is_finite())
+}
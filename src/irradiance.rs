@@ -0,0 +1,80 @@
+use crate::color::Color;
+use crate::tuple::Tuple;
+
+use std::f64::consts::PI;
+
+/// Light arriving at a surface from everything around it, stored as the first
+/// nine spherical harmonics of the surroundings. Smooth enough for diffuse
+/// lighting, and cheap to look up for any normal.
+///
+/// See Ramamoorthi and Hanrahan, "An Efficient Representation for Irradiance
+/// Environment Maps" (2001).
+#[derive(Copy, Clone, Debug)]
+pub struct Irradiance {
+    coefficients: [Color; 9],
+}
+
+impl Irradiance {
+    /// Projects light arriving from the given directions, each with the solid
+    /// angle it covers, onto the harmonics.
+    pub fn from_radiance<I>(samples: I) -> Self
+    where
+        I: IntoIterator<Item = (Tuple, Color, f64)>,
+    {
+        let mut coefficients = [Color::new(0.0, 0.0, 0.0); 9];
+        for (direction, radiance, solid_angle) in samples {
+            let basis = basis(direction.normalize());
+            for (coefficient, y) in coefficients.iter_mut().zip(basis) {
+                *coefficient = *coefficient + radiance * (y * solid_angle);
+            }
+        }
+        Irradiance { coefficients }
+    }
+
+    /// Light reaching a surface facing along a normal, scaled so that
+    /// surroundings of one color give that color.
+    pub fn at(&self, normal: Tuple) -> Color {
+        // Convolving with the clamped cosine scales each band by its own
+        // factor, which for irradiance divided by pi is 1, 2/3, then 1/4.
+        const BAND: [f64; 9] = [
+            1.0,
+            2.0 / 3.0,
+            2.0 / 3.0,
+            2.0 / 3.0,
+            0.25,
+            0.25,
+            0.25,
+            0.25,
+            0.25,
+        ];
+        let basis = basis(normal.normalize());
+        let mut color = Color::new(0.0, 0.0, 0.0);
+        for ((coefficient, band), y) in self.coefficients.iter().zip(BAND).zip(basis) {
+            color = color + *coefficient * (band * y);
+        }
+
+        // Nine harmonics can't follow sharp changes, like the edge of a bright
+        // sun, and ring below zero opposite them.
+        Color::new(
+            color.red.max(0.0),
+            color.green.max(0.0),
+            color.blue.max(0.0),
+        )
+    }
+}
+
+/// The real spherical harmonics of the first three bands in a direction.
+fn basis(d: Tuple) -> [f64; 9] {
+    let (x, y, z) = (d.x(), d.y(), d.z());
+    [
+        0.5 * (1.0 / PI).sqrt(),
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3.0 * z * z - 1.0),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y),
+    ]
+}
@@ -0,0 +1,167 @@
+use crate::tuple::Tuple;
+use crate::voxel::VoxelGrid;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+
+/// The corners of a cube, numbered by bit: x is bit 0, y bit 1, and z bit 2.
+/// Each of the six tetrahedra runs from corner 0 to corner 7 through one edge
+/// and one face diagonal. Every cube in a grid is split the same way, so the
+/// tetrahedra of neighboring cubes share the diagonals of the faces between
+/// them, and the surfaces extracted from them meet without gaps.
+const TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 1, 3, 7],
+    [0, 1, 5, 7],
+    [0, 2, 3, 7],
+    [0, 2, 6, 7],
+    [0, 4, 5, 7],
+    [0, 4, 6, 7],
+];
+
+/// Triangles sharing vertices.
+#[derive(Clone, Debug, Default)]
+pub struct TriangleMesh {
+    pub vertices: Vec<Tuple>,
+
+    /// Indices into the vertices, counterclockwise seen from outside.
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl TriangleMesh {
+    /// Writes the mesh as a Wavefront OBJ file.
+    pub fn write_obj<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        for v in &self.vertices {
+            writeln!(writer, "v {} {} {}", v.x(), v.y(), v.z())?;
+        }
+        for [a, b, c] in &self.triangles {
+            // OBJ indices start at 1.
+            writeln!(writer, "f {} {} {}", a + 1, b + 1, c + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the mesh as a binary STL file, as 3D printing tools expect.
+    pub fn write_stl<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        writer.write_all(&[0; 80])?;
+        writer.write_all(&(self.triangles.len() as u32).to_le_bytes())?;
+        for [a, b, c] in &self.triangles {
+            let (a, b, c) = (self.vertices[*a], self.vertices[*b], self.vertices[*c]);
+            let normal = (b - a).cross(c - a).normalize();
+            for t in [normal, a, b, c] {
+                for v in [t.x(), t.y(), t.z()] {
+                    writer.write_all(&(v as f32).to_le_bytes())?;
+                }
+            }
+            // No attributes.
+            writer.write_all(&[0; 2])?;
+        }
+        Ok(())
+    }
+}
+
+/// Extracts the surface where a grid's values cross `level`, with the values
+/// below it inside. For a signed distance grid, a level of 0.0 gives the
+/// object's surface.
+///
+/// This is marching tetrahedra, a variant of marching cubes splitting each
+/// cube between samples into tetrahedra. Unlike with cubes, the surface through
+/// a tetrahedron is never ambiguous, so the mesh is closed, with no holes, as
+/// 3D printing needs. It has more triangles than marching cubes would make.
+pub fn extract_isosurface(grid: &VoxelGrid, level: f64) -> TriangleMesh {
+    let mut mesh = TriangleMesh::default();
+
+    // Vertices by the edge they were made on, so triangles sharing an edge
+    // share its vertex.
+    let mut edge_vertices: HashMap<(usize, usize), usize> = HashMap::new();
+
+    let [nx, ny, nz] = grid.dimensions;
+    for z in 0..nz.saturating_sub(1) {
+        for y in 0..ny.saturating_sub(1) {
+            for x in 0..nx.saturating_sub(1) {
+                let corner = |i: usize| {
+                    let (cx, cy, cz) = (x + (i & 1), y + ((i >> 1) & 1), z + ((i >> 2) & 1));
+                    let index = (cz * ny + cy) * nx + cx;
+                    (index, grid.center(cx, cy, cz), grid.values[index] - level)
+                };
+                let corners: Vec<_> = (0..8).map(corner).collect();
+                for tetrahedron in &TETRAHEDRA {
+                    let points = tetrahedron.map(|i| corners[i]);
+                    march_tetrahedron(&points, &mut mesh, &mut edge_vertices);
+                }
+            }
+        }
+    }
+    mesh
+}
+
+/// Adds the surface through one tetrahedron, given its corners' grid index,
+/// position, and value relative to the level.
+fn march_tetrahedron(
+    corners: &[(usize, Tuple, f64); 4],
+    mesh: &mut TriangleMesh,
+    edge_vertices: &mut HashMap<(usize, usize), usize>,
+) {
+    let (inside, outside): (Vec<usize>, Vec<usize>) = (0..4).partition(|&i| corners[i].2 < 0.0);
+    if inside.is_empty() || outside.is_empty() {
+        return;
+    }
+
+    let mut vertex = |a: usize, b: usize| {
+        let ((ia, pa, va), (ib, pb, vb)) = (corners[a], corners[b]);
+        let key = (ia.min(ib), ia.max(ib));
+        *edge_vertices.entry(key).or_insert_with(|| {
+            mesh.vertices.push(pa + (pb - pa) * (va / (va - vb)));
+            mesh.vertices.len() - 1
+        })
+    };
+    let triangles = match (inside.len(), outside.len()) {
+        (1, 3) => {
+            let i = inside[0];
+            vec![[
+                vertex(i, outside[0]),
+                vertex(i, outside[1]),
+                vertex(i, outside[2]),
+            ]]
+        }
+        (3, 1) => {
+            let o = outside[0];
+            vec![[
+                vertex(inside[0], o),
+                vertex(inside[1], o),
+                vertex(inside[2], o),
+            ]]
+        }
+        _ => {
+            // The crossings of the four edges between the two inside and two
+            // outside corners, in order around the quad they form.
+            let (a, b) = (inside[0], inside[1]);
+            let (c, d) = (outside[0], outside[1]);
+            let quad = [vertex(a, c), vertex(a, d), vertex(b, d), vertex(b, c)];
+            vec![[quad[0], quad[1], quad[2]], [quad[0], quad[2], quad[3]]]
+        }
+    };
+
+    // Turn each triangle to face away from the inside corners.
+    let centroid = |indices: &[usize]| {
+        let sum = indices
+            .iter()
+            .fold(Tuple::vector(0.0, 0.0, 0.0), |sum, &i| {
+                sum + (corners[i].1 - Tuple::point(0.0, 0.0, 0.0))
+            });
+        sum * (1.0 / indices.len() as f64)
+    };
+    let outward = centroid(&outside) - centroid(&inside);
+    for [a, b, c] in triangles {
+        let (pa, pb, pc) = (mesh.vertices[a], mesh.vertices[b], mesh.vertices[c]);
+        let normal = (pb - pa).cross(pc - pa);
+        if normal.magnitude() == 0.0 {
+            continue;
+        }
+        if normal.dot(outward) < 0.0 {
+            mesh.triangles.push([a, c, b]);
+        } else {
+            mesh.triangles.push([a, b, c]);
+        }
+    }
+}
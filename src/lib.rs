@@ -0,0 +1,53 @@
+pub mod algorithm;
+pub mod animation;
+pub mod background;
+pub mod blue_noise;
+pub mod bounds;
+pub mod bump;
+pub mod camera;
+pub mod cancel;
+pub mod canvas;
+pub mod caustics;
+pub mod checkpoint;
+pub mod chess;
+pub mod color;
+pub mod compare;
+pub mod compiled;
+pub mod film;
+pub mod filter;
+pub mod foliage;
+pub mod grade;
+pub mod invariants;
+pub mod irradiance;
+pub mod isosurface;
+pub mod light;
+pub mod material;
+pub mod matrix;
+pub mod mesh_cache;
+pub mod obj_file;
+pub mod occlusion;
+pub mod object;
+pub mod path_log;
+pub mod pattern;
+pub mod post;
+#[cfg(feature = "preview")]
+pub mod preview;
+pub mod ray;
+pub mod rng;
+pub mod sample_log;
+pub mod sampler;
+pub mod settings;
+pub mod shadow_cache;
+pub mod shadow_map;
+pub mod shape;
+pub mod slide;
+pub mod solo;
+pub mod swatch;
+pub mod sweep;
+pub mod testing;
+pub mod tuple;
+pub mod util;
+pub mod video;
+pub mod voxel;
+pub mod weathering;
+pub mod world;
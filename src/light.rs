@@ -1,17 +1,117 @@
+use crate::canvas::Canvas;
 use crate::color::*;
 use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::slide::Slide;
 use crate::tuple::*;
 
 pub enum Light {
-    Point { position: Tuple, intensity: Color },
-    Directional { direction: Tuple, intensity: Color },
+    Point {
+        position: Tuple,
+        intensity: Color,
+        attenuation: Attenuation,
+    },
+    Directional {
+        direction: Tuple,
+        intensity: Color,
+    },
+    /// A point light that only shines within a cone.
+    Spot {
+        position: Tuple,
+
+        /// Transforms world space into the light's, where it shines toward -z
+        /// with +y up.
+        view: Matrix<4>,
+
+        intensity: Color,
+        attenuation: Attenuation,
+
+        /// Angle between the cone's axis and its edge.
+        cone_angle: f64,
+
+        /// Image the light projects, like a gobo in a stage light. Each color
+        /// scales the intensity shone in its direction, so a cookie can cast
+        /// patterned light and shadows, such as through window blinds, without
+        /// modeling whatever would cast them.
+        cookie: Option<Slide>,
+    },
+    /// Light reaching every surface evenly from all around, for the ambient
+    /// term of each material to reflect. Scenes without one are lit by white
+    /// ambient light; see `World::ambient_fill`.
+    Ambient {
+        intensity: Color,
+    },
+    /// Light from a sky above and the ground below, all around the scene, for
+    /// outdoor lighting without an environment image. Each shaded point casts
+    /// shadow rays toward the dome, so objects cast soft shadows and darken
+    /// what's under them. Only lights diffusely.
+    Dome {
+        sky: Color,
+        ground: Color,
+
+        /// Angle of elevation, in radians, over which the sky fades into the
+        /// ground around the horizon. Zero for a sharp horizon.
+        horizon: f64,
+
+        /// Shadow rays cast per shaded point. More rays give smoother
+        /// shadows.
+        samples: usize,
+
+        /// Scales the sky and ground.
+        intensity: Color,
+    },
 }
 
 impl Light {
+    /// A point light whose intensity doesn't fall off with distance.
     pub fn new_point(position: Tuple, intensity: Color) -> Self {
+        Light::new_attenuated_point(position, intensity, Attenuation::none())
+    }
+
+    pub fn new_attenuated_point(
+        position: Tuple,
+        intensity: Color,
+        attenuation: Attenuation,
+    ) -> Self {
         Light::Point {
             position,
             intensity,
+            attenuation,
+        }
+    }
+
+    /// A spot light at `from`, aimed at `to`, with a sharp edged cone. `up`
+    /// orients the cookie, whose longer side spans the cone. The cookie can
+    /// fade to black toward its edges to soften the cone's.
+    pub fn new_spot(
+        from: Tuple,
+        to: Tuple,
+        up: Tuple,
+        intensity: Color,
+        cone_angle: f64,
+        cookie: Option<Canvas>,
+    ) -> Self {
+        Light::Spot {
+            position: from,
+            view: Matrix::view_transform(from, to, up),
+            intensity,
+            attenuation: Attenuation::none(),
+            cone_angle,
+            cookie: cookie.map(|image| Slide::new(image, 2.0 * cone_angle)),
+        }
+    }
+
+    pub fn new_ambient(intensity: Color) -> Self {
+        Light::Ambient { intensity }
+    }
+
+    pub fn new_dome(sky: Color, ground: Color, horizon: f64, samples: usize) -> Self {
+        Light::Dome {
+            sky,
+            ground,
+            horizon,
+            samples,
+            intensity: Color::new(1.0, 1.0, 1.0),
         }
     }
 
@@ -22,6 +122,118 @@ impl Light {
             intensity,
         }
     }
+
+    pub fn kind(&self) -> LightKind {
+        match self {
+            Light::Point { .. } | Light::Directional { .. } | Light::Spot { .. } => {
+                LightKind::Direct
+            }
+            Light::Ambient { .. } => LightKind::Ambient,
+            Light::Dome { .. } => LightKind::Dome,
+        }
+    }
+
+    pub fn intensity(&self) -> Color {
+        match self {
+            Light::Point { intensity, .. }
+            | Light::Directional { intensity, .. }
+            | Light::Spot { intensity, .. }
+            | Light::Ambient { intensity }
+            | Light::Dome { intensity, .. } => *intensity,
+        }
+    }
+
+    pub fn set_intensity(&mut self, color: Color) {
+        match self {
+            Light::Point { intensity, .. }
+            | Light::Directional { intensity, .. }
+            | Light::Spot { intensity, .. }
+            | Light::Ambient { intensity }
+            | Light::Dome { intensity, .. } => *intensity = color,
+        }
+    }
+}
+
+/// Fraction of a spot light's intensity, in each color, shone toward a point
+/// in the light's space.
+pub fn spot_beam(point: Tuple, cone_angle: f64, cookie: Option<&Slide>) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0);
+    let axis = Tuple::vector(0.0, 0.0, -1.0);
+    let direction = Tuple::vector(point.x(), point.y(), point.z()).normalize();
+    if direction.dot(axis) < cone_angle.cos() {
+        return black;
+    }
+    match cookie {
+        Some(cookie) => cookie.color_at(point).unwrap_or(black),
+        None => Color::new(1.0, 1.0, 1.0),
+    }
+}
+
+/// Color of a dome light's sky or ground in a direction, blending between
+/// them across the horizon over `horizon` radians of elevation.
+pub fn dome_color(direction: Tuple, sky: Color, ground: Color, horizon: f64) -> Color {
+    let elevation = direction.normalize().y().clamp(-1.0, 1.0).asin();
+    let t = if horizon > 0.0 {
+        (elevation / horizon + 0.5).clamp(0.0, 1.0)
+    } else if elevation >= 0.0 {
+        1.0
+    } else {
+        0.0
+    };
+    ground * (1.0 - t) + sky * t
+}
+
+/// How lights reach surfaces, which decides how they're shaded.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    /// From a point or direction, checked for shadows by a ray toward it.
+    Direct,
+
+    /// Evenly from all around. See `Light::Ambient`.
+    Ambient,
+
+    /// From a dome around the scene. See `Light::Dome`.
+    Dome,
+}
+
+/// How a point light's intensity falls off with distance `d`, scaling it by
+/// `1 / (constant + linear * d + quadratic * d * d)`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Attenuation {
+    pub constant: f64,
+    pub linear: f64,
+    pub quadratic: f64,
+}
+
+impl Attenuation {
+    pub fn new(constant: f64, linear: f64, quadratic: f64) -> Self {
+        Attenuation {
+            constant,
+            linear,
+            quadratic,
+        }
+    }
+
+    /// Constant intensity at every distance.
+    pub fn none() -> Self {
+        Attenuation::new(1.0, 0.0, 0.0)
+    }
+
+    /// Physically based falloff with the square of the distance.
+    pub fn inverse_square() -> Self {
+        Attenuation::new(0.0, 0.0, 1.0)
+    }
+
+    /// Factor the intensity is scaled by at a distance from the light.
+    pub fn factor(&self, distance: f64) -> f64 {
+        let denominator =
+            self.constant + self.linear * distance + self.quadratic * distance * distance;
+        if denominator > 0.0 {
+            1.0 / denominator
+        } else {
+            1.0
+        }
+    }
 }
 
 /// A light source illuminating a point.
@@ -47,12 +259,19 @@ impl LightSource {
     }
 }
 
-/// Illuminate a point using the Phong reflection model.
-pub fn phong<'a, L>(material: &Material, light_sources: L, normal: &Tuple, viewer: &Tuple) -> Color
+/// Illuminate a point using the Phong reflection model. `ambient` is the
+/// ambient light reaching the point.
+pub fn phong<L>(
+    material: &Material,
+    ambient: Color,
+    light_sources: L,
+    normal: &Tuple,
+    viewer: &Tuple,
+) -> Color
 where
     L: Iterator<Item = LightSource>,
 {
-    let mut intensity = Color::new(1.0, 1.0, 1.0) * material.ambient;
+    let mut intensity = ambient * material.ambient;
     for light in light_sources {
         let light_dot_normal = light.direction.dot(*normal);
         if light_dot_normal > 0.0 {
@@ -67,5 +286,5 @@ where
             }
         }
     }
-    intensity
+    intensity.checked("Phong shading")
 }
@@ -1,56 +1,1027 @@
-mod algorithm;
-mod camera;
-mod canvas;
-mod color;
-mod light;
-mod material;
-mod matrix;
-mod object;
-mod pattern;
-mod ray;
-mod shape;
-mod tuple;
-mod util;
-mod world;
-
-use camera::*;
-use color::*;
-use light::*;
-use material::*;
-use matrix::*;
-use object::*;
-use pattern::PatternBuilder;
-use shape::*;
-use tuple::*;
-use world::*;
+use raytracer::animation::*;
+use raytracer::background::{Background, Environment};
+use raytracer::bounds::Bounds;
+use raytracer::camera::*;
+use raytracer::canvas::Canvas;
+use raytracer::caustics;
+use raytracer::checkpoint::Checkpoint;
+use raytracer::chess;
+use raytracer::color::*;
+use raytracer::compare;
+use raytracer::filter::Filter;
+use raytracer::foliage;
+use raytracer::grade::{Grade, Lut};
+use raytracer::isosurface::extract_isosurface;
+use raytracer::light::*;
+use raytracer::material::*;
+use raytracer::matrix::*;
+use raytracer::mesh_cache::MeshCache;
+use raytracer::obj_file::load_obj;
+use raytracer::object::*;
+use raytracer::occlusion::{self, AmbientOcclusion};
+use raytracer::path_log::PathLog;
+use raytracer::pattern::PatternBuilder;
+use raytracer::post::ToneMapper;
+#[cfg(feature = "preview")]
+use raytracer::preview::Preview;
+use raytracer::rng::{stable_hash, Pcg32};
+use raytracer::sample_log::SampleLog;
+use raytracer::sampler::Sampler;
+use raytracer::settings::RenderSettings;
+use raytracer::shadow_cache::ShadowCache;
+use raytracer::shape::*;
+use raytracer::solo::SoloMode;
+use raytracer::sweep::{self, Sweep};
+use raytracer::tuple::*;
+use raytracer::video::VideoWriter;
+use raytracer::voxel::VoxelGrid;
+use raytracer::world::*;
 
+use clap::parser::ValueSource;
+use clap::{ArgGroup, ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
 use rand::prelude::*;
 use std::f64::consts::PI;
 use std::fs;
-use std::time::Instant;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Largest side of the render measuring the image for --normalize-lights.
+const LIGHT_PROBE_SIZE: usize = 64;
+
+/// Renders one of the built-in scenes to a PPM image.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+#[command(group(ArgGroup::new("animation").args(["turntable", "motion"]).multiple(true)))]
+#[command(group(ArgGroup::new("patch_pixels").args(["region", "objects"]).multiple(true)))]
+#[command(group(ArgGroup::new("sequence").args(["turntable", "motion", "sweep"]).multiple(true)))]
+struct Args {
+    /// Scene to render.
+    #[arg(value_enum, default_value_t = SceneName::Csg)]
+    scene: SceneName,
+
+    /// Path of the rendered image.
+    #[arg(short, long, default_value = "out.ppm")]
+    output: PathBuf,
+
+    /// Image width in pixels, overriding the scene's camera.
+    #[arg(long)]
+    width: Option<usize>,
+
+    /// Image height in pixels, overriding the scene's camera.
+    #[arg(long)]
+    height: Option<usize>,
+
+    /// Field of view in degrees, overriding the scene's camera.
+    #[arg(long)]
+    fov: Option<f64>,
+
+    /// Render settings to start from, as saved with --save-settings. Options
+    /// given on the command line override them.
+    #[arg(long, value_name = "FILE")]
+    settings: Option<PathBuf>,
+
+    /// Write the render settings, after applying the other options, to a file
+    /// instead of rendering.
+    #[arg(long, value_name = "FILE")]
+    save_settings: Option<PathBuf>,
+
+    /// Compare two images, such as a render against a reference, printing
+    /// their RMSE, PSNR, SSIM, and largest channel error instead of
+    /// rendering.
+    #[arg(long, num_args = 2, value_names = ["A", "B"])]
+    compare: Option<Vec<PathBuf>>,
+
+    /// Write a heat map of where the compared images differ to this file.
+    #[arg(long, value_name = "FILE", requires = "compare")]
+    heatmap: Option<PathBuf>,
+
+    /// Rays traced per pixel.
+    #[arg(short, long, default_value_t = RenderSettings::new().samples)]
+    samples: usize,
+
+    /// Pixel reconstruction filter: box, tent, gaussian, or mitchell.
+    #[arg(long, default_value_t = RenderSettings::new().filter)]
+    filter: Filter,
+
+    /// Where in each pixel samples are taken: ring, spiral, or poisson.
+    #[arg(long, default_value_t = RenderSettings::new().sampler)]
+    sampler: Sampler,
+
+    /// Spread path tracing noise over the image as fine, even grain.
+    #[arg(long)]
+    blue_noise: bool,
+
+    /// Rendering algorithm: whitted, or path for path tracing.
+    #[arg(long, default_value_t = RenderSettings::new().mode)]
+    mode: RenderMode,
+
+    /// Seed for everything random in the scene and render. The same seed
+    /// always gives the same image.
+    #[arg(long, default_value_t = RenderSettings::new().seed)]
+    seed: u64,
+
+    /// Maximum reflection and refraction recursion depth, or bounces when
+    /// path tracing.
+    #[arg(short, long, default_value_t = RenderSettings::new().max_depth)]
+    depth: u8,
+
+    /// Stop following reflections and refractions once they carry less than
+    /// this fraction of their light to the camera. Raise --depth along with it
+    /// to let mirrors reflect deeper while dim reflections stop early.
+    #[arg(long, value_name = "FRACTION", default_value_t = RenderSettings::new().min_throughput)]
+    min_throughput: f64,
+
+    /// Equirectangular PPM or PNG image seen in every direction rays escape
+    /// the scene, instead of black. Its light also tints the ambient light.
+    #[arg(long, value_name = "IMAGE")]
+    environment: Option<PathBuf>,
+
+    /// Turn the environment image about the vertical axis, in degrees.
+    #[arg(long, value_name = "DEGREES", default_value_t = 0.0)]
+    environment_azimuth: f64,
+
+    /// Tilt the environment image, in degrees, raising its middle.
+    #[arg(long, value_name = "DEGREES", default_value_t = 0.0)]
+    environment_elevation: f64,
+
+    /// Brighten or darken the environment image, in stops.
+    #[arg(long, value_name = "STOPS", default_value_t = 0.0)]
+    environment_exposure: f64,
+
+    /// Scale every direct light's intensity together so the image averages
+    /// this luminance, after exposure, as measured by a quick low resolution
+    /// render. Ambient and dome lights are left as they are.
+    #[arg(long, value_name = "LUMINANCE")]
+    normalize_lights: Option<f64>,
+
+    /// Light the scene with ambient light of this brightness in place of its
+    /// own ambient lights, or the white ambient light of scenes without any,
+    /// to balance fill light across every material at once. 0 turns ambient
+    /// light off.
+    #[arg(long, value_name = "LEVEL")]
+    ambient: Option<f64>,
+
+    /// Add a dome light of this brightness, a blue sky above brown ground
+    /// meeting at a soft horizon, lighting the scene from every direction with
+    /// soft shadows, for outdoor lighting without an environment image.
+    #[arg(long, value_name = "INTENSITY")]
+    dome: Option<f64>,
+
+    /// Shadow rays each shaded point casts toward the --dome light.
+    #[arg(long, value_name = "RAYS", default_value_t = 16, requires = "dome")]
+    dome_samples: usize,
+
+    /// Darken ambient light in creases by casting this many occlusion rays
+    /// per shaded point.
+    #[arg(long, value_name = "RAYS")]
+    ao_samples: Option<usize>,
+
+    /// Furthest distance at which surfaces occlude ambient light.
+    #[arg(long, default_value_t = 1.0)]
+    ao_distance: f64,
+
+    /// Trace ambient occlusion once per FACTOR by FACTOR block of pixels and
+    /// upsample it, for faster previews.
+    #[arg(long, value_name = "FACTOR")]
+    ao_downsample: Option<usize>,
+
+    /// Cache shadow rays in cells of this size. Faster for static scenes with
+    /// many lights, at the cost of some accuracy along shadow edges.
+    #[arg(long, value_name = "CELL_SIZE")]
+    shadow_cache: Option<f64>,
+
+    /// Approximate shadows from point lights with depth maps of this
+    /// resolution instead of tracing shadow rays. Useful for fast drafts.
+    #[arg(long, value_name = "RESOLUTION")]
+    shadow_maps: Option<usize>,
+
+    /// Soften shadow map shadows as if every point and spot light were a
+    /// sphere of this radius, widening penumbrae with the distance from
+    /// whatever casts them, for soft shadows in drafts without sampling
+    /// area lights.
+    #[arg(long, value_name = "RADIUS", requires = "shadow_maps")]
+    shadow_softness: Option<f64>,
+
+    /// How rays treat surfaces partly cut away by cutouts, such as the soft
+    /// edges of leaves: blend, exact for finals, or stochastic, which is
+    /// noisier but much faster through stacked layers.
+    #[arg(long, value_name = "MODE", default_value_t = CutoutMode::Blend)]
+    cutouts: CutoutMode,
+
+    /// How refraction tells whether rays enter or leave transparent objects:
+    /// ordering, counting the surfaces along each ray, or normal, going by
+    /// which side of each surface the ray comes from, which keeps nested
+    /// glass, open meshes, and CSG objects right.
+    #[arg(long, value_name = "POLICY", default_value_t = BackfacePolicy::Ordering)]
+    backface_policy: BackfacePolicy,
+
+    /// Flatten the scene before rendering, with its transforms composed and
+    /// its objects sorted into a bounding volume hierarchy, so rays find what
+    /// they hit without testing every object. Speeds up scenes of many
+    /// objects, and can differ from other renders by rounding.
+    #[arg(long)]
+    compile: bool,
+
+    /// Render a turntable animation of this many frames, spinning the scene
+    /// about the y axis. Frames are numbered after the output path.
+    #[arg(long, value_name = "FRAMES")]
+    turntable: Option<usize>,
+
+    /// Animate objects by transforms baked frame by frame, such as by a
+    /// physics simulation, from a CSV file with a line per object per frame:
+    /// the frame number, the object's id in the scene listing, and the 16
+    /// entries of its transform, row by row. Renders every frame up to the
+    /// last one in the file.
+    #[arg(long, value_name = "FILE")]
+    motion: Option<PathBuf>,
+
+    /// Sample every frame of an animation with the same seed, so noise stays
+    /// put instead of changing from frame to frame.
+    #[arg(long)]
+    locked_noise: bool,
+
+    /// Render only this frame of the animation, numbered from 1, as one job
+    /// of a render farm.
+    #[arg(long, value_name = "N", requires = "animation")]
+    frame: Option<usize>,
+
+    /// Write a JSON description of the animation's frames, their seeds, and
+    /// output paths for render farm schedulers, instead of rendering it.
+    #[arg(long, value_name = "FILE", requires = "animation")]
+    manifest: Option<PathBuf>,
+
+    /// Encode the animation into this video file instead of a numbered image
+    /// sequence, or a sweep's thumbnails instead of a strip of them: .y4m for
+    /// uncompressed video that players such as mpv and VLC play directly,
+    /// .apng for an animated PNG, which needs the png feature, or .gif for an
+    /// animated GIF to share small previews, which needs the gif feature.
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "sequence",
+        conflicts_with_all = ["frame", "manifest"]
+    )]
+    video: Option<PathBuf>,
+
+    /// Patch an existing render, re-rendering only the pixels selected with
+    /// --region or --objects and writing the result to the output path.
+    #[arg(long, value_name = "IMAGE", requires = "patch_pixels")]
+    patch: Option<PathBuf>,
+
+    /// After rendering the caustics scene, measure the light the glass
+    /// focuses onto the table against this reference render of it, such as
+    /// one with many more samples, and print how they compare.
+    #[arg(long, value_name = "IMAGE")]
+    caustic_reference: Option<PathBuf>,
+
+    /// Pixels to re-render when patching.
+    #[arg(long, value_name = "X,Y,WIDTH,HEIGHT")]
+    region: Option<Region>,
+
+    /// Objects whose pixels are re-rendered when patching, by the ids shown in
+    /// the scene listing.
+    #[arg(long, value_delimiter = ',', value_name = "IDS")]
+    objects: Vec<Obj>,
+
+    /// Light only with this light, by its index in the scene.
+    #[arg(long, value_name = "INDEX")]
+    solo_light: Option<usize>,
+
+    /// Switch off lights, by their index in the scene.
+    #[arg(long, value_delimiter = ',', value_name = "INDICES")]
+    mute_lights: Vec<usize>,
+
+    /// Show only this object normally, by the id shown in the scene listing.
+    #[arg(long, value_name = "ID")]
+    solo_object: Option<Obj>,
+
+    /// Hide the other objects when soloing one, instead of shading them with
+    /// gray clay.
+    #[arg(long)]
+    solo_hide: bool,
+
+    /// Leave objects out of the render, by the ids shown in the scene listing.
+    #[arg(long, value_delimiter = ',', value_name = "IDS")]
+    mute_objects: Vec<Obj>,
+
+    /// Shade every object with plain gray clay, keeping the lights and shapes.
+    #[arg(long)]
+    clay: bool,
+
+    /// Give every object in the scene a sphere stand-in, traced instead of the
+    /// object while it covers fewer than this many pixels across the image.
+    /// Objects going on forever, such as planes, keep their detail.
+    #[arg(long, value_name = "PIXELS")]
+    lod: Option<f64>,
+
+    /// Add a Wavefront OBJ model to the scene, in the default material. May
+    /// be given more than once.
+    #[arg(long, value_name = "FILE")]
+    obj: Vec<PathBuf>,
+
+    /// Keep parsed OBJ models in this directory, so loading one again skips
+    /// parsing it.
+    #[arg(long, value_name = "DIR", requires = "obj")]
+    mesh_cache: Option<PathBuf>,
+
+    /// Instead of rendering the image, render a strip of labeled thumbnails
+    /// stepping a parameter between two values, written
+    /// object:ID.PARAMETER=START..END for the material parameters of an object
+    /// listed with the scene, or light:INDEX.intensity=START..END to scale a
+    /// light.
+    #[arg(long, value_name = "SWEEP")]
+    sweep: Option<Sweep>,
+
+    /// Number of thumbnails in a sweep, including both ends.
+    #[arg(long, value_name = "N", default_value_t = sweep::DEFAULT_STEPS)]
+    sweep_steps: usize,
+
+    /// Largest side of each sweep thumbnail, in pixels.
+    #[arg(long, value_name = "PIXELS", default_value_t = 128)]
+    sweep_size: usize,
+
+    /// Stop rendering after this many seconds and write the image rendered so
+    /// far.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    time_limit: Option<f64>,
+
+    /// Stop rendering after casting this many rays from the camera and write
+    /// the image rendered so far.
+    #[arg(long, value_name = "RAYS")]
+    ray_limit: Option<u64>,
+
+    /// Save the render's progress to this file, and resume from it if it
+    /// already exists. The file is removed once the image is complete.
+    #[arg(long, value_name = "FILE")]
+    checkpoint: Option<PathBuf>,
+
+    /// Seconds between checkpoint saves.
+    #[arg(long, value_name = "SECONDS", default_value_t = 60.0, value_parser = parse_seconds)]
+    checkpoint_interval: f64,
+
+    /// Exposure adjustment in stops.
+    #[arg(long, default_value_t = RenderSettings::new().exposure)]
+    exposure: f64,
+
+    /// Tone mapping operator: clamp, reinhard, or aces.
+    #[arg(long, default_value_t = RenderSettings::new().tone_mapper)]
+    tone_map: ToneMapper,
+
+    /// Encode the output with the sRGB curve instead of linearly.
+    #[arg(long)]
+    srgb: bool,
+
+    /// Grade the output with a 3D LUT in .cube format, after encoding it with
+    /// --srgb.
+    #[arg(long, value_name = "FILE")]
+    lut: Option<PathBuf>,
+
+    /// Lift applied to the blacks when grading.
+    #[arg(long, default_value_t = 0.0)]
+    grade_lift: f64,
+
+    /// Gamma applied to the midtones when grading.
+    #[arg(long, default_value_t = 1.0)]
+    grade_gamma: f64,
+
+    /// Gain applied to the whites when grading.
+    #[arg(long, default_value_t = 1.0)]
+    grade_gain: f64,
+
+    /// Show the render in a window as it progresses, and keep the window open
+    /// until it's closed or Escape is pressed.
+    #[cfg(feature = "preview")]
+    #[arg(long)]
+    preview: bool,
+
+    /// Write the object chosen with --export-object as a signed distance field,
+    /// in a legacy VTK file, instead of rendering.
+    #[arg(long, value_name = "FILE", requires = "export_object")]
+    export_sdf: Option<PathBuf>,
+
+    /// Write the object chosen with --export-object as a triangle mesh, for 3D
+    /// printing or other modeling tools, instead of rendering. The file is
+    /// binary STL if its name ends in .stl, and Wavefront OBJ otherwise.
+    #[arg(long, value_name = "FILE", requires = "export_object")]
+    export_mesh: Option<PathBuf>,
+
+    /// Object to export, by the id shown in the scene listing. It must be a
+    /// closed solid, or a group or CSG of them.
+    #[arg(long, value_name = "ID")]
+    export_object: Option<Obj>,
+
+    /// Voxels along the longest side of the exported object.
+    #[arg(long, value_name = "VOXELS", default_value_t = 64)]
+    export_resolution: usize,
+
+    /// Render threads. Zero uses every available core.
+    #[arg(short = 'j', long, default_value_t = RenderSettings::new().threads)]
+    threads: usize,
+
+    /// Render at the lowest scheduling priority, keeping the machine usable.
+    #[arg(long)]
+    low_priority: bool,
+
+    /// Width and height of the tiles the image is rendered in. Zero picks a
+    /// size from the image size and core count.
+    #[arg(long, value_name = "PIXELS", default_value_t = RenderSettings::new().tile_size)]
+    tile_size: usize,
+
+    /// Order tiles are rendered in: scanline, or morton to fill the image in
+    /// evenly across it.
+    #[arg(long, value_name = "ORDER", default_value_t = RenderSettings::new().tile_order)]
+    tile_order: TileOrder,
+
+    /// Test every object with every camera ray, instead of only those within
+    /// reach of the ray's tile. The image is the same either way, so this is
+    /// only for comparing speed.
+    #[arg(long)]
+    no_frustum_culling: bool,
+
+    /// Write every sample traced to this file as CSV, with its pixel, sample
+    /// number, path length when path tracing, and color, for analysis such as
+    /// variance studies. Expect files of tens of bytes per sample.
+    #[arg(long, value_name = "FILE")]
+    sample_log: Option<PathBuf>,
+
+    /// Record the light paths of a random fraction of samples, set by
+    /// --path-fraction, and write them to this file as polylines to inspect in
+    /// a 3D viewer: .obj, or .ply with each vertex's kind and its path's
+    /// brightness. Vertices are colored by what happened there: white at the
+    /// camera, orange for diffuse bounces, blue for reflections, cyan for
+    /// refractions, red where paths ended on a surface, and gray where they
+    /// left the scene. Requires --mode path.
+    #[arg(long, value_name = "FILE", conflicts_with = "sequence")]
+    export_paths: Option<PathBuf>,
+
+    /// Fraction of samples whose paths --export-paths records.
+    #[arg(long, value_name = "FRACTION", default_value_t = 0.001)]
+    path_fraction: f64,
+
+    /// Log more diagnostics to stderr: -v for info, -vv for debug, and -vvv
+    /// for trace. RUST_LOG picks levels per module instead, such as
+    /// RUST_LOG=raytracer::camera=debug.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+/// Scenes built into the binary.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum SceneName {
+    Shield,
+    Csg,
+    Chess,
+    Caustics,
+    Foliage,
+}
+
+/// A rectangle of pixels.
+#[derive(Copy, Clone, Debug)]
+struct Region {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        match values[..] {
+            [x, y, width, height] => Ok(Region {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err("expected X,Y,WIDTH,HEIGHT".to_string()),
+        }
+    }
+}
+
+/// Parses a positive number of seconds that fits in a `Duration`.
+fn parse_seconds(s: &str) -> Result<f64, String> {
+    let seconds: f64 = s.trim().parse().map_err(|e| format!("{}", e))?;
+    if seconds > 0.0 && Duration::try_from_secs_f64(seconds).is_ok() {
+        Ok(seconds)
+    } else {
+        Err("expected a positive, finite number of seconds".to_string())
+    }
+}
 
 fn main() {
-    let result = render_scene();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let level = match args.verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .init();
+    let result = render_settings(&args, &matches).and_then(|settings| {
+        if let Some(images) = &args.compare {
+            compare_images(&images[0], &images[1], args.heatmap.as_deref())
+        } else if let Some(path) = &args.save_settings {
+            settings.save(path)
+        } else {
+            render_scene(&args, &settings)
+        }
+    });
     if let Result::Err(e) = result {
         eprintln!("error: {}", e);
     }
 }
 
-fn render_scene() -> Result<(), Box<dyn std::error::Error>> {
-    let (obj_pool, camera, lights) = _csg_scene();
+/// Prints how much two images differ, and writes a heat map of where they do
+/// if given a path for it.
+fn compare_images(
+    a: &Path,
+    b: &Path,
+    heatmap: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (a, b) = (Canvas::open(a)?, Canvas::open(b)?);
+    let comparison = compare::compare(&a, &b)?;
+    println!("rmse: {}", comparison.rmse);
+    println!("psnr: {} dB", comparison.psnr);
+    println!("ssim: {}", comparison.ssim);
+    println!("max error: {}", comparison.max_error);
+    if let Some(path) = heatmap {
+        fs::write(path, compare::difference_heatmap(&a, &b)?.to_ppm()?)?;
+    }
+    Ok(())
+}
+
+/// The settings loaded with --settings, or the defaults, overridden by the
+/// options given on the command line.
+fn render_settings(
+    args: &Args,
+    matches: &ArgMatches,
+) -> Result<RenderSettings, Box<dyn std::error::Error>> {
+    let mut settings = match &args.settings {
+        Some(path) => RenderSettings::open(path)?,
+        None => RenderSettings::new(),
+    };
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if given("samples") {
+        settings.samples = args.samples;
+    }
+    if given("filter") {
+        settings.filter = args.filter;
+    }
+    if given("sampler") {
+        settings.sampler = args.sampler;
+    }
+    if given("blue_noise") {
+        settings.blue_noise = args.blue_noise;
+    }
+    if given("depth") {
+        settings.max_depth = args.depth;
+    }
+    if given("min_throughput") {
+        settings.min_throughput = args.min_throughput;
+    }
+    if given("mode") {
+        settings.mode = args.mode;
+    }
+    if given("seed") {
+        settings.seed = args.seed;
+    }
+    if given("threads") {
+        settings.threads = args.threads;
+    }
+    if given("low_priority") {
+        settings.low_priority = args.low_priority;
+    }
+    if given("tile_size") {
+        settings.tile_size = args.tile_size;
+    }
+    if given("tile_order") {
+        settings.tile_order = args.tile_order;
+    }
+    if given("no_frustum_culling") {
+        settings.frustum_culling = !args.no_frustum_culling;
+    }
+    if given("exposure") {
+        settings.exposure = args.exposure;
+    }
+    if given("tone_map") {
+        settings.tone_mapper = args.tone_map;
+    }
+    if given("srgb") {
+        settings.srgb = args.srgb;
+    }
+    Ok(settings)
+}
+
+fn render_scene(args: &Args, settings: &RenderSettings) -> Result<(), Box<dyn std::error::Error>> {
+    if args.caustic_reference.is_some() && !matches!(args.scene, SceneName::Caustics) {
+        return Err("--caustic-reference only measures the caustics scene".into());
+    }
+    let (mut obj_pool, mut camera, mut lights) = match args.scene {
+        SceneName::Shield => shield_scene(),
+        SceneName::Csg => csg_scene(settings.seed),
+        SceneName::Chess => chess::scene(settings.seed),
+        SceneName::Caustics => caustics::scene(),
+        SceneName::Foliage => foliage::scene(settings.seed),
+    };
+    obj_pool.set_seed(settings.seed);
+    let cache = args.mesh_cache.as_ref().map(MeshCache::new);
+    for path in &args.obj {
+        let loaded = match &cache {
+            Some(cache) => cache.load_obj(path, &mut obj_pool, |_| None),
+            None => load_obj(
+                io::BufReader::new(fs::File::open(path)?),
+                &mut obj_pool,
+                |_| None,
+            ),
+        };
+        loaded.map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    if let Some(min_size) = args.lod {
+        let roots: Vec<Obj> = obj_pool.roots().collect();
+        for root in roots {
+            obj_pool.add_sphere_proxy(root, min_size, None);
+        }
+    }
+    let mut animation = args.turntable.map(|frames| {
+        let roots: Vec<Obj> = obj_pool.roots().collect();
+        let group = obj_pool.add_group(Matrix::identity());
+        for root in roots {
+            obj_pool.add_child(group, root);
+        }
+        Animation::turntable(group, frames)
+    });
+    if let Some(path) = &args.motion {
+        let animation = animation.get_or_insert_with(|| Animation::new(DEFAULT_FPS, 0));
+        animation
+            .read_baked(io::BufReader::new(fs::File::open(path)?))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        if let Some(obj) = animation.baked_objects().find(|&obj| obj >= obj_pool.len()) {
+            return Err(format!("{}: no object {}", path.display(), obj).into());
+        }
+        let last = animation.last_baked_frame().unwrap_or(0);
+        animation.frames = animation.frames.max(last);
+    }
+    if let Some(animation) = &mut animation {
+        if args.locked_noise {
+            animation.noise = FrameNoise::Locked;
+        }
+    }
     println!("{}", obj_pool);
-    let world = World::new(obj_pool, lights);
+    let exporting = args.export_sdf.is_some() || args.export_mesh.is_some();
+    if let Some(obj) = args.export_object.filter(|_| exporting) {
+        let grid = VoxelGrid::occupancy(&obj_pool, obj, args.export_resolution)?;
+        let distance = grid.signed_distance();
+        if let Some(path) = &args.export_sdf {
+            let file = io::BufWriter::new(fs::File::create(path)?);
+            distance.write_vtk(file, "distance")?;
+        }
+        if let Some(path) = &args.export_mesh {
+            let mesh = extract_isosurface(&distance, 0.0);
+            let file = io::BufWriter::new(fs::File::create(path)?);
+            match path.extension().and_then(|e| e.to_str()) {
+                Some(e) if e.eq_ignore_ascii_case("stl") => mesh.write_stl(file)?,
+                _ => mesh.write_obj(file)?,
+            }
+        }
+        return Ok(());
+    }
+    if let Some(level) = args.ambient {
+        // Keep the scene's ambient lights, dark, so light indices still match.
+        for light in lights.iter_mut() {
+            if let Light::Ambient { intensity } = light {
+                *intensity = Color::new(0.0, 0.0, 0.0);
+            }
+        }
+        lights.push(Light::new_ambient(Color::new(level, level, level)));
+    }
+    if let Some(intensity) = args.dome {
+        // A clear sky over sunlit ground, fading into each other over 20
+        // degrees around the horizon.
+        let sky = Color::new(0.55, 0.7, 1.0);
+        let ground = Color::new(0.35, 0.3, 0.25);
+        let horizon = 20.0 * PI / 180.0;
+        let mut dome = Light::new_dome(sky, ground, horizon, args.dome_samples);
+        dome.set_intensity(Color::new(intensity, intensity, intensity));
+        lights.push(dome);
+    }
+    let mut world = World::new(obj_pool, lights);
+    world.cutout_mode = args.cutouts;
+    world.backface_policy = args.backface_policy;
+    world.seed = settings.seed;
+    if let Some(path) = &args.environment {
+        let mut environment = Environment::new(Canvas::open(path)?);
+        environment.azimuth = args.environment_azimuth * PI / 180.0;
+        environment.elevation = args.environment_elevation * PI / 180.0;
+        environment.exposure = args.environment_exposure;
+        world.background = Background::Environment(environment);
+        world.ambient_light = world.background.irradiance();
+    }
+    world.ambient_occlusion = args
+        .ao_samples
+        .map(|samples| AmbientOcclusion::new(samples, args.ao_distance));
+    world.shadow_cache = args.shadow_cache.map(ShadowCache::new);
+    world.solo.light = args.solo_light;
+    world.solo.muted_lights = args.mute_lights.clone();
+    world.solo.object = args.solo_object;
+    if args.solo_hide {
+        world.solo.mode = SoloMode::Hide;
+    }
+    world.solo.muted_objects = args.mute_objects.clone();
+    if args.clay {
+        world.material_override = Some(Material::clay());
+    }
+    if let Some(resolution) = args.shadow_maps {
+        world.build_shadow_maps(resolution);
+        if let (Some(maps), Some(radius)) = (&mut world.shadow_maps, args.shadow_softness) {
+            maps.set_light_radius(radius);
+        }
+    }
 
+    let hsize = args.width.unwrap_or_else(|| camera.hsize());
+    let vsize = args.height.unwrap_or_else(|| camera.vsize());
+    camera.set_size(hsize, vsize);
+    if let Some(fov) = args.fov {
+        camera.set_field_of_view(fov * PI / 180.0);
+    }
+    camera.set_settings(*settings);
+    camera.select_lod(&mut world);
+    if args.compile {
+        let start = Instant::now();
+        world.compile();
+        if let Some(scene) = &world.compiled {
+            log::info!("compiled {} objects in {:?}", scene.len(), start.elapsed());
+        }
+    }
+    if let Some(target) = args.normalize_lights {
+        match camera.normalize_lights(&mut world, target, LIGHT_PROBE_SIZE) {
+            Some(factor) => log::info!("scaled direct light intensities by {}", factor),
+            None => log::warn!("direct lights don't brighten the image, leaving them alone"),
+        }
+    }
+    let sample_log = match &args.sample_log {
+        Some(path) => {
+            let log = Arc::new(SampleLog::create(path)?);
+            let record = Arc::clone(&log);
+            camera.set_on_sample(move |sample| record.record(sample));
+            Some(log)
+        }
+        None => None,
+    };
+    let path_log = match &args.export_paths {
+        Some(_) => {
+            if settings.mode != RenderMode::PathTraced {
+                return Err("--export-paths records path traced samples; use --mode path".into());
+            }
+            let log = Arc::new(PathLog::new(escape_length(&world)));
+            let record = Arc::clone(&log);
+            camera.set_on_path(args.path_fraction, move |path| record.record(path));
+            Some(log)
+        }
+        None => None,
+    };
+
+    let mut post = settings.post_process();
+    if args.grade_lift != 0.0 || args.grade_gamma != 1.0 || args.grade_gain != 1.0 {
+        let gray = |v: f64| Color::new(v, v, v);
+        post.grades.push(Grade::LiftGammaGain {
+            lift: gray(args.grade_lift),
+            gamma: gray(args.grade_gamma),
+            gain: gray(args.grade_gain),
+        });
+    }
+    if let Some(lut) = &args.lut {
+        post.grades
+            .push(Grade::Lut(Lut::from_cube(fs::File::open(lut)?)?));
+    }
+
+    #[cfg(feature = "preview")]
+    let mut preview = None;
     let render_start = Instant::now();
-    let image = camera.render(&world);
+    if let Some(sweep) = &args.sweep {
+        let mut sweep = sweep.clone();
+        sweep.steps = args.sweep_steps;
+        if let Some(path) = &args.video {
+            let thumbnails = sweep.thumbnails(&mut world, &camera, &post, args.sweep_size)?;
+            let (width, height) = (thumbnails[0].width, thumbnails[0].height);
+            let mut video =
+                VideoWriter::create(path, width, height, sweep::VIDEO_FPS, thumbnails.len())?;
+            for thumbnail in &thumbnails {
+                video.write_frame(thumbnail)?;
+            }
+            video.finish()?;
+        } else {
+            let strip = sweep.render(&mut world, &camera, &post, args.sweep_size)?;
+            fs::write(&args.output, strip.to_ppm()?)?;
+        }
+    } else if let Some(animation) = animation {
+        if let Some(path) = &args.manifest {
+            // Frames only belong together if rendered and processed alike.
+            let scene = scene_key(args, settings.seed)?;
+            let key = stable_hash(&(
+                camera.settings_key(scene),
+                &post,
+                animation.frames,
+                animation.fps.to_bits(),
+            ));
+            let json = manifest(&animation, camera.seed(), key, &args.output);
+            fs::write(path, json)?;
+        } else if let Some(frame) = args.frame {
+            render_frame(
+                &mut world,
+                &mut camera,
+                &animation,
+                frame,
+                &post,
+                &args.output,
+            )?;
+        } else if let Some(path) = &args.video {
+            let video = VideoWriter::create(
+                path,
+                camera.hsize(),
+                camera.vsize(),
+                animation.fps,
+                animation.frames,
+            )?;
+            encode_animation(&mut world, &mut camera, &animation, &post, video)?;
+        } else {
+            render_animation(&mut world, &mut camera, &animation, &post, &args.output)?;
+        }
+    } else if let Some(patch) = &args.patch {
+        let mut image = Canvas::open(patch)?;
+        if image.width != camera.hsize() || image.height != camera.vsize() {
+            return Err(format!(
+                "cannot patch a {}x{} image with a {}x{} render",
+                image.width,
+                image.height,
+                camera.hsize(),
+                camera.vsize()
+            )
+            .into());
+        }
+        let mut mask = if args.objects.is_empty() {
+            vec![false; camera.hsize() * camera.vsize()]
+        } else {
+            camera.object_mask(&world, &args.objects)
+        };
+        if let Some(r) = args.region {
+            let region = camera.region_mask(r.x, r.y, r.width, r.height);
+            for (m, r) in mask.iter_mut().zip(region) {
+                *m = *m || r;
+            }
+        }
+        image.patch(&post.apply(&camera.render_masked(&world, &mask)), &mask);
+        fs::write(&args.output, image.to_ppm()?)?;
+    } else {
+        let budget = Budget {
+            time: args.time_limit.map(Duration::from_secs_f64),
+            camera_rays: args.ray_limit,
+        };
+        let coarse_ao = match args.ao_downsample {
+            Some(factor) if factor > 1 => world.ambient_occlusion.take().map(|ao| (ao, factor)),
+            _ => None,
+        };
+        let checkpoint = match &args.checkpoint {
+            Some(path) => {
+                let interval = Duration::from_secs_f64(args.checkpoint_interval);
+                Some(Checkpoint::new(
+                    path,
+                    interval,
+                    scene_key(args, settings.seed)?,
+                ))
+            }
+            None => None,
+        };
+        let render = |camera: &Camera| match &checkpoint {
+            Some(checkpoint) => camera
+                .render_checkpointed(&world, budget, checkpoint)
+                .map_err(|e| e.to_string()),
+            None => Ok(camera.render_within(&world, budget)),
+        };
+        #[cfg(feature = "preview")]
+        let mut rendering = if args.preview {
+            let window = preview.insert(Preview::open(camera.hsize(), camera.vsize())?);
+            window.watch(&mut camera, &post, render)?
+        } else {
+            render(&camera)?
+        };
+        #[cfg(not(feature = "preview"))]
+        let mut rendering = render(&camera)?;
+        if let Some((ao, factor)) = coarse_ao {
+            occlusion::apply_coarse(&mut rendering.image, &camera, &world, &ao, factor);
+        }
+        if rendering.partial {
+            log::warn!("render budget ran out, writing a partial image");
+        }
+        let image = post.apply(&rendering.image);
+        fs::write(&args.output, image.to_ppm()?)?;
+        if let Some(path) = &args.caustic_reference {
+            let reference = Canvas::open(path)?;
+            let mask = camera.object_mask(&world, &[caustics::TABLE]);
+            println!("{}", caustics::measure(&image, &reference, &mask)?);
+        }
+    }
     let render_end = Instant::now();
+    if let Some(log) = sample_log {
+        log.finish()?;
+    }
+    if let (Some(log), Some(path)) = (path_log, &args.export_paths) {
+        log.save(path)?;
+        log::info!("wrote {} light paths to {}", log.len(), path.display());
+    }
     display_benchmark("render", render_start, render_end);
 
-    fs::write("out.ppm", image.to_ppm()?)?;
+    #[cfg(feature = "preview")]
+    if let Some(preview) = &mut preview {
+        preview.wait(&post);
+    }
 
     Ok(())
 }
 
+/// How far past their last surface exported paths that leave the scene are
+/// drawn: the size of the scene's finite objects, so that they clear it
+/// without dwarfing it.
+fn escape_length(world: &World) -> f64 {
+    let pool = &world.obj_pool;
+    let bounds = pool
+        .roots()
+        .filter_map(|root| pool.bounds(root))
+        .filter(Bounds::is_finite)
+        .fold(Bounds::empty(), Bounds::merge);
+    if bounds.is_finite() {
+        bounds.size().magnitude().max(1.0)
+    } else {
+        10.0
+    }
+}
+
+/// Hash of the options and files that the scene is built from, so that a
+/// checkpoint of another scene isn't resumed. Files are hashed by their
+/// contents, so editing one changes the scene.
+fn scene_key(args: &Args, seed: u64) -> Result<u64, Box<dyn std::error::Error>> {
+    let environment = args.environment.as_deref().map(file_key).transpose()?;
+    let models = args
+        .obj
+        .iter()
+        .map(|path| file_key(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let motion = args.motion.as_deref().map(file_key).transpose()?;
+    let scene = format!(
+        "{:?}",
+        (
+            args.scene,
+            seed,
+            (
+                environment,
+                args.environment_azimuth,
+                args.environment_elevation,
+                args.environment_exposure,
+            ),
+            (
+                args.normalize_lights,
+                args.ambient,
+                args.dome,
+                args.dome_samples,
+            ),
+            (args.ao_samples, args.ao_distance),
+            args.shadow_cache,
+            (args.shadow_maps, args.shadow_softness),
+            (
+                args.solo_light,
+                &args.mute_lights,
+                args.solo_object,
+                args.solo_hide,
+                &args.mute_objects,
+            ),
+            args.clay,
+            (args.lod, models),
+            motion,
+            (args.cutouts, args.backface_policy, args.compile),
+        )
+    );
+    Ok(stable_hash(&scene))
+}
+
+/// Hash of a file's contents, the same on every build.
+fn file_key(path: &Path) -> Result<u64, Box<dyn std::error::Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(stable_hash(&bytes))
+}
+
 fn display_benchmark(label: &str, start: Instant, end: Instant) {
     let duration = end.duration_since(start);
     let secs = duration.as_secs();
@@ -58,7 +1029,7 @@ fn display_benchmark(label: &str, start: Instant, end: Instant) {
     println!("{}: {}s {}ms", label, secs, millis);
 }
 
-fn _shield_scene() -> (ObjPool, Camera, Vec<Light>) {
+fn shield_scene() -> (ObjPool, Camera, Vec<Light>) {
     let light = Light::new_point(Tuple::point(-10.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
 
     let (h, w) = (1280, 960);
@@ -86,6 +1057,7 @@ fn _shield_scene() -> (ObjPool, Camera, Vec<Light>) {
             .transform(Matrix::rotation_z(PI / 2.0))
             .build();
         material.specular = 0.0;
+        material.casts_shadow = false;
         let _dome = obj_pool.add_shape(shape, transform, material);
     }
 
@@ -120,18 +1092,19 @@ fn _shield_scene() -> (ObjPool, Camera, Vec<Light>) {
     (obj_pool, camera, vec![light])
 }
 
-fn _csg_scene() -> (ObjPool, Camera, Vec<Light>) {
+/// The scene's area light is sampled randomly, from the seed.
+fn csg_scene(seed: u64) -> (ObjPool, Camera, Vec<Light>) {
     let lights = {
         let scale = 0.1;
         let transform = Matrix::translation(0.0, 10.0, 0.0) * Matrix::scaling(scale, scale, scale);
         let n_samples = 25;
         let point_intensity = 0.8;
         let intensity = point_intensity / (n_samples as f64);
-        let mut rng = rand::thread_rng();
+        let mut rng = Pcg32::seed_from_u64(seed);
         let mut lights = Vec::new();
         for _ in 0..n_samples {
             let position = transform * sample_point(&mut rng);
-            println!("{:?}", position);
+            log::debug!("area light sample at {:?}", position);
             let intensity = Color::new(intensity, intensity, 0.95 * intensity);
             let light = Light::new_point(position, intensity);
             lights.push(light);
@@ -166,21 +1139,21 @@ fn _csg_scene() -> (ObjPool, Camera, Vec<Light>) {
     let s1 = {
         let shape = Shape::Sphere;
         let transform = Matrix::scaling(1.25, 1.25, 1.25);
-        let material = Material::new();
-        obj_pool.add_shape(shape, transform, material)
+        obj_pool.add_shape(shape, transform, None)
     };
 
     let c1 = {
         let shape = Shape::Cube;
         let transform = Matrix::identity();
-        let material = Material::new();
-        obj_pool.add_shape(shape, transform, material)
+        obj_pool.add_shape(shape, transform, None)
     };
 
-    let csg = {
+    let _csg = {
         let op = CsgOp::Difference;
         let transform = Matrix::translation(0.0, 3.0, 0.0) * Matrix::rotation_y(PI / 4.0);
-        obj_pool.add_csg(op, transform, c1, s1)
+        let csg = obj_pool.add_csg(op, transform, c1, s1);
+        obj_pool.set_material(csg, Material::new());
+        csg
     };
 
     (obj_pool, camera, lights)
@@ -1,9 +1,18 @@
+use crate::bump::Bump;
 use crate::color::Color;
 use crate::pattern::Pattern;
+use crate::weathering::Weathering;
 
 pub struct Material {
     pub color: Color,
     pub pattern: Option<Pattern>,
+
+    /// Surface detail applied to the shading normal.
+    pub bump: Option<Bump>,
+
+    /// Dirt darkening the color in crevices.
+    pub weathering: Option<Weathering>,
+
     pub ambient: f64,
     pub diffuse: f64,
     pub specular: f64,
@@ -11,6 +20,32 @@ pub struct Material {
     pub reflective: f64,
     pub transparency: f64,
     pub refractive_index: f64,
+
+    /// Light given off by the surface itself, which lights other surfaces
+    /// when path tracing.
+    pub emission: Color,
+
+    /// Varies the emission across the surface, scaling it by the pattern's
+    /// color, like a TV screen or a stained glass window lit from behind.
+    pub emission_pattern: Option<Pattern>,
+
+    /// Whether the surface gives off light from both faces, like a fill card,
+    /// or only from the outside of the object, like a light panel.
+    pub two_sided_emission: bool,
+
+    /// Treats the surface as a zero-thickness interface. Transmitted rays pass
+    /// straight through without bending and the object has no interior medium.
+    pub thin_walled: bool,
+
+    /// Whether the object blocks light from reaching other surfaces.
+    pub casts_shadow: bool,
+
+    /// Cuts the surface away, like the outline of a leaf on a flat card: the
+    /// pattern's luminance is how much of the surface is there, from 0.0 where
+    /// it's cut away, which rays pass straight through, to 1.0 where it's
+    /// solid. How rays treat partly covered surfaces is up to the world's
+    /// `CutoutMode`.
+    pub cutout: Option<Pattern>,
 }
 
 impl Material {
@@ -18,6 +53,8 @@ impl Material {
         Material {
             color: Color::new(1.0, 1.0, 1.0),
             pattern: None,
+            bump: None,
+            weathering: None,
             ambient: 0.1,
             diffuse: 0.9,
             specular: 0.9,
@@ -25,6 +62,29 @@ impl Material {
             reflective: 0.0,
             transparency: 0.0,
             refractive_index: 1.0,
+            emission: Color::new(0.0, 0.0, 0.0),
+            emission_pattern: None,
+            two_sided_emission: true,
+            thin_walled: false,
+            casts_shadow: true,
+            cutout: None,
         }
     }
 }
+
+impl Material {
+    /// Plain gray without highlights, for judging lighting and shapes apart
+    /// from surface detail.
+    pub fn clay() -> Self {
+        let mut clay = Material::new();
+        clay.color = Color::new(0.5, 0.5, 0.5);
+        clay.specular = 0.0;
+        clay
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::new()
+    }
+}
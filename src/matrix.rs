@@ -1,3 +1,5 @@
+#![allow(clippy::needless_range_loop)]
+
 use crate::algorithm::dot_product;
 use crate::tuple::Tuple;
 use crate::util::*;
@@ -13,11 +15,16 @@ impl<const N: usize> Matrix<N> {
         Self { elements }
     }
 
+    /// A matrix with the given rows.
+    pub fn from_rows(rows: [[f64; N]; N]) -> Self {
+        Matrix::new(rows)
+    }
+
     pub fn identity() -> Self {
         Matrix::new(identity_elements())
     }
 
-    fn element(&self, row: usize, column: usize) -> f64 {
+    pub fn element(&self, row: usize, column: usize) -> f64 {
         self.elements[row][column]
     }
 
@@ -213,6 +220,13 @@ impl Matrix<4> {
         d
     }
 
+    /// Whether the matrix has an inverse. Determinants too close to zero to
+    /// divide by without blowing up count as none.
+    pub fn is_invertible(&self) -> bool {
+        let d = self.determinant();
+        d.is_finite() && d.abs() > f64::EPSILON
+    }
+
     pub fn inverse(&self) -> Matrix<4> {
         let d = self.determinant();
         let mut elements = zero_elements();
@@ -237,7 +251,7 @@ impl<const N: usize> PartialEq for Matrix<N> {
                 }
             }
         }
-        return true;
+        true
     }
 }
 
@@ -0,0 +1,91 @@
+use crate::material::Material;
+use crate::obj_file::{parse_obj, Mesh};
+use crate::object::{Obj, ObjPool};
+
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Marks a cached mesh file, and its format's version.
+const MAGIC: &[u8; 8] = b"RTMESH1\n";
+
+/// Parsed meshes saved in a directory, keyed by a hash of the files they were
+/// parsed from, so loading a large model again skips parsing it. A changed
+/// file hashes differently, so stale entries are never used, only left behind.
+#[derive(Clone, Debug)]
+pub struct MeshCache {
+    pub dir: PathBuf,
+}
+
+impl MeshCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        MeshCache { dir: dir.into() }
+    }
+
+    /// Loads a Wavefront OBJ file like `obj_file::load_obj`, parsing it only if
+    /// it isn't cached yet.
+    pub fn load_obj(
+        &self,
+        path: &Path,
+        obj_pool: &mut ObjPool,
+        materials: impl FnMut(&str) -> Option<Material>,
+    ) -> Result<Obj, Box<dyn Error>> {
+        let source = fs::read(path)?;
+        Ok(self.mesh(&source)?.add_to(obj_pool, materials))
+    }
+
+    /// The mesh parsed from an OBJ file's contents, from the cache if there.
+    pub fn mesh(&self, source: &[u8]) -> Result<Mesh, Box<dyn Error>> {
+        let path = self.entry(source);
+        if let Some(mesh) = read_entry(&path) {
+            log::debug!("mesh cache hit {}", path.display());
+            return Ok(mesh);
+        }
+
+        log::debug!("mesh cache miss {}", path.display());
+        let mesh = parse_obj(source)?;
+        // A cache that can't be written only costs the next load its time.
+        if let Err(e) = self.write_entry(&path, &mesh) {
+            log::debug!("can't write mesh cache {}: {}", path.display(), e);
+        }
+        Ok(mesh)
+    }
+
+    fn entry(&self, source: &[u8]) -> PathBuf {
+        // The standard hasher may change between Rust releases, which only
+        // means entries saved by an older build are missed.
+        let mut hasher = DefaultHasher::new();
+        hasher.write(source);
+        self.dir.join(format!("{:016x}.mesh", hasher.finish()))
+    }
+
+    /// Writes an entry next to its path first and then moves it into place, so
+    /// that a crash or another render loading the same model never sees half
+    /// of one.
+    fn write_entry(&self, path: &Path, mesh: &Mesh) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        let temporary = path.with_extension(format!("mesh.{}.tmp", std::process::id()));
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        writer.write_all(MAGIC)?;
+        mesh.write_to(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+/// Reads a cached mesh, or `None` if there isn't one or it can't be read, in
+/// which case the mesh is parsed again and the entry replaced.
+fn read_entry(path: &Path) -> Option<Mesh> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic).ok()?;
+    if &magic != MAGIC {
+        return None;
+    }
+    Mesh::read_from(reader).ok()
+}
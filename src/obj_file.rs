@@ -0,0 +1,304 @@
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::object::{Obj, ObjPool};
+use crate::shape::Shape;
+use crate::tuple::Tuple;
+
+use std::error::Error;
+use std::io::{BufRead, Read, Write};
+
+/// Name of the group holding faces that come before any `g` statement.
+const DEFAULT_GROUP: &str = "default";
+
+/// Triangles sharing an OBJ group and material.
+struct Part {
+    group: String,
+    material: Option<String>,
+    triangles: Vec<Shape>,
+}
+
+/// A parsed Wavefront OBJ model, which can be added to object pools any number
+/// of times.
+pub struct Mesh {
+    parts: Vec<Part>,
+}
+
+/// Loads a Wavefront OBJ model into an object pool, returning the group that
+/// holds it. See `parse_obj` and `Mesh::add_to`.
+pub fn load_obj<R: BufRead>(
+    reader: R,
+    obj_pool: &mut ObjPool,
+    materials: impl FnMut(&str) -> Option<Material>,
+) -> Result<Obj, Box<dyn Error>> {
+    Ok(parse_obj(reader)?.add_to(obj_pool, materials))
+}
+
+/// Parses a Wavefront OBJ model.
+///
+/// Polygons are split into triangles. Faces whose vertices all have `vn`
+/// normals become smooth triangles. Each `g` group becomes a child group of
+/// the model, and faces that `usemtl` different materials within a group are
+/// split into a child group per material. Statements other than `v`, `vn`,
+/// `f`, `g`, and `usemtl` are ignored.
+pub fn parse_obj<R: BufRead>(reader: R) -> Result<Mesh, Box<dyn Error>> {
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut parts: Vec<Part> = Vec::new();
+    let mut group = DEFAULT_GROUP.to_string();
+    let mut material = None;
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_error = |message: String| format!("line {}: {}", number + 1, message);
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => vertices.push(parse_tuple(words, 1.0).map_err(line_error)?),
+            Some("vn") => normals.push(parse_tuple(words, 0.0).map_err(line_error)?),
+            Some("g") => {
+                let name = words.collect::<Vec<_>>().join(" ");
+                group = if name.is_empty() {
+                    DEFAULT_GROUP.to_string()
+                } else {
+                    name
+                };
+            }
+            Some("usemtl") => material = words.next().map(|name| name.to_string()),
+            Some("f") => {
+                let corners = words
+                    .map(|word| parse_corner(word, &vertices, &normals))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(line_error)?;
+                if corners.len() < 3 {
+                    return Err(line_error("face needs at least 3 vertices".to_string()).into());
+                }
+
+                let part = match parts
+                    .iter()
+                    .position(|p| p.group == group && p.material == material)
+                {
+                    Some(index) => &mut parts[index],
+                    None => {
+                        parts.push(Part {
+                            group: group.clone(),
+                            material: material.clone(),
+                            triangles: Vec::new(),
+                        });
+                        parts.last_mut().unwrap()
+                    }
+                };
+
+                // Fan triangulation, which is correct for convex polygons.
+                let (p1, n1) = corners[0];
+                for pair in corners[1..].windows(2) {
+                    let (p2, n2) = pair[0];
+                    let (p3, n3) = pair[1];
+                    let triangle = match (n1, n2, n3) {
+                        (Some(n1), Some(n2), Some(n3)) => {
+                            Shape::smooth_triangle(p1, p2, p3, n1, n2, n3)
+                        }
+                        _ => Shape::triangle(p1, p2, p3),
+                    };
+                    part.triangles.push(triangle);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Mesh { parts })
+}
+
+impl Mesh {
+    /// Adds the model to an object pool, returning the group that holds it.
+    /// `materials` makes the material for a `usemtl` name; faces with no
+    /// material, or one it returns `None` for, inherit the model's.
+    pub fn add_to(
+        &self,
+        obj_pool: &mut ObjPool,
+        mut materials: impl FnMut(&str) -> Option<Material>,
+    ) -> Obj {
+        let model = obj_pool.add_group(Matrix::identity());
+        let mut groups: Vec<(&str, Obj)> = Vec::new();
+        for part in &self.parts {
+            let group = match groups.iter().find(|(name, _)| *name == part.group) {
+                Some((_, group)) => *group,
+                None => {
+                    let group = obj_pool.add_group(Matrix::identity());
+                    obj_pool.add_child(model, group);
+                    groups.push((&part.group, group));
+                    group
+                }
+            };
+
+            let shared_group = self.parts.iter().filter(|p| p.group == part.group).count() > 1;
+            let parent = if shared_group {
+                let subgroup = obj_pool.add_group(Matrix::identity());
+                obj_pool.add_child(group, subgroup);
+                subgroup
+            } else {
+                group
+            };
+            if let Some(material) = part.material.as_deref().and_then(&mut materials) {
+                obj_pool.set_material(parent, material);
+            }
+
+            let triangles: Vec<Obj> = part
+                .triangles
+                .iter()
+                .map(|triangle| obj_pool.add_shape(*triangle, Matrix::identity(), None))
+                .collect();
+            obj_pool.add_children(parent, &triangles);
+        }
+
+        model
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        writer.write_all(&(self.parts.len() as u64).to_le_bytes())?;
+        for part in &self.parts {
+            write_str(&mut writer, &part.group)?;
+            match &part.material {
+                Some(material) => {
+                    writer.write_all(&[1])?;
+                    write_str(&mut writer, material)?;
+                }
+                None => writer.write_all(&[0])?,
+            }
+            writer.write_all(&(part.triangles.len() as u64).to_le_bytes())?;
+            for triangle in &part.triangles {
+                // Only the corners are written; the rest is recomputed when
+                // read, exactly as when parsed.
+                let (corners, normals) = match *triangle {
+                    Shape::Triangle { p1, p2, p3, .. } => ([p1, p2, p3], None),
+                    Shape::SmoothTriangle {
+                        p1,
+                        p2,
+                        p3,
+                        n1,
+                        n2,
+                        n3,
+                        ..
+                    } => ([p1, p2, p3], Some([n1, n2, n3])),
+                    _ => unreachable!("meshes only hold triangles"),
+                };
+                writer.write_all(&[normals.is_some() as u8])?;
+                for t in corners.iter().chain(normals.iter().flatten()) {
+                    for v in [t.x(), t.y(), t.z()] {
+                        writer.write_all(&v.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Mesh, Box<dyn Error>> {
+        let mut parts = Vec::new();
+        for _ in 0..read_u64(&mut reader)? {
+            let group = read_str(&mut reader)?;
+            let material = match read_u8(&mut reader)? {
+                0 => None,
+                _ => Some(read_str(&mut reader)?),
+            };
+            let count = read_u64(&mut reader)?;
+            let mut triangles = Vec::new();
+            for _ in 0..count {
+                let smooth = read_u8(&mut reader)? != 0;
+                let p1 = read_tuple(&mut reader, 1.0)?;
+                let p2 = read_tuple(&mut reader, 1.0)?;
+                let p3 = read_tuple(&mut reader, 1.0)?;
+                let triangle = if smooth {
+                    let n1 = read_tuple(&mut reader, 0.0)?;
+                    let n2 = read_tuple(&mut reader, 0.0)?;
+                    let n3 = read_tuple(&mut reader, 0.0)?;
+                    Shape::smooth_triangle(p1, p2, p3, n1, n2, n3)
+                } else {
+                    Shape::triangle(p1, p2, p3)
+                };
+                triangles.push(triangle);
+            }
+            parts.push(Part {
+                group,
+                material,
+                triangles,
+            });
+        }
+        Ok(Mesh { parts })
+    }
+}
+
+fn write_str<W: Write>(writer: &mut W, s: &str) -> Result<(), Box<dyn Error>> {
+    writer.write_all(&(s.len() as u64).to_le_bytes())?;
+    writer.write_all(s.as_bytes())?;
+    Ok(())
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, Box<dyn Error>> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Box<dyn Error>> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_str<R: Read>(reader: &mut R) -> Result<String, Box<dyn Error>> {
+    let len = read_u64(reader)?;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err("unexpected end of mesh".into());
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn read_tuple<R: Read>(reader: &mut R, w: f64) -> Result<Tuple, Box<dyn Error>> {
+    let mut coordinate =
+        || -> Result<f64, Box<dyn Error>> { Ok(f64::from_bits(read_u64(reader)?)) };
+    Ok(Tuple::new(coordinate()?, coordinate()?, coordinate()?, w))
+}
+
+fn parse_tuple<'a>(mut words: impl Iterator<Item = &'a str>, w: f64) -> Result<Tuple, String> {
+    let mut coordinate = || -> Result<f64, String> {
+        let word = words.next().ok_or("expected 3 coordinates")?;
+        word.parse()
+            .map_err(|_| format!("invalid coordinate '{}'", word))
+    };
+    Ok(Tuple::new(coordinate()?, coordinate()?, coordinate()?, w))
+}
+
+/// Parses a face's corner, written as `v`, `v/vt`, `v//vn`, or `v/vt/vn`.
+fn parse_corner(
+    word: &str,
+    vertices: &[Tuple],
+    normals: &[Tuple],
+) -> Result<(Tuple, Option<Tuple>), String> {
+    let mut indices = word.split('/');
+    let vertex = indices.next().unwrap_or("");
+    let vertex = vertices[resolve_index(vertex, vertices.len())?];
+    let normal = match indices.nth(1) {
+        Some(normal) if !normal.is_empty() => Some(normals[resolve_index(normal, normals.len())?]),
+        _ => None,
+    };
+    Ok((vertex, normal))
+}
+
+/// Converts a 1-based OBJ index, or a negative one counting back from the
+/// latest element, into a 0-based index.
+fn resolve_index(word: &str, len: usize) -> Result<usize, String> {
+    let index: i64 = word
+        .parse()
+        .map_err(|_| format!("invalid index '{}'", word))?;
+    let resolved = if index < 0 {
+        len as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(format!("index {} out of range", index));
+    }
+    Ok(resolved as usize)
+}
@@ -1,11 +1,22 @@
+use crate::bounds::Bounds;
 use crate::material::*;
 use crate::matrix::*;
 use crate::ray::*;
+use crate::rng::Pcg32;
 use crate::shape::*;
 use crate::tuple::*;
 
+use rand::Rng;
+
 use std::fmt;
 
+/// Radius of a ground disk, in multiples of the distance across the scene it's
+/// under, so that it catches shadows cast at low angles and fills most views.
+const GROUND_DISK_SCALE: f64 = 4.0;
+
+/// Thickness of a ground disk, as a fraction of its radius.
+const GROUND_DISK_THICKNESS: f64 = 0.01;
+
 /// Handle to an object in an object pool.
 pub type Obj = usize;
 
@@ -17,6 +28,9 @@ pub enum CsgOp {
     Difference,
 }
 
+// Triangles make shapes large, but meshes are mostly triangles, so boxing
+// them would cost more than the space other objects waste.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 enum ObjTag {
     /// A primitive shape.
@@ -33,21 +47,83 @@ enum ObjTag {
 pub struct Intersection {
     pub t: f64,
     pub obj: Obj,
+
+    /// Where on the surface the hit is, for shapes that track it, such as the
+    /// barycentric coordinates on a triangle. Zero otherwise.
+    pub u: f64,
+    pub v: f64,
 }
 
 impl Intersection {
     pub fn new_shape(t: f64, obj: Obj) -> Self {
-        Intersection { t, obj }
+        Intersection::new_shape_uv(t, obj, 0.0, 0.0)
+    }
+
+    pub fn new_shape_uv(t: f64, obj: Obj, u: f64, v: f64) -> Self {
+        Intersection { t, obj, u, v }
     }
 }
 
+/// Where the material an object is shaded with is kept: an object's own
+/// material, a CSG difference's cut material, or the pool's default.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MaterialSlot {
+    Own(Obj),
+    Cut(Obj),
+    Default,
+}
+
+/// An object rays can hit, as `ObjPool::flatten` lists it.
+#[derive(Copy, Clone, Debug)]
+pub struct FlatObject {
+    pub obj: Obj,
+
+    /// Transform from world space to the shape's space, or for a CSG object,
+    /// to its parent's space, to intersect it with `intersect_in_parent`.
+    pub transform_inverse: Matrix<4>,
+
+    /// The shape to intersect, or `None` for a CSG object.
+    pub shape: Option<Shape>,
+
+    /// Bounds in world space, or `None` if the object goes on forever.
+    pub bounds: Option<Bounds>,
+}
+
 pub struct ObjPool {
     tag: Vec<ObjTag>,
     pub transform_inverse: Vec<Matrix<4>>,
-    pub material: Vec<Material>,
+    material: Vec<Option<Material>>,
+
+    /// Whether an object's material replaces its descendants' own materials.
+    material_override: Vec<bool>,
+
+    /// Material for the faces a CSG difference cuts into its first operand.
+    cut_material: Vec<Option<Material>>,
+
+    /// Whether a CSG difference's cut faces are textured as flat caps.
+    cut_cap: Vec<bool>,
+
+    /// Simplified stand-in for an object, and the size on screen in pixels
+    /// below which it's traced instead.
+    proxy: Vec<Option<(Obj, f64)>>,
+
+    /// Whether an object's stand-in is traced instead of the object, as last
+    /// chosen by `select_lod`.
+    proxy_active: Vec<bool>,
+
+    default_material: Material,
     parent: Vec<Option<Obj>>,
     left: Vec<Option<Obj>>,
     right: Vec<Option<Obj>>,
+    instance: Vec<bool>,
+
+    /// Whether an object is a CSG node made by `add_csg_many` to hold part of
+    /// its operands, rather than one added for its own sake.
+    balancing: Vec<bool>,
+
+    /// Seeds `variation`, so that scenes built from different seeds vary
+    /// differently.
+    seed: u64,
 }
 
 impl ObjPool {
@@ -56,35 +132,77 @@ impl ObjPool {
             tag: Vec::new(),
             transform_inverse: Vec::new(),
             material: Vec::new(),
+            material_override: Vec::new(),
+            cut_material: Vec::new(),
+            cut_cap: Vec::new(),
+            proxy: Vec::new(),
+            proxy_active: Vec::new(),
+            default_material: Material::new(),
             parent: Vec::new(),
             left: Vec::new(),
             right: Vec::new(),
+            instance: Vec::new(),
+            balancing: Vec::new(),
+            seed: 0,
         }
     }
 
+    /// Sets the seed objects' variations are drawn with, usually the seed the
+    /// scene was built from.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Number of objects in the pool, whose ids run from 0 up to it.
+    pub fn len(&self) -> usize {
+        self.tag.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tag.is_empty()
+    }
+
     fn next_id(&self) -> usize {
         self.tag.len()
     }
 
-    fn add(&mut self, tag: ObjTag, transform: Matrix<4>, material: Material) -> Obj {
+    fn add(&mut self, tag: ObjTag, transform: Matrix<4>, material: Option<Material>) -> Obj {
         let id = self.next_id();
 
         self.tag.push(tag);
         self.transform_inverse.push(transform.inverse());
         self.material.push(material);
+        self.material_override.push(false);
+        self.cut_material.push(None);
+        self.cut_cap.push(false);
+        self.proxy.push(None);
+        self.proxy_active.push(false);
         self.parent.push(None);
         self.left.push(None);
         self.right.push(None);
+        self.instance.push(false);
+        self.balancing.push(false);
 
         id
     }
 
-    pub fn add_shape(&mut self, shape: Shape, transform: Matrix<4>, material: Material) -> Obj {
-        self.add(ObjTag::Shape(shape), transform, material)
+    /// Adds a primitive shape. A shape without a material inherits the material
+    /// of its nearest ancestor that has one.
+    pub fn add_shape(
+        &mut self,
+        shape: Shape,
+        transform: Matrix<4>,
+        material: impl Into<Option<Material>>,
+    ) -> Obj {
+        self.add(ObjTag::Shape(shape), transform, material.into())
+    }
+
+    pub fn parent(&self, obj: Obj) -> Option<Obj> {
+        self.parent[obj]
     }
 
     pub fn add_group(&mut self, transform: Matrix<4>) -> Obj {
-        self.add(ObjTag::Group, transform, Material::new())
+        self.add(ObjTag::Group, transform, None)
     }
 
     pub fn add_child(&mut self, parent: Obj, child: Obj) {
@@ -103,8 +221,24 @@ impl ObjPool {
         }
     }
 
+    /// Adds many children at once. Faster than adding them one by one, which
+    /// walks every sibling added before each.
+    pub fn add_children(&mut self, parent: Obj, children: &[Obj]) {
+        let (first, rest) = match children.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        self.add_child(parent, *first);
+        let mut previous = *first;
+        for &child in rest {
+            self.parent[child] = Some(parent);
+            self.right[previous] = Some(child);
+            previous = child;
+        }
+    }
+
     pub fn add_csg(&mut self, op: CsgOp, transform: Matrix<4>, left: Obj, right: Obj) -> Obj {
-        let csg = self.add(ObjTag::Csg(op), transform, Material::new());
+        let csg = self.add(ObjTag::Csg(op), transform, None);
         self.parent[left] = Some(csg);
         self.parent[right] = Some(csg);
         self.left[csg] = Some(left);
@@ -112,68 +246,585 @@ impl ObjPool {
         csg
     }
 
+    /// Combines any number of objects, including groups, with one operation.
+    /// A difference subtracts every operand after the first from the first.
+    ///
+    /// The operands are arranged into a balanced tree of two-operand CSG
+    /// nodes, so a ray tests each operand at most once per level instead of
+    /// once per operand. Only the returned node carries the transform, and
+    /// the scene listing shows the operands directly under it.
+    ///
+    /// Panics if there are fewer than two operands.
+    pub fn add_csg_many(&mut self, op: CsgOp, transform: Matrix<4>, operands: &[Obj]) -> Obj {
+        assert!(operands.len() >= 2, "CSG needs at least two operands");
+        let (left, right) = match op {
+            // A - B - C - ... is the same as A - (B + C + ...).
+            CsgOp::Difference => (operands[0], self.add_balanced(CsgOp::Union, &operands[1..])),
+            CsgOp::Union | CsgOp::Intersection => {
+                let middle = operands.len() / 2;
+                (
+                    self.add_balanced(op, &operands[..middle]),
+                    self.add_balanced(op, &operands[middle..]),
+                )
+            }
+        };
+        self.add_csg(op, transform, left, right)
+    }
+
+    /// Balanced tree of an associative operation over some operands, or the
+    /// operand itself if there is only one.
+    fn add_balanced(&mut self, op: CsgOp, operands: &[Obj]) -> Obj {
+        if operands.len() == 1 {
+            return operands[0];
+        }
+        let middle = operands.len() / 2;
+        let left = self.add_balanced(op, &operands[..middle]);
+        let right = self.add_balanced(op, &operands[middle..]);
+        let csg = self.add_csg(op, Matrix::identity(), left, right);
+        self.balancing[csg] = true;
+        csg
+    }
+
+    /// A CSG node's operands, looking through the nodes `add_csg_many` made
+    /// to balance its tree.
+    fn csg_operands(&self, csg: Obj) -> Vec<Obj> {
+        let mut operands = Vec::new();
+        for child in [self.left[csg], self.right[csg]].iter().flatten() {
+            if self.balancing[*child] {
+                operands.extend(self.csg_operands(*child));
+            } else {
+                operands.push(*child);
+            }
+        }
+        operands
+    }
+
+    pub fn set_transform(&mut self, obj: Obj, transform: Matrix<4>) {
+        self.transform_inverse[obj] = transform.inverse();
+    }
+
+    /// Objects without a parent.
+    pub fn roots(&self) -> impl Iterator<Item = Obj> + '_ {
+        (0..self.next_id()).filter(move |id| self.parent[*id].is_none())
+    }
+
+    /// Sets an object's material. Descendants without a material of their own
+    /// inherit it.
+    pub fn set_material(&mut self, obj: Obj, material: Material) {
+        self.material[obj] = Some(material);
+        self.material_override[obj] = false;
+    }
+
+    /// An object's own material, to change in place, or `None` if it inherits
+    /// one.
+    pub fn material_mut(&mut self, obj: Obj) -> Option<&mut Material> {
+        self.material[obj].as_mut()
+    }
+
+    /// Sets an object's material, replacing its descendants' own materials
+    /// too, so a CSG object can be shaded as one piece whatever its operands
+    /// are made of.
+    pub fn set_material_override(&mut self, obj: Obj, material: Material) {
+        self.material[obj] = Some(material);
+        self.material_override[obj] = true;
+    }
+
+    /// Sets the material of the faces a CSG difference cuts into its first
+    /// operand, which are the surfaces of the objects subtracted from it, to
+    /// give the cut a distinct cross-section look.
+    ///
+    /// Panics if the object isn't a difference.
+    pub fn set_cut_material(&mut self, csg: Obj, material: Material) {
+        assert!(
+            matches!(self.tag[csg], ObjTag::Csg(CsgOp::Difference)),
+            "only CSG differences have cut faces"
+        );
+        self.cut_material[csg] = Some(material);
+        self.cut_cap[csg] = false;
+    }
+
+    /// Sets a cut material like `set_cut_material`, and textures the cut faces
+    /// as flat caps, the way engineering cutaways hatch their cross-sections.
+    /// Each face is projected onto whichever of the difference's axis planes
+    /// it's nearest parallel to, and its material's patterns see each point as
+    /// (u, v, 0), in units of the difference's space. See `cut_uv`.
+    ///
+    /// Panics if the object isn't a difference.
+    pub fn set_cut_cap(&mut self, csg: Obj, material: Material) {
+        self.set_cut_material(csg, material);
+        self.cut_cap[csg] = true;
+    }
+
+    /// The material an object is shaded with. A shape uses its own material,
+    /// or else inherits its nearest ancestor's, so CSG operands keep their
+    /// own materials by default. A CSG difference's cut material then applies
+    /// to the shapes subtracted by it, and an ancestor's material override to
+    /// every shape under it. The outermost of these wins, except that a
+    /// difference's cut material wins over its own override.
+    pub fn material(&self, obj: Obj) -> &Material {
+        self.slot_material(self.material_slot(obj))
+    }
+
+    /// Where the material an object is shaded with is kept, as `material`
+    /// resolves it.
+    pub fn material_slot(&self, obj: Obj) -> MaterialSlot {
+        let mut slot = MaterialSlot::Default;
+        let mut child = None;
+        let mut node = Some(obj);
+        while let Some(o) = node {
+            if self.material[o].is_some()
+                && (slot == MaterialSlot::Default || self.material_override[o])
+            {
+                slot = MaterialSlot::Own(o);
+            }
+            if let (Some(_), Some(c)) = (&self.cut_material[o], child) {
+                if self.right[o] == Some(c) {
+                    slot = MaterialSlot::Cut(o);
+                }
+            }
+            child = node;
+            node = self.parent[o];
+        }
+        slot
+    }
+
+    /// The material kept in a slot.
+    pub fn slot_material(&self, slot: MaterialSlot) -> &Material {
+        let material = match slot {
+            MaterialSlot::Own(obj) => self.material[obj].as_ref(),
+            MaterialSlot::Cut(csg) => self.cut_material[csg].as_ref(),
+            MaterialSlot::Default => None,
+        };
+        material.unwrap_or(&self.default_material)
+    }
+
+    /// Texture coordinates of a point on a shape that is a cut face of a
+    /// difference made with `set_cut_cap`, or `None` if it isn't one.
+    pub fn cut_uv(&self, obj: Obj, world_point: Tuple) -> Option<SurfaceUv> {
+        let csg = match self.material_slot(obj) {
+            MaterialSlot::Cut(csg) if self.cut_cap[csg] => csg,
+            _ => return None,
+        };
+        let shape = match &self.tag[obj] {
+            ObjTag::Shape(shape) => shape,
+            _ => return None,
+        };
+
+        // Bring the face's normal up into the difference's space, to pick the
+        // plane to project onto.
+        let object_point = self.world_to_object(obj, world_point);
+        let mut normal = shape.normal_at(object_point, &Intersection::new_shape(0.0, obj));
+        let mut node = obj;
+        while node != csg {
+            normal = self.transform_inverse[node].transpose() * normal;
+            normal.set_w(0.0);
+            node = self.parent[node]?;
+        }
+
+        let point = self.world_to_object(csg, world_point);
+        let (x, y, z) = (normal.x().abs(), normal.y().abs(), normal.z().abs());
+        let (u, v) = if x >= y && x >= z {
+            (point.z(), -point.y())
+        } else if y >= z {
+            (point.x(), -point.z())
+        } else {
+            (point.x(), -point.y())
+        };
+        Some(SurfaceUv {
+            region: UvRegion::Cut,
+            u,
+            v,
+        })
+    }
+
     pub fn intersect(&self, ray: &Ray) -> Vec<Intersection> {
-        fn intersect_rec(obj_pool: &ObjPool, root: Obj, ray: &Ray, xs: &mut Vec<Intersection>) {
-            let ray = ray.transform(obj_pool.transform_inverse[root]);
-            match &obj_pool.tag[root] {
-                ObjTag::Shape(shape) => shape.intersects(ray, root, xs),
+        let mut xs = Vec::new();
+        self.intersect_into(ray, &mut xs);
+        xs
+    }
+
+    /// Fills a buffer with a ray's intersections sorted by distance. Reusing
+    /// the buffer between rays avoids allocating for every ray.
+    pub fn intersect_into(&self, ray: &Ray, xs: &mut Vec<Intersection>) {
+        self.intersect_into_culled(ray, xs, &[]);
+    }
+
+    /// Like `intersect_into`, skipping the objects marked by `cull`, which
+    /// must be ones the ray can't hit, so the intersections are the same.
+    pub fn intersect_into_culled(&self, ray: &Ray, xs: &mut Vec<Intersection>, culled: &[bool]) {
+        xs.clear();
+        for root in self.roots() {
+            self.intersect_rec(root, ray, xs, culled);
+        }
+        xs.sort_by(|x1, x2| x1.t.partial_cmp(&x2.t).unwrap());
+    }
+
+    /// Whether a ray hits anything accepted by `blocks` at a distance in
+    /// (0, max_t). Stops as soon as one hit is found, and skips sorting.
+    pub fn any_hit<F>(
+        &self,
+        ray: &Ray,
+        max_t: f64,
+        xs: &mut Vec<Intersection>,
+        mut blocks: F,
+    ) -> bool
+    where
+        F: FnMut(&Intersection) -> bool,
+    {
+        self.roots().any(|root| {
+            xs.clear();
+            self.intersect_rec(root, ray, xs, &[]);
+            xs.iter().any(|x| x.t > 0.0 && x.t < max_t && blocks(x))
+        })
+    }
+
+    /// Appends the intersections of a ray with an object, unsorted, skipping
+    /// culled objects.
+    fn intersect_rec(&self, root: Obj, ray: &Ray, xs: &mut Vec<Intersection>, culled: &[bool]) {
+        if culled.get(root) == Some(&true) {
+            return;
+        }
+        let ray = ray.transform(self.transform_inverse[root]);
+        if let Some((proxy, _)) = self.proxy[root].filter(|_| self.proxy_active[root]) {
+            self.intersect_rec(proxy, &ray, xs, culled);
+            return;
+        }
+        match &self.tag[root] {
+            ObjTag::Shape(shape) => shape.intersects(ray, root, xs),
+            ObjTag::Group => {
+                let mut child = self.left[root];
+                while let Some(c) = child {
+                    self.intersect_rec(c, &ray, xs, culled);
+                    child = self.right[c];
+                }
+            }
+            ObjTag::Csg(op) => {
+                let left = self.left[root].unwrap();
+                let right = self.right[root].unwrap();
+
+                // Gather the children's intersections at the end of the buffer,
+                // then keep the ones on the CSG's surface in place.
+                let start = xs.len();
+                self.intersect_rec(left, &ray, xs, culled);
+                self.intersect_rec(right, &ray, xs, culled);
+                xs[start..].sort_by(|x1, x2| x1.t.partial_cmp(&x2.t).unwrap());
+
+                let mut in_left = false;
+                let mut in_right = false;
+                let mut end = start;
+                for i in start..xs.len() {
+                    let x = xs[i];
+                    let left_hit = self.includes(x.obj, left);
+                    let include_x = match op {
+                        CsgOp::Union => left_hit && !in_right || !left_hit && !in_left,
+                        CsgOp::Intersection => left_hit && in_right || !left_hit && in_left,
+                        CsgOp::Difference => left_hit && !in_right || !left_hit && in_left,
+                    };
+                    if include_x {
+                        xs[end] = x;
+                        end += 1;
+                    }
+                    if left_hit {
+                        in_left = !in_left;
+                    } else {
+                        in_right = !in_right;
+                    }
+                }
+                xs.truncate(end);
+            }
+        }
+    }
+
+    /// Sorted intersections of a ray with one object and its descendants,
+    /// placed where they are in the scene, as if nothing else were there.
+    pub fn intersect_object(&self, obj: Obj, ray: &Ray, xs: &mut Vec<Intersection>) {
+        xs.clear();
+        let ray = self
+            .ancestors(obj)
+            .iter()
+            .rev()
+            .fold(*ray, |ray, &ancestor| {
+                ray.transform(self.transform_inverse[ancestor])
+            });
+        self.intersect_rec(obj, &ray, xs, &[]);
+        xs.sort_by(|x1, x2| x1.t.partial_cmp(&x2.t).unwrap());
+    }
+
+    /// Appends, unsorted, a ray's intersections with an object and its
+    /// descendants, from a ray in the space of the object's parent. See
+    /// `flatten`.
+    pub fn intersect_in_parent(&self, obj: Obj, ray: &Ray, xs: &mut Vec<Intersection>) {
+        self.intersect_rec(obj, ray, xs, &[]);
+    }
+
+    /// The objects rays can hit, with the transforms down to them composed, so
+    /// that they can be intersected without walking the groups they're in.
+    /// Objects traced as their stand-ins are listed as the stand-ins. CSG
+    /// objects are listed whole, since which of their operands' hits count
+    /// depends on all of them.
+    pub fn flatten(&self) -> Vec<FlatObject> {
+        // Listed in the order `intersect_into` visits them, so that hits at
+        // the same distance come in the same order.
+        let mut flat = Vec::new();
+        let mut pending: Vec<(Obj, Matrix<4>)> = self
+            .roots()
+            .map(|root| (root, Matrix::identity()))
+            .collect();
+        pending.reverse();
+        while let Some((obj, to_parent)) = pending.pop() {
+            let to_object = self.transform_inverse[obj] * to_parent;
+            if let Some((proxy, _)) = self.proxy[obj].filter(|_| self.proxy_active[obj]) {
+                pending.push((proxy, to_object));
+                continue;
+            }
+            match &self.tag[obj] {
+                ObjTag::Shape(shape) => flat.push(FlatObject {
+                    obj,
+                    transform_inverse: to_object,
+                    shape: Some(*shape),
+                    bounds: shape.bounds().transform(to_object.inverse()),
+                }),
                 ObjTag::Group => {
-                    let mut child = obj_pool.left[root];
+                    let first = pending.len();
+                    let mut child = self.left[obj];
                     while let Some(c) = child {
-                        intersect_rec(obj_pool, c, &ray, xs);
-                        child = obj_pool.right[c];
+                        pending.push((c, to_object));
+                        child = self.right[c];
                     }
+                    pending[first..].reverse();
                 }
-                ObjTag::Csg(op) => {
-                    let left = obj_pool.left[root].unwrap();
-                    let right = obj_pool.right[root].unwrap();
-
-                    let mut csg_xs = Vec::new();
-                    intersect_rec(obj_pool, left, &ray, &mut csg_xs);
-                    intersect_rec(obj_pool, right, &ray, &mut csg_xs);
-                    csg_xs.sort_by(|x1, x2| x1.t.partial_cmp(&x2.t).unwrap());
-
-                    let mut in_left = false;
-                    let mut in_right = false;
-                    for x in csg_xs.iter() {
-                        let left_hit = obj_pool.includes(x.obj, left);
-                        let include_x = match op {
-                            CsgOp::Union => left_hit && !in_right || !left_hit && !in_left,
-                            CsgOp::Intersection => left_hit && in_right || !left_hit && in_left,
-                            CsgOp::Difference => left_hit && !in_right || !left_hit && in_left,
-                        };
-                        if include_x {
-                            xs.push(*x);
-                        }
-                        if left_hit {
-                            in_left = !in_left;
-                        } else {
-                            in_right = !in_right;
-                        }
-                    }
+                ObjTag::Csg(_) => flat.push(FlatObject {
+                    obj,
+                    transform_inverse: to_parent,
+                    shape: None,
+                    bounds: self
+                        .local_bounds(obj)
+                        .and_then(|bounds| bounds.transform(to_parent.inverse())),
+                }),
+            }
+        }
+        flat
+    }
+
+    /// Whether a point in world space is inside an object, which must be a
+    /// closed solid, or a group or CSG of them. Planes, and cylinders and cones
+    /// without caps, have no inside.
+    ///
+    /// A ray leaving the point crosses the object's surface an odd number of
+    /// times if the point is inside. Rays grazing an edge can miscount, so
+    /// three rays in unrelated directions vote.
+    pub fn contains(&self, obj: Obj, point: Tuple, xs: &mut Vec<Intersection>) -> bool {
+        let directions = [
+            Tuple::vector(0.5377, 0.8141, 0.2188),
+            Tuple::vector(-0.6893, 0.3129, -0.6534),
+            Tuple::vector(0.2417, -0.7631, 0.5993),
+        ];
+        let votes = directions
+            .iter()
+            .filter(|&&direction| {
+                self.intersect_object(obj, &Ray::new(point, direction), xs);
+                xs.iter().filter(|x| x.t > 0.0).count() % 2 == 1
+            })
+            .count();
+        votes >= 2
+    }
+
+    /// The smallest axis aligned box, in world space, containing an object's
+    /// bounding box, or `None` if the object goes on forever.
+    pub fn bounds(&self, obj: Obj) -> Option<Bounds> {
+        let bounds = self.local_bounds(obj)?;
+        self.ancestors(obj)
+            .iter()
+            .try_fold(bounds, |bounds, &ancestor| {
+                bounds.transform(self.transform_inverse[ancestor].inverse())
+            })
+    }
+
+    /// Every object's bounds in world space, as `bounds` gives them, by id.
+    pub fn world_bounds(&self) -> Vec<Option<Bounds>> {
+        (0..self.next_id()).map(|obj| self.bounds(obj)).collect()
+    }
+
+    /// Marks the objects, by id, whose world space bounds from `world_bounds`
+    /// some rays can't reach, as judged by `outside`, so that
+    /// `intersect_into_culled` can skip them and everything in them. Objects
+    /// going on forever, and objects traced as their stand-ins, are kept.
+    pub fn cull<F>(&self, bounds: &[Option<Bounds>], outside: F) -> Vec<bool>
+    where
+        F: Fn(&Bounds) -> bool,
+    {
+        let mut culled = vec![false; self.next_id()];
+        let mut pending: Vec<Obj> = self.roots().collect();
+        while let Some(obj) = pending.pop() {
+            if self.proxy[obj].is_some() && self.proxy_active[obj] {
+                continue;
+            }
+            if bounds[obj].as_ref().is_some_and(&outside) {
+                culled[obj] = true;
+            } else if let ObjTag::Group = self.tag[obj] {
+                let mut child = self.left[obj];
+                while let Some(c) = child {
+                    pending.push(c);
+                    child = self.right[c];
                 }
             }
         }
+        culled
+    }
 
-        let mut xs = Vec::new();
+    /// The smallest axis aligned box containing the bounds of every object
+    /// that doesn't go on forever, or `None` if there are no such objects.
+    pub fn scene_bounds(&self) -> Option<Bounds> {
+        self.roots()
+            .filter_map(|root| self.bounds(root))
+            .reduce(Bounds::merge)
+    }
 
-        for id in 0..self.next_id() {
-            if self.parent[id].is_none() {
-                let root = id;
-                intersect_rec(self, root, ray, &mut xs);
+    /// Adds a floor for the scene to stand on: a flat disk centered under the
+    /// scene's bounds, with its top at their bottom, and wide enough to catch
+    /// the scene's shadows. Objects going on forever, such as planes, are left
+    /// out of the bounds. Add it once the rest of the scene is in place.
+    ///
+    /// Returns the disk, or `None` if there's nothing bounded to put it under.
+    /// Bounds are boxes, so the disk can sit a little below rotated objects.
+    pub fn add_ground_disk(&mut self, material: impl Into<Option<Material>>) -> Option<Obj> {
+        let bounds = self.scene_bounds()?;
+        let (min, size) = (bounds.min, bounds.size());
+        let across = (size.x() * size.x() + size.z() * size.z())
+            .sqrt()
+            .max(size.y());
+        let radius = GROUND_DISK_SCALE * if across > 0.0 { across } else { 1.0 };
+
+        let shape = Shape::Cylinder {
+            y_min: -1.0,
+            y_max: 0.0,
+            closed: true,
+        };
+        let center = bounds.center();
+        let transform = Matrix::translation(center.x(), min.y(), center.z())
+            * Matrix::scaling(radius, radius * GROUND_DISK_THICKNESS, radius);
+        Some(self.add_shape(shape, transform, material))
+    }
+
+    /// Gives an object a simplified stand-in, traced instead of the object
+    /// while it covers fewer than `min_size` pixels across the image, as
+    /// chosen by `select_lod`. Far away detail then costs far less to trace.
+    ///
+    /// The proxy is placed in the object's space and inherits its material,
+    /// unless it has one of its own. It becomes part of the object, so isn't
+    /// traced on its own. Panics if the proxy already has a parent.
+    pub fn set_proxy(&mut self, obj: Obj, proxy: Obj, min_size: f64) {
+        assert!(
+            self.parent[proxy].is_none(),
+            "object {} is already part of another object",
+            proxy
+        );
+        self.parent[proxy] = Some(obj);
+        self.proxy[obj] = Some((proxy, min_size));
+        self.proxy_active[obj] = false;
+    }
+
+    /// Gives an object a sphere stand-in, stretched to fill its bounding box.
+    /// See `set_proxy`. Returns the sphere, or `None` if the object goes on
+    /// forever.
+    pub fn add_sphere_proxy(
+        &mut self,
+        obj: Obj,
+        min_size: f64,
+        material: impl Into<Option<Material>>,
+    ) -> Option<Obj> {
+        let bounds = self.object_bounds(obj).filter(Bounds::is_finite)?;
+        let (center, size) = (bounds.center(), bounds.size());
+        let radius = |extent: f64| (extent / 2.0).max(1e-3);
+        let transform = Matrix::translation(center.x(), center.y(), center.z())
+            * Matrix::scaling(radius(size.x()), radius(size.y()), radius(size.z()));
+        let sphere = self.add_shape(Shape::Sphere, transform, material);
+        self.set_proxy(obj, sphere, min_size);
+        Some(sphere)
+    }
+
+    /// Chooses whether each object with a stand-in is traced as itself or as
+    /// its stand-in, from how many pixels across the image its world space
+    /// bounds cover, as measured by `screen_size`. Objects going on forever
+    /// are always traced as themselves. Returns whether any choice changed.
+    pub fn select_lod<F>(&mut self, screen_size: F) -> bool
+    where
+        F: Fn(&Bounds) -> f64,
+    {
+        let mut changed = false;
+        for obj in 0..self.next_id() {
+            let active = match self.proxy[obj] {
+                Some((_, min_size)) => self
+                    .bounds(obj)
+                    .is_some_and(|bounds| screen_size(&bounds) < min_size),
+                None => false,
+            };
+            changed |= active != self.proxy_active[obj];
+            self.proxy_active[obj] = active;
+        }
+        changed
+    }
+
+    /// Bounds of an object in its own space.
+    fn object_bounds(&self, obj: Obj) -> Option<Bounds> {
+        match &self.tag[obj] {
+            ObjTag::Shape(shape) => Some(shape.bounds()),
+            // A union's surface takes in both operands, which are the CSG's
+            // left and right links rather than a list of children.
+            ObjTag::Csg(_) => {
+                let left = self.local_bounds(self.left[obj]?)?;
+                let right = self.local_bounds(self.right[obj]?)?;
+                Some(left.merge(right))
+            }
+            ObjTag::Group => {
+                let mut bounds: Option<Bounds> = None;
+                let mut child = self.left[obj];
+                while let Some(c) = child {
+                    let c_bounds = self.local_bounds(c)?;
+                    bounds = Some(match bounds {
+                        Some(bounds) => bounds.merge(c_bounds),
+                        None => c_bounds,
+                    });
+                    child = self.right[c];
+                }
+                // An empty group has nothing to bound, so an empty box at its
+                // origin will do.
+                Some(bounds.unwrap_or_else(|| {
+                    let origin = Tuple::point(0.0, 0.0, 0.0);
+                    Bounds::new(origin, origin)
+                }))
             }
         }
+    }
 
-        xs.sort_by(|x1, x2| x1.t.partial_cmp(&x2.t).unwrap());
+    /// Bounds of an object in its parent's space.
+    fn local_bounds(&self, obj: Obj) -> Option<Bounds> {
+        self.object_bounds(obj)?
+            .transform(self.transform_inverse[obj].inverse())
+    }
 
-        xs
+    /// An object's parent, grandparent, and so on up to its root.
+    fn ancestors(&self, obj: Obj) -> Vec<Obj> {
+        let mut ancestors = Vec::new();
+        let mut parent = self.parent[obj];
+        while let Some(p) = parent {
+            ancestors.push(p);
+            parent = self.parent[p];
+        }
+        ancestors
     }
 
-    pub fn normal_at(&self, obj: Obj, world_point: Tuple) -> Tuple {
+    /// Normal at the point where a ray hit an object.
+    pub fn normal_at(&self, hit: &Intersection, world_point: Tuple) -> Tuple {
+        let obj = hit.obj;
         match &self.tag[obj] {
             ObjTag::Shape(shape) => {
                 let object_point = self.world_to_object(obj, world_point);
-                let object_normal = shape.normal_at(object_point);
+                let object_normal = shape.normal_at(object_point, hit);
+                let object_normal = match &self.material(obj).bump {
+                    Some(bump) => bump.perturb(object_point, object_normal),
+                    None => object_normal,
+                };
                 self.normal_to_world(obj, object_normal)
             }
             ObjTag::Group => panic!("cannot take a normal of a group object"),
@@ -181,6 +832,54 @@ impl ObjPool {
         }
     }
 
+    /// Normal of the true surface where a ray hit an object, facing out of
+    /// the solid it bounds: without bump maps or a smooth triangle's
+    /// interpolation, and turned around for shapes subtracted by CSG
+    /// differences, whose solid is on their inside.
+    pub fn geometric_normal_at(&self, hit: &Intersection, world_point: Tuple) -> Tuple {
+        let obj = hit.obj;
+        let shape = match &self.tag[obj] {
+            ObjTag::Shape(shape) => shape,
+            ObjTag::Group => panic!("cannot take a normal of a group object"),
+            ObjTag::Csg(_) => unreachable!("intersections are always with shapes"),
+        };
+        let object_point = self.world_to_object(obj, world_point);
+        let normal = self.normal_to_world(obj, shape.geometric_normal_at(object_point, hit));
+        if self.subtracted(obj) {
+            -normal
+        } else {
+            normal
+        }
+    }
+
+    /// Whether an object is turned inside out by being subtracted by an odd
+    /// number of the CSG differences above it.
+    fn subtracted(&self, obj: Obj) -> bool {
+        let mut subtracted = false;
+        let mut child = obj;
+        while let Some(parent) = self.parent[child] {
+            if matches!(self.tag[parent], ObjTag::Csg(CsgOp::Difference))
+                && self.right[parent] == Some(child)
+            {
+                subtracted = !subtracted;
+            }
+            child = parent;
+        }
+        subtracted
+    }
+
+    /// Texture coordinates of a point on a shape. See `Shape::uv_at`, and
+    /// `cut_uv` for cut faces.
+    pub fn uv_at(&self, obj: Obj, world_point: Tuple) -> Option<SurfaceUv> {
+        if let Some(uv) = self.cut_uv(obj, world_point) {
+            return Some(uv);
+        }
+        match &self.tag[obj] {
+            ObjTag::Shape(shape) => shape.uv_at(self.world_to_object(obj, world_point)),
+            _ => None,
+        }
+    }
+
     pub fn world_to_object(&self, obj: Obj, point: Tuple) -> Tuple {
         let point = if let Some(parent) = self.parent[obj] {
             self.world_to_object(parent, point)
@@ -205,39 +904,52 @@ impl ObjPool {
         }
     }
 
-    fn includes(&self, search_target: Obj, node: Obj) -> bool {
-        if search_target == node {
-            return true;
-        }
+    /// Marks an object as an instance, such as one of many copies of a model,
+    /// so everything in it shares one variation.
+    pub fn mark_instance(&mut self, obj: Obj) {
+        self.instance[obj] = true;
+    }
 
-        match &self.tag[node] {
-            ObjTag::Shape(_) => false,
-            ObjTag::Group => {
-                let mut child = self.left[node];
-                while let Some(c) = child {
-                    if self.includes(search_target, c) {
-                        return true;
-                    }
-                    child = self.right[c];
-                }
-                false
+    /// The instance an object is part of: its nearest ancestor marked as one,
+    /// or else the outermost CSG object it belongs to, or else the object
+    /// itself.
+    pub fn instance_of(&self, obj: Obj) -> Obj {
+        let mut instance = obj;
+        let mut node = Some(obj);
+        while let Some(o) = node {
+            if self.instance[o] {
+                return o;
             }
-            ObjTag::Csg(_) => {
-                if let Some(left) = self.left[node] {
-                    if self.includes(search_target, left) {
-                        return true;
-                    }
-                }
+            if let ObjTag::Csg(_) = self.tag[o] {
+                instance = o;
+            }
+            node = self.parent[o];
+        }
+        instance
+    }
 
-                if let Some(right) = self.right[node] {
-                    if self.includes(search_target, right) {
-                        return true;
-                    }
-                }
+    /// Random number in [0, 1) that is the same all over an object's instance,
+    /// and stays the same from render to render with the pool's seed.
+    pub fn variation(&self, obj: Obj) -> f64 {
+        Pcg32::for_key(self.seed, self.instance_of(obj)).gen()
+    }
 
-                false
+    /// Whether an object is the node itself or one of its descendants.
+    pub fn includes(&self, search_target: Obj, node: Obj) -> bool {
+        let mut obj = Some(search_target);
+        while let Some(o) = obj {
+            if o == node {
+                return true;
             }
+            obj = self.parent[o];
         }
+        false
+    }
+}
+
+impl Default for ObjPool {
+    fn default() -> Self {
+        ObjPool::new()
     }
 }
 
@@ -254,9 +966,9 @@ impl fmt::Display for ObjPool {
             }
 
             match &obj_pool.tag[root] {
-                ObjTag::Shape(shape) => writeln!(f, "{:?}", shape)?,
+                ObjTag::Shape(shape) => writeln!(f, "[{}] {:?}", root, shape)?,
                 ObjTag::Group => {
-                    writeln!(f, "Group")?;
+                    writeln!(f, "[{}] Group", root)?;
                     let mut child = obj_pool.left[root];
                     while let Some(c) = child {
                         write_rec(obj_pool, f, c, depth + 1)?;
@@ -264,15 +976,19 @@ impl fmt::Display for ObjPool {
                     }
                 }
                 ObjTag::Csg(op) => {
-                    writeln!(f, "CSG({:?})", op)?;
-                    if let Some(left) = obj_pool.left[root] {
-                        write_rec(obj_pool, f, left, depth + 1)?;
-                    }
-                    if let Some(right) = obj_pool.right[root] {
-                        write_rec(obj_pool, f, right, depth + 1)?;
+                    writeln!(f, "[{}] CSG({:?})", root, op)?;
+                    for operand in obj_pool.csg_operands(root) {
+                        write_rec(obj_pool, f, operand, depth + 1)?;
                     }
                 }
             }
+            if let Some((proxy, min_size)) = obj_pool.proxy[root] {
+                for _ in 0..depth + 1 {
+                    write!(f, "    ")?;
+                }
+                writeln!(f, "stand-in below {} pixels:", min_size)?;
+                write_rec(obj_pool, f, proxy, depth + 2)?;
+            }
 
             Ok(())
         }
@@ -0,0 +1,161 @@
+use crate::algorithm::cosine_hemisphere;
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::object::Intersection;
+use crate::ray::Ray;
+use crate::rng::Pcg32;
+use crate::tuple::Tuple;
+use crate::world::{Computations, World};
+
+pub use crate::algorithm::cosine_hemisphere as cosine_weighted;
+
+/// Darkens the ambient light reaching creases, corners, and contact points by
+/// how much of the sky above a point nearby surfaces hide.
+#[derive(Copy, Clone, Debug)]
+pub struct AmbientOcclusion {
+    /// Rays cast per shaded point. More rays give smoother results.
+    pub samples: usize,
+
+    /// How far away surfaces can be and still occlude a point.
+    pub distance: f64,
+}
+
+impl AmbientOcclusion {
+    pub fn new(samples: usize, distance: f64) -> Self {
+        AmbientOcclusion { samples, distance }
+    }
+
+    /// Directions to cast occlusion rays in from a point, spread over the
+    /// hemisphere around its normal with more toward the normal, where
+    /// occluders hide the most light.
+    ///
+    /// The directions are random, but seeded by the render's seed and the
+    /// point, so a render comes out the same however its pixels are divided
+    /// among threads.
+    pub fn directions(
+        &self,
+        seed: u64,
+        point: Tuple,
+        normal: Tuple,
+    ) -> impl Iterator<Item = Tuple> {
+        let key = (
+            point.x().to_bits(),
+            point.y().to_bits(),
+            point.z().to_bits(),
+        );
+        let mut rng = Pcg32::for_key(seed, key);
+
+        (0..self.samples).map(move |_| cosine_hemisphere(normal, &mut rng))
+    }
+}
+
+/// Applies ambient occlusion to an image rendered without it, tracing the
+/// occlusion only once per `factor` by `factor` block of pixels. Much faster
+/// for previews, at the cost of detail in small creases.
+///
+/// Each pixel takes the occlusion of the nearby blocks whose surfaces match
+/// its own in depth and orientation, a joint bilateral upsample, so occlusion
+/// doesn't bleed across the edges of objects. Pixels whose surface matches no
+/// nearby block are traced on their own. Only what camera rays see directly is
+/// occluded, not what is seen in reflections or through transparent surfaces.
+pub fn apply_coarse(
+    image: &mut Canvas,
+    camera: &Camera,
+    world: &World,
+    ao: &AmbientOcclusion,
+    factor: usize,
+) {
+    let factor = factor.max(1);
+    let width = camera.hsize().div_ceil(factor);
+    let height = camera.vsize().div_ceil(factor);
+    let mut xs = Vec::new();
+
+    // Occlusion at the center of each block. Each block stands in for many
+    // pixels, so it casts more rays to keep its noise from showing as blotches.
+    let block_ao = AmbientOcclusion::new(ao.samples * factor, ao.distance);
+    let center = factor as f64 / 2.0;
+    let mut blocks = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let block = camera
+                .ray_for_pixel(x * factor, y * factor, center, center)
+                .and_then(|ray| {
+                    let (guide, comps) = guide_at(world, &ray, &mut xs)?;
+                    let exposure = world.exposure(&block_ao, &comps, &mut xs);
+                    Some((guide, exposure))
+                });
+            blocks.push(block);
+        }
+    }
+
+    for py in 0..camera.vsize() {
+        for px in 0..camera.hsize() {
+            let ray = match camera.ray_for_pixel(px, py, 0.5, 0.5) {
+                Some(ray) => ray,
+                None => continue,
+            };
+            let (guide, comps) = match guide_at(world, &ray, &mut xs) {
+                Some(hit) => hit,
+                None => continue,
+            };
+
+            // The four blocks around the pixel, weighted bilinearly by distance
+            // and by how well their surfaces match the pixel's.
+            let bx = (px as f64 + 0.5) / factor as f64 - 0.5;
+            let by = (py as f64 + 0.5) / factor as f64 - 0.5;
+            let (x0, y0) = (bx.floor(), by.floor());
+            let mut total_weight = 0.0;
+            let mut total_exposure = 0.0;
+            for (x, wx) in [(x0, 1.0 - (bx - x0)), (x0 + 1.0, bx - x0)] {
+                for (y, wy) in [(y0, 1.0 - (by - y0)), (y0 + 1.0, by - y0)] {
+                    let x = (x.max(0.0) as usize).min(width - 1);
+                    let y = (y.max(0.0) as usize).min(height - 1);
+                    if let Some((block, exposure)) = &blocks[y * width + x] {
+                        let weight = wx * wy * guide.similarity(block);
+                        total_weight += weight;
+                        total_exposure += weight * exposure;
+                    }
+                }
+            }
+            let exposure = if total_weight > 1e-3 {
+                total_exposure / total_weight
+            } else {
+                world.exposure(ao, &comps, &mut xs)
+            };
+
+            let occluded = world.ambient_reflected(&comps, &mut xs) * (1.0 - exposure);
+            let color = image.pixel_at(px, py) - occluded;
+            let color = Color::new(
+                color.red.max(0.0),
+                color.green.max(0.0),
+                color.blue.max(0.0),
+            );
+            image.write_pixel(px, py, color);
+        }
+    }
+}
+
+/// Depth and orientation of the surface a camera ray sees first.
+struct Guide {
+    depth: f64,
+    normal: Tuple,
+}
+
+impl Guide {
+    /// How alike two surfaces are, from 0.0 to 1.0 for the same surface.
+    fn similarity(&self, other: &Guide) -> f64 {
+        let depth = (self.depth - other.depth) / (0.05 * self.depth);
+        let normal = self.normal.dot(other.normal).max(0.0).powi(8);
+        (-depth * depth).exp() * normal
+    }
+}
+
+fn guide_at(world: &World, ray: &Ray, xs: &mut Vec<Intersection>) -> Option<(Guide, Computations)> {
+    let comps = world.hit(ray, xs)?;
+    let guide = Guide {
+        depth: comps.t,
+        normal: comps.normalv,
+    };
+    Some((guide, comps))
+}
@@ -0,0 +1,199 @@
+use crate::camera::PathRecord;
+use crate::tuple::Tuple;
+use crate::world::VertexKind;
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Every kind of path vertex, in the order of their codes in PLY files.
+const KINDS: [VertexKind; 6] = [
+    VertexKind::Camera,
+    VertexKind::Diffuse,
+    VertexKind::Reflection,
+    VertexKind::Refraction,
+    VertexKind::Absorbed,
+    VertexKind::Escaped,
+];
+
+/// Collects light paths shown to `Camera::set_on_path`, and saves them as
+/// polylines to look at in a 3D viewer along with the scene. Share it with
+/// the camera in an `Arc`.
+///
+/// Each path is a line through its vertices, colored by what happened at
+/// each: white at the camera, orange where it scattered diffusely, blue
+/// where it reflected, cyan where it refracted, red where it ended on a
+/// surface, and gray where it left the scene. Paths that left the scene are
+/// drawn out to the escape length past their last surface.
+pub struct PathLog {
+    paths: Mutex<Vec<PathRecord>>,
+    escape_length: f64,
+}
+
+impl PathLog {
+    pub fn new(escape_length: f64) -> Self {
+        PathLog {
+            paths: Mutex::new(Vec::new()),
+            escape_length,
+        }
+    }
+
+    pub fn record(&self, path: &PathRecord) {
+        self.paths.lock().unwrap().push(path.clone());
+    }
+
+    /// Number of paths recorded so far.
+    pub fn len(&self) -> usize {
+        self.paths.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Saves the paths in the format given by the file's extension: `.obj`
+    /// for Wavefront OBJ, with vertex colors, or `.ply` for ASCII PLY, which
+    /// also gives each vertex its kind and its path's brightness to filter
+    /// by.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        let mut file = match extension.as_deref() {
+            Some("obj") | Some("ply") => BufWriter::new(File::create(path)?),
+            _ => {
+                return Err(format!(
+                    "unknown path format for {}; use .obj or .ply",
+                    path.display()
+                )
+                .into())
+            }
+        };
+        if extension.as_deref() == Some("ply") {
+            self.write_ply(&mut file)?;
+        } else {
+            self.write_obj(&mut file)?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn write_obj(&self, w: &mut impl Write) -> io::Result<()> {
+        let paths = self.sorted_paths();
+        writeln!(w, "# {} light paths", paths.len())?;
+        let mut first = 1;
+        for path in &paths {
+            let c = path.radiance;
+            writeln!(w, "o path_{}_{}_{}", path.x, path.y, path.sample)?;
+            writeln!(w, "# radiance {} {} {}", c.red, c.green, c.blue)?;
+            let points = self.points(path);
+            for (point, kind) in &points {
+                let (r, g, b) = kind_color(*kind);
+                writeln!(
+                    w,
+                    "v {} {} {} {} {} {}",
+                    point.x(),
+                    point.y(),
+                    point.z(),
+                    r as f64 / 255.0,
+                    g as f64 / 255.0,
+                    b as f64 / 255.0
+                )?;
+            }
+            if points.len() > 1 {
+                let indices: Vec<String> = (first..first + points.len())
+                    .map(|i| i.to_string())
+                    .collect();
+                writeln!(w, "l {}", indices.join(" "))?;
+            }
+            first += points.len();
+        }
+        Ok(())
+    }
+
+    pub fn write_ply(&self, w: &mut impl Write) -> io::Result<()> {
+        let paths = self.sorted_paths();
+        let points: Vec<_> = paths.iter().map(|path| self.points(path)).collect();
+        let vertices: usize = points.iter().map(Vec::len).sum();
+        let edges: usize = points.iter().map(|p| p.len().saturating_sub(1)).sum();
+
+        writeln!(w, "ply")?;
+        writeln!(w, "format ascii 1.0")?;
+        writeln!(w, "comment {} light paths", paths.len())?;
+        for (code, kind) in KINDS.iter().enumerate() {
+            writeln!(w, "comment kind {} is {:?}", code, kind)?;
+        }
+        writeln!(w, "element vertex {}", vertices)?;
+        for property in &["x", "y", "z"] {
+            writeln!(w, "property float {}", property)?;
+        }
+        for property in &["red", "green", "blue", "kind"] {
+            writeln!(w, "property uchar {}", property)?;
+        }
+        writeln!(w, "property int path")?;
+        writeln!(w, "property float luminance")?;
+        writeln!(w, "element edge {}", edges)?;
+        writeln!(w, "property int vertex1")?;
+        writeln!(w, "property int vertex2")?;
+        writeln!(w, "end_header")?;
+
+        for (i, (path, points)) in paths.iter().zip(&points).enumerate() {
+            let luminance = path.radiance.luminance();
+            for (point, kind) in points {
+                let (r, g, b) = kind_color(*kind);
+                let code = KINDS.iter().position(|k| k == kind).unwrap();
+                writeln!(
+                    w,
+                    "{} {} {} {} {} {} {} {} {}",
+                    point.x(),
+                    point.y(),
+                    point.z(),
+                    r,
+                    g,
+                    b,
+                    code,
+                    i,
+                    luminance
+                )?;
+            }
+        }
+        let mut first = 0;
+        for points in &points {
+            for i in first + 1..first + points.len() {
+                writeln!(w, "{} {}", i - 1, i)?;
+            }
+            first += points.len();
+        }
+        Ok(())
+    }
+
+    /// The recorded paths in image order, since render threads record them
+    /// in whatever order they finish.
+    fn sorted_paths(&self) -> Vec<PathRecord> {
+        let mut paths = self.paths.lock().unwrap().clone();
+        paths.sort_by_key(|path| (path.y, path.x, path.sample));
+        paths
+    }
+
+    /// A path's vertices to draw, with escaping ends pushed out to the
+    /// escape length.
+    fn points(&self, path: &PathRecord) -> Vec<(Tuple, VertexKind)> {
+        let mut points: Vec<_> = path.vertices.iter().map(|v| (v.point, v.kind)).collect();
+        if let [.., (from, _), (to, VertexKind::Escaped)] = points.as_mut_slice() {
+            *to = *from + (*to - *from) * self.escape_length;
+        }
+        points
+    }
+}
+
+fn kind_color(kind: VertexKind) -> (u8, u8, u8) {
+    match kind {
+        VertexKind::Camera => (255, 255, 255),
+        VertexKind::Diffuse => (230, 150, 40),
+        VertexKind::Reflection => (80, 140, 255),
+        VertexKind::Refraction => (40, 220, 200),
+        VertexKind::Absorbed => (220, 40, 40),
+        VertexKind::Escaped => (150, 150, 150),
+    }
+}
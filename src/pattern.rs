@@ -1,26 +1,78 @@
+use crate::canvas::Canvas;
 use crate::color::*;
 use crate::matrix::*;
+use crate::object::Obj;
+use crate::shape::{SurfaceUv, UvRegion};
+use crate::slide::Slide;
 use crate::tuple::*;
 
-pub trait PatternMap {
+pub trait PatternMap: Send + Sync {
     fn color_at(&self, point: Tuple) -> Color;
+
+    /// Color at a point, for maps that also depend on the shape of the
+    /// surface around it.
+    fn color_at_surface(&self, point: Tuple, _surface: &dyn Surface) -> Color {
+        self.color_at(point)
+    }
+}
+
+/// What a pattern can ask about the surface it colors, beyond the point.
+pub trait Surface {
+    /// Mean curvature of the surface around the point, measured across a
+    /// neighborhood of the given radius in world units. Positive on convex
+    /// bumps and edges, negative in concave creases, and zero on flat faces.
+    /// A unit sphere's curvature is 1.
+    fn curvature(&self, radius: f64) -> f64;
+
+    /// Random number in [0, 1) that is the same all over the object's
+    /// instance and differs between instances. See `ObjPool::instance_of`.
+    fn variation(&self) -> f64;
+
+    /// Texture coordinates of the point, on shapes that have them. See
+    /// `Shape::uv_at`.
+    fn uv(&self) -> Option<SurfaceUv>;
+}
+
+/// The coordinate system a pattern is anchored to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PatternSpace {
+    /// Moves with the object the pattern is on.
+    Object,
+
+    /// Fixed in the world, so objects move through the pattern.
+    World,
+
+    /// Moves with another object, such as an empty group used as a locator,
+    /// so one pattern can flow across several objects.
+    Frame(Obj),
 }
 
 pub struct Pattern {
     map: Box<dyn PatternMap>,
     transform_inverse: Matrix<4>,
+    space: PatternSpace,
 }
 
 impl Pattern {
+    pub fn space(&self) -> PatternSpace {
+        self.space
+    }
+
     pub fn color_at_object(&self, object_point: Tuple) -> Color {
         let pattern_point = self.transform_inverse * object_point;
         self.map.color_at(pattern_point)
     }
+
+    pub fn color_at_surface(&self, object_point: Tuple, surface: &dyn Surface) -> Color {
+        let pattern_point = self.transform_inverse * object_point;
+        self.map.color_at_surface(pattern_point, surface)
+    }
 }
 
 pub struct PatternBuilder {
     map: Option<Box<dyn PatternMap>>,
     transform_inverse: Matrix<4>,
+    space: PatternSpace,
 }
 
 impl PatternBuilder {
@@ -28,6 +80,7 @@ impl PatternBuilder {
         PatternBuilder {
             map: None,
             transform_inverse: Matrix::identity(),
+            space: PatternSpace::Object,
         }
     }
 
@@ -37,14 +90,21 @@ impl PatternBuilder {
         Some(Pattern {
             map,
             transform_inverse,
+            space: self.space,
         })
     }
 
+    /// Sets the pattern's transform, relative to its space.
     pub fn transform(mut self, transform: Matrix<4>) -> Self {
         self.transform_inverse = transform.inverse();
         self
     }
 
+    pub fn space(mut self, space: PatternSpace) -> Self {
+        self.space = space;
+        self
+    }
+
     pub fn stripes(mut self, a: Color, b: Color) -> Self {
         let map = Stripes { a, b };
         self.map = Some(Box::new(map));
@@ -68,6 +128,75 @@ impl PatternBuilder {
         self.map = Some(Box::new(map));
         self
     }
+
+    /// Projects an image onto whatever the pattern covers, like a slide
+    /// projector at the origin of pattern space looking toward -z with +y up.
+    /// `field_of_view` spans the image's longer side, and points outside the
+    /// image's frustum, or behind the projector, get the `outside` color.
+    ///
+    /// Aim the projector with the inverse of a view transform, such as
+    /// `transform(Matrix::view_transform(from, to, up).inverse())`, and usually
+    /// in world space so the image stays put as objects move through it. The
+    /// image passes through objects rather than being blocked by them.
+    pub fn projector(mut self, image: Canvas, field_of_view: f64, outside: Color) -> Self {
+        let map = Projector {
+            slide: Slide::new(image, field_of_view),
+            outside,
+        };
+        self.map = Some(Box::new(map));
+        self
+    }
+
+    /// Colors with an image, taking each point's x and y as the image's u and
+    /// v, from (0, 0) at its top left to (1, 1) at its bottom right. It wraps
+    /// around horizontally and stretches its edges vertically. Meant to be
+    /// mapped onto surfaces with `uv_map`.
+    pub fn image(mut self, image: Canvas) -> Self {
+        let map = Image { image };
+        self.map = Some(Box::new(map));
+        self
+    }
+
+    /// Colors cylinders and cones by their texture coordinates, with one
+    /// pattern for the side and one for each cap, such as a label wrapped
+    /// around a can and another on its lid. Each pattern sees a point's
+    /// coordinates as the point (u, v, 0), so an `image` pattern covers its
+    /// whole region, and others can be scaled by their transforms to repeat
+    /// across it. Points on other shapes are colored by the side pattern,
+    /// where they are.
+    pub fn uv_map(mut self, side: Pattern, top: Pattern, bottom: Pattern) -> Self {
+        let map = UvMap { side, top, bottom };
+        self.map = Some(Box::new(map));
+        self
+    }
+
+    /// Gives each instance its own color, picked at random between `a` and
+    /// `b`, so many copies of an object don't look identical.
+    pub fn variation(mut self, a: Color, b: Color) -> Self {
+        let map = Variation { a, b };
+        self.map = Some(Box::new(map));
+        self
+    }
+
+    /// Colors by the surface's curvature measured across `radius`, blending
+    /// from `flat` toward `concave` in creases and toward `convex` on edges
+    /// and bumps. Using the base color for all but `convex` gives worn edges.
+    pub fn curvature(mut self, concave: Color, flat: Color, convex: Color, radius: f64) -> Self {
+        let map = Curvature {
+            concave,
+            flat,
+            convex,
+            radius,
+        };
+        self.map = Some(Box::new(map));
+        self
+    }
+}
+
+impl Default for PatternBuilder {
+    fn default() -> Self {
+        PatternBuilder::new()
+    }
 }
 
 struct Stripes {
@@ -127,3 +256,89 @@ impl PatternMap for Checkers {
         }
     }
 }
+
+struct Curvature {
+    concave: Color,
+    flat: Color,
+    convex: Color,
+    radius: f64,
+}
+
+impl PatternMap for Curvature {
+    fn color_at(&self, _point: Tuple) -> Color {
+        self.flat
+    }
+
+    fn color_at_surface(&self, _point: Tuple, surface: &dyn Surface) -> Color {
+        // Curvature is the inverse of the radius of the curve, so a curve as
+        // tight as the measuring radius gets the full color.
+        let t = (surface.curvature(self.radius) * self.radius).clamp(-1.0, 1.0);
+        if t < 0.0 {
+            self.flat + (self.concave - self.flat) * -t
+        } else {
+            self.flat + (self.convex - self.flat) * t
+        }
+    }
+}
+
+struct Variation {
+    a: Color,
+    b: Color,
+}
+
+impl PatternMap for Variation {
+    fn color_at(&self, _point: Tuple) -> Color {
+        self.a
+    }
+
+    fn color_at_surface(&self, _point: Tuple, surface: &dyn Surface) -> Color {
+        self.a + (self.b - self.a) * surface.variation()
+    }
+}
+
+struct Projector {
+    slide: Slide,
+    outside: Color,
+}
+
+impl PatternMap for Projector {
+    fn color_at(&self, point: Tuple) -> Color {
+        self.slide.color_at(point).unwrap_or(self.outside)
+    }
+}
+
+struct Image {
+    image: Canvas,
+}
+
+impl PatternMap for Image {
+    fn color_at(&self, point: Tuple) -> Color {
+        self.image.sample(point.x(), point.y(), true)
+    }
+}
+
+struct UvMap {
+    side: Pattern,
+    top: Pattern,
+    bottom: Pattern,
+}
+
+impl PatternMap for UvMap {
+    fn color_at(&self, point: Tuple) -> Color {
+        self.side.color_at_object(point)
+    }
+
+    fn color_at_surface(&self, point: Tuple, surface: &dyn Surface) -> Color {
+        match surface.uv() {
+            Some(uv) => {
+                let pattern = match uv.region {
+                    UvRegion::Side | UvRegion::Cut => &self.side,
+                    UvRegion::TopCap => &self.top,
+                    UvRegion::BottomCap => &self.bottom,
+                };
+                pattern.color_at_surface(Tuple::point(uv.u, uv.v, 0.0), surface)
+            }
+            None => self.side.color_at_surface(point, surface),
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::grade::Grade;
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+/// Operators compressing scene radiance into the displayable [0, 1] range.
+#[derive(Copy, Clone, Debug, PartialEq, Hash)]
+pub enum ToneMapper {
+    /// Clips anything brighter than white.
+    Clamp,
+
+    /// `x / (1 + x)` per channel, rolling highlights off smoothly.
+    Reinhard,
+
+    /// Narkowicz's fit of the ACES filmic curve, with more contrast than
+    /// Reinhard.
+    Aces,
+}
+
+impl ToneMapper {
+    pub fn apply(&self, color: Color) -> Color {
+        let channel = |x: f64| {
+            let x = x.max(0.0);
+            let y = match self {
+                ToneMapper::Clamp => x,
+                ToneMapper::Reinhard => x / (1.0 + x),
+                ToneMapper::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+            };
+            y.min(1.0)
+        };
+        Color::new(
+            channel(color.red),
+            channel(color.green),
+            channel(color.blue),
+        )
+    }
+}
+
+impl FromStr for ToneMapper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMapper::Clamp),
+            "reinhard" => Ok(ToneMapper::Reinhard),
+            "aces" => Ok(ToneMapper::Aces),
+            _ => Err(format!(
+                "unknown tone mapper '{}', expected clamp, reinhard, or aces",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ToneMapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ToneMapper::Clamp => "clamp",
+            ToneMapper::Reinhard => "reinhard",
+            ToneMapper::Aces => "aces",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Processing applied to a rendered image before it is quantized and written
+/// out. Steps run in the order of the fields.
+pub struct PostProcess {
+    /// Exposure adjustment in stops. Each stop doubles the brightness.
+    pub exposure: f64,
+
+    pub tone_mapper: ToneMapper,
+
+    /// Encode the colors with the sRGB transfer curve, for display on typical
+    /// monitors. Otherwise colors are written linearly.
+    pub srgb: bool,
+
+    /// Color grades, applied in order to the display referred colors: the
+    /// encoded colors with `srgb` set, and otherwise the tone mapped colors,
+    /// clamped to [0, 1]. Grading tools' LUTs expect encoded colors.
+    pub grades: Vec<Grade>,
+}
+
+impl PostProcess {
+    /// Leaves images unchanged, other than clamping them.
+    pub fn new() -> Self {
+        PostProcess {
+            exposure: 0.0,
+            tone_mapper: ToneMapper::Clamp,
+            srgb: false,
+            grades: Vec::new(),
+        }
+    }
+
+    pub fn apply(&self, image: &Canvas) -> Canvas {
+        let mut output = Canvas::new(image.width, image.height);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                output.write_pixel(x, y, self.apply_color(image.pixel_at(x, y)));
+            }
+        }
+        output
+    }
+
+    pub fn apply_color(&self, color: Color) -> Color {
+        let color = self.tone_mapper.apply(color * 2f64.powf(self.exposure));
+        let color = if self.srgb {
+            Color::new(
+                srgb_encode(color.red),
+                srgb_encode(color.green),
+                srgb_encode(color.blue),
+            )
+        } else {
+            let clamp = |x: f64| x.clamp(0.0, 1.0);
+            Color::new(clamp(color.red), clamp(color.green), clamp(color.blue))
+        };
+        self.grades
+            .iter()
+            .fold(color, |color, grade| grade.apply(color))
+    }
+}
+
+/// Hashes everything about the processing that affects the image, for keys
+/// such as the one a render farm checks frames against.
+impl Hash for PostProcess {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.exposure.to_bits().hash(state);
+        self.tone_mapper.hash(state);
+        self.srgb.hash(state);
+        self.grades.hash(state);
+    }
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        PostProcess::new()
+    }
+}
+
+/// The sRGB transfer function, from linear to encoded values in [0, 1].
+pub fn srgb_encode(x: f64) -> f64 {
+    let x = x.clamp(0.0, 1.0);
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grade::Lut;
+    use crate::rng::stable_hash;
+    use crate::testing::{assert_color_near, COLOR_TOLERANCE};
+
+    const IDENTITY_CUBE: &str = "LUT_3D_SIZE 2
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1
+";
+
+    #[test]
+    fn identity_lut_leaves_encoded_colors_unchanged() {
+        for tone_mapper in [ToneMapper::Clamp, ToneMapper::Reinhard, ToneMapper::Aces] {
+            let mut post = PostProcess::new();
+            post.tone_mapper = tone_mapper;
+            post.srgb = true;
+            let colors = [
+                Color::new(0.0, 0.0, 0.0),
+                Color::new(0.02, 0.18, 0.5),
+                Color::new(0.9, 1.5, 4.0),
+            ];
+            let expected: Vec<Color> = colors.iter().map(|c| post.apply_color(*c)).collect();
+
+            post.grades.push(Grade::Lut(
+                Lut::from_cube(IDENTITY_CUBE.as_bytes()).unwrap(),
+            ));
+            for (color, expected) in colors.iter().zip(expected) {
+                assert_color_near(post.apply_color(*color), expected, COLOR_TOLERANCE);
+            }
+        }
+    }
+
+    #[test]
+    fn grades_encoded_colors() {
+        // Doubling gain on an encoded mid gray, not the linear value.
+        let mut post = PostProcess::new();
+        post.srgb = true;
+        post.grades.push(Grade::LiftGammaGain {
+            lift: Color::new(0.0, 0.0, 0.0),
+            gamma: Color::new(1.0, 1.0, 1.0),
+            gain: Color::new(2.0, 2.0, 2.0),
+        });
+        let gray = srgb_encode(0.1);
+        let expected = Color::new(2.0 * gray, 2.0 * gray, 2.0 * gray);
+        assert_color_near(
+            post.apply_color(Color::new(0.1, 0.1, 0.1)),
+            expected,
+            COLOR_TOLERANCE,
+        );
+    }
+
+    #[test]
+    fn hashes_every_setting() {
+        let key = |post: &PostProcess| stable_hash(post);
+        let base = PostProcess::new();
+        assert_eq!(key(&base), key(&PostProcess::new()));
+
+        let mut exposed = PostProcess::new();
+        exposed.exposure = 0.5;
+        let mut encoded = PostProcess::new();
+        encoded.srgb = true;
+        let mut graded = PostProcess::new();
+        graded.grades.push(Grade::Lut(
+            Lut::from_cube(IDENTITY_CUBE.as_bytes()).unwrap(),
+        ));
+        for post in [exposed, encoded, graded] {
+            assert_ne!(key(&post), key(&base));
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::camera::Camera;
+use crate::canvas::{Canvas, Downsample};
+use crate::color::Color;
+use crate::post::PostProcess;
+use crate::util::clamp;
+
+use minifb::{Key, Window, WindowOptions};
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// How often the window is redrawn while waiting on tiles.
+const FRAME_TIME: Duration = Duration::from_millis(30);
+
+/// Largest window opened, in pixels across and down. Larger images are shown
+/// shrunk by a whole factor to fit, so that the window fits on most screens.
+const MAX_WINDOW: (usize, usize) = (1600, 1000);
+
+/// A window showing a render as its tiles come in.
+pub struct Preview {
+    window: Window,
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+
+    /// The image so far, post-processed, and the factor it's shrunk by to
+    /// show it.
+    image: Canvas,
+    factor: usize,
+
+    sender: Sender<(usize, usize, Canvas)>,
+    tiles: Receiver<(usize, usize, Canvas)>,
+}
+
+impl Preview {
+    /// Opens a window for an image of the given size, shrunk to fit in
+    /// `MAX_WINDOW` if it's larger.
+    pub fn open(width: usize, height: usize) -> Result<Self, Box<dyn Error>> {
+        let factor = width
+            .div_ceil(MAX_WINDOW.0)
+            .max(height.div_ceil(MAX_WINDOW.1))
+            .max(1);
+        let image = Canvas::new(width, height);
+        let (width, height) = (width.div_ceil(factor), height.div_ceil(factor));
+        let options = WindowOptions {
+            resize: true,
+            ..WindowOptions::default()
+        };
+        let window = Window::new("raytracer", width, height, options)?;
+        let (sender, tiles) = mpsc::channel();
+        Ok(Preview {
+            window,
+            width,
+            height,
+            pixels: vec![0; width * height],
+            image,
+            factor,
+            sender,
+            tiles,
+        })
+    }
+
+    /// Runs a render on another thread while showing the camera's tiles as
+    /// they're rendered, post-processed for display, and returns the render's
+    /// result. Closing the window doesn't stop the render.
+    pub fn watch<T, F>(&mut self, camera: &mut Camera, post: &PostProcess, render: F) -> T
+    where
+        T: Send,
+        F: FnOnce(&Camera) -> T + Send,
+    {
+        let sender = self.sender.clone();
+        camera.set_on_tile(move |x, y, tile| {
+            // The preview may have been closed and dropped by now.
+            let _ = sender.send((x, y, tile));
+        });
+
+        let camera = &*camera;
+        thread::scope(|s| {
+            let rendering = s.spawn(move || render(camera));
+            while !rendering.is_finished() {
+                self.update(post);
+                thread::sleep(FRAME_TIME);
+            }
+            self.update(post);
+            rendering.join().unwrap()
+        })
+    }
+
+    /// Keeps showing the image until the window is closed or Escape pressed.
+    pub fn wait(&mut self, post: &PostProcess) {
+        while self.window.is_open() && !self.window.is_key_down(Key::Escape) {
+            self.update(post);
+            thread::sleep(FRAME_TIME);
+        }
+    }
+
+    /// Draws the tiles received so far.
+    fn update(&mut self, post: &PostProcess) {
+        let mut received = false;
+        for (x, y, tile) in self.tiles.try_iter() {
+            let tile = post.apply(&tile);
+            for ty in 0..tile.height.min(self.image.height - y) {
+                for tx in 0..tile.width.min(self.image.width - x) {
+                    self.image
+                        .write_pixel(x + tx, y + ty, tile.pixel_at(tx, ty));
+                }
+            }
+            received = true;
+        }
+        if received {
+            let shown = if self.factor > 1 {
+                self.image.downsample(self.factor, Downsample::Box)
+            } else {
+                self.image.clone()
+            };
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    self.pixels[y * self.width + x] = pixel(shown.pixel_at(x, y));
+                }
+            }
+        }
+        if self.window.is_open() {
+            // A failed redraw leaves the last frame up, which is fine for a
+            // preview.
+            let _ = self
+                .window
+                .update_with_buffer(&self.pixels, self.width, self.height);
+        }
+    }
+}
+
+/// Packs a color into a 0RGB pixel, quantized the way images are saved.
+fn pixel(color: Color) -> u32 {
+    let channel = |v: f64| (255.0 * clamp(v, 0.0, 1.0)) as u32;
+    (channel(color.red) << 16) | (channel(color.green) << 8) | channel(color.blue)
+}
@@ -0,0 +1,168 @@
+use rand::{Error, RngCore, SeedableRng};
+
+use std::hash::{Hash, Hasher};
+
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Small, fast, seedable random number generator (PCG-XSH-RR 32).
+///
+/// Every stochastic part of a render draws from one of these, seeded from the
+/// render's seed and what is being sampled, so the same seed always gives the
+/// same image, whatever the thread count.
+#[derive(Clone, Debug)]
+pub struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    /// Generator for one of 2^63 independent streams of a seed.
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Pcg32 {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.step();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.step();
+        rng
+    }
+
+    /// Generator for the stream identified by a key, such as a pixel's
+    /// coordinates and sample number. Keys are hashed the same way by every
+    /// build, so images repeat across Rust versions and platforms.
+    pub fn for_key<K: Hash>(seed: u64, key: K) -> Self {
+        Pcg32::new(seed, stable_hash(&key))
+    }
+
+    fn step(&mut self) {
+        self.state = self
+            .state
+            .wrapping_mul(MULTIPLIER)
+            .wrapping_add(self.increment);
+    }
+}
+
+/// Hash of a value that is the same from build to build, across Rust versions
+/// and platforms, unlike the standard library's, for keys saved to files or
+/// compared between machines. See `StableHasher`.
+pub fn stable_hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = StableHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// FNV-1a hash of a value's bytes, finished with SplitMix64's mix so that
+/// values differing in a bit hash unrelatedly. Integers are hashed as little
+/// endian, and sizes as 64 bits, to hash the same on every platform.
+#[derive(Clone, Debug)]
+pub struct StableHasher(u64);
+
+impl StableHasher {
+    pub fn new() -> Self {
+        StableHasher(FNV_OFFSET)
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher::new()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, n: u16) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl RngCore for Pcg32 {
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.step();
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rotation = (old >> 59) as u32;
+        xorshifted.rotate_right(rotation)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        let high = self.next_u32() as u64;
+        (high << 32) | low
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for Pcg32 {
+    type Seed = [u8; 16];
+
+    /// Takes the seed and the stream, in little endian order.
+    fn from_seed(seed: Self::Seed) -> Self {
+        let mut state = [0; 8];
+        let mut stream = [0; 8];
+        state.copy_from_slice(&seed[..8]);
+        stream.copy_from_slice(&seed[8..]);
+        Pcg32::new(u64::from_le_bytes(state), u64::from_le_bytes(stream))
+    }
+
+    fn seed_from_u64(seed: u64) -> Self {
+        Pcg32::new(seed, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyed_streams_are_the_same_on_every_build() {
+        let mut rng = Pcg32::for_key(7, (3_usize, 4_usize, 5_usize));
+        assert_eq!(rng.next_u32(), 481_795_874);
+    }
+
+    #[test]
+    fn keys_and_seeds_pick_different_streams() {
+        let first = |seed, key| Pcg32::for_key(seed, key).next_u64();
+        assert_ne!(first(0, (3_usize, 4_usize)), first(0, (4_usize, 3_usize)));
+        assert_ne!(first(0, (3_usize, 4_usize)), first(1, (3_usize, 4_usize)));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::camera::SampleRecord;
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Writes samples shown to `Camera::set_on_sample` as CSV, one row per sample
+/// with the columns `x,y,sample,path_length,red,green,blue`. Path lengths are
+/// left empty for samples without one. Share it with the camera in an `Arc`.
+pub struct SampleLog {
+    output: Mutex<Output>,
+}
+
+struct Output {
+    writer: Box<dyn Write + Send>,
+
+    /// The first write that failed. Samples after it are dropped.
+    error: Option<io::Error>,
+}
+
+impl SampleLog {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        let mut output = Output {
+            writer: Box::new(writer),
+            error: None,
+        };
+        output.write(|w| writeln!(w, "x,y,sample,path_length,red,green,blue"));
+        SampleLog {
+            output: Mutex::new(output),
+        }
+    }
+
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let file = File::create(path)?;
+        Ok(SampleLog::new(BufWriter::new(file)))
+    }
+
+    pub fn record(&self, record: &SampleRecord) {
+        let path_length = record
+            .path_length
+            .map_or(String::new(), |length| length.to_string());
+        let c = record.radiance;
+        self.output.lock().unwrap().write(|w| {
+            writeln!(
+                w,
+                "{},{},{},{},{},{},{}",
+                record.x, record.y, record.sample, path_length, c.red, c.green, c.blue
+            )
+        });
+    }
+
+    /// Flushes the samples logged so far, and reports whether any of them
+    /// couldn't be written.
+    pub fn finish(&self) -> Result<(), Box<dyn Error>> {
+        let mut output = self.output.lock().unwrap();
+        if let Some(e) = output.error.take() {
+            return Err(e.into());
+        }
+        output.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Output {
+    fn write(&mut self, write: impl FnOnce(&mut dyn Write) -> io::Result<()>) {
+        if self.error.is_none() {
+            if let Err(e) = write(&mut self.writer) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::rng::Pcg32;
+
+use rand::Rng;
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// Candidates tried for each point of a Poisson disk set, per point already
+/// placed. More spread the points more evenly, and cost more to generate.
+const POISSON_CANDIDATES: usize = 16;
+
+/// Most candidates tried for a point, so large sets still generate quickly.
+/// Their points are packed so close that spacing them perfectly matters less.
+const MAX_POISSON_CANDIDATES: usize = 256;
+
+/// Where in a pixel its samples are taken. Every pixel uses the same offsets,
+/// so these patterns cost nothing per sample, and suit low sample counts where
+/// random offsets would clump.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sampler {
+    /// One sample at the center, and the rest spaced evenly around a circle
+    /// halfway to the pixel's edge.
+    Ring,
+
+    /// Samples spiraling out from the center by the golden angle, spread
+    /// evenly over a disk reaching the pixel's edges.
+    Spiral,
+
+    /// Samples scattered over the pixel with no two closer than they need to
+    /// be, a Poisson disk set. The same set is generated for every render.
+    Poisson,
+}
+
+impl Sampler {
+    /// Offsets of `count` samples within a pixel, from (0, 0) at its top left
+    /// corner to (1, 1) at its bottom right.
+    pub fn offsets(&self, count: usize) -> Vec<(f64, f64)> {
+        match self {
+            Sampler::Ring => RayOffsets::new(count as i64).collect(),
+            Sampler::Spiral => spiral(count),
+            Sampler::Poisson => poisson_disk(count),
+        }
+    }
+}
+
+impl FromStr for Sampler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ring" => Ok(Sampler::Ring),
+            "spiral" => Ok(Sampler::Spiral),
+            "poisson" => Ok(Sampler::Poisson),
+            _ => Err(format!(
+                "unknown sampler '{}', expected ring, spiral, or poisson",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Sampler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Sampler::Ring => "ring",
+            Sampler::Spiral => "spiral",
+            Sampler::Poisson => "poisson",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Iterates through rays generated for a given pixel.
+struct RayOffsets {
+    count: i64,
+    current: i64,
+}
+
+impl RayOffsets {
+    fn new(count: i64) -> Self {
+        let current = count;
+        RayOffsets { count, current }
+    }
+}
+
+impl Iterator for RayOffsets {
+    type Item = (f64, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.current -= 1;
+        match self.current {
+            x if x < 0 => None,
+            0 => Some((0.5, 0.5)),
+            x => {
+                let angle = 2.0 * std::f64::consts::PI * ((x as f64) / (self.count as f64));
+                let u = angle.sin() / 4.0 + 0.5;
+                let v = angle.cos() / 4.0 + 0.5;
+                Some((u, v))
+            }
+        }
+    }
+}
+
+/// Vogel's spiral: each point turns by the golden angle from the last, and
+/// the radius grows so that every point covers the same area of the disk.
+fn spiral(count: usize) -> Vec<(f64, f64)> {
+    let golden_angle = PI * (3.0 - 5.0_f64.sqrt());
+    (0..count)
+        .map(|i| {
+            let radius = 0.5 * ((i as f64 + 0.5) / count as f64).sqrt();
+            let angle = i as f64 * golden_angle;
+            (0.5 + radius * angle.cos(), 0.5 + radius * angle.sin())
+        })
+        .collect()
+}
+
+/// Mitchell's best candidate algorithm: each point is the candidate furthest
+/// from the points before it. Distances wrap around the pixel's edges, so the
+/// set tiles without clumping where neighboring pixels meet.
+fn poisson_disk(count: usize) -> Vec<(f64, f64)> {
+    let mut rng = Pcg32::new(0, 0);
+    let mut points: Vec<(f64, f64)> = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut best = (0.0, 0.0);
+        let mut best_distance = -1.0;
+        let candidates = (POISSON_CANDIDATES * points.len()).min(MAX_POISSON_CANDIDATES) + 1;
+        for _ in 0..candidates {
+            let candidate: (f64, f64) = (rng.gen(), rng.gen());
+            let distance = points
+                .iter()
+                .map(|p| wrapped_distance_squared(*p, candidate))
+                .fold(f64::INFINITY, f64::min);
+            if distance > best_distance {
+                best = candidate;
+                best_distance = distance;
+            }
+        }
+        points.push(best);
+    }
+    points
+}
+
+fn wrapped_distance_squared(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let wrap = |d: f64| {
+        let d = d.abs();
+        d.min(1.0 - d)
+    };
+    let dx = wrap(a.0 - b.0);
+    let dy = wrap(a.1 - b.1);
+    dx * dx + dy * dy
+}
@@ -0,0 +1,201 @@
+use crate::camera::{RenderMode, TileOrder};
+use crate::filter::Filter;
+use crate::post::{PostProcess, ToneMapper};
+use crate::sampler::Sampler;
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Everything about how a scene is rendered, apart from the scene and camera
+/// themselves: how it's sampled and traced, on how many threads, and how the
+/// result is tone mapped. Cameras keep their settings in one of these.
+///
+/// Settings are saved as text, one `name = value` line per setting, with
+/// blank lines and lines starting with `#` ignored. Settings left out of a
+/// file keep their defaults.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    /// Rays traced per pixel. At least one is always traced.
+    pub samples: usize,
+
+    /// Filter weighting samples onto the pixels around them.
+    pub filter: Filter,
+
+    /// Where in each pixel its samples are taken.
+    pub sampler: Sampler,
+
+    /// Shifts each pixel's random numbers by a blue noise mask when path
+    /// tracing. See `Camera::set_blue_noise`.
+    pub blue_noise: bool,
+
+    /// Times a ray may be reflected or refracted, or bounces when path
+    /// tracing.
+    pub max_depth: u8,
+
+    /// Stops following reflections and refractions once they pass on less
+    /// than this fraction of their light to the camera, short of `max_depth`.
+    /// Zero always follows them to `max_depth`. Only applies to Whitted style
+    /// rendering; path tracing ends dim paths by Russian roulette.
+    pub min_throughput: f64,
+
+    pub mode: RenderMode,
+
+    /// Seed for the render's random sampling. Renders with the same seed and
+    /// settings come out identical.
+    pub seed: u64,
+
+    /// Render threads. Zero uses every available core.
+    pub threads: usize,
+
+    /// Runs render threads at the lowest scheduling priority. See
+    /// `Camera::set_low_priority`.
+    pub low_priority: bool,
+
+    /// Width and height of the tiles the image is rendered in. Zero picks a
+    /// size from the image size and thread count.
+    pub tile_size: usize,
+
+    /// Order the tiles are rendered in, which changes how a render in
+    /// progress fills in but not the finished image.
+    pub tile_order: TileOrder,
+
+    /// Skips objects out of reach of each tile's camera rays when finding
+    /// what the rays hit first. See `Camera::set_frustum_culling`.
+    pub frustum_culling: bool,
+
+    /// Exposure adjustment in stops, applied before tone mapping.
+    pub exposure: f64,
+
+    pub tone_mapper: ToneMapper,
+
+    /// Encode the output with the sRGB transfer curve instead of linearly.
+    pub srgb: bool,
+}
+
+impl RenderSettings {
+    pub fn new() -> Self {
+        RenderSettings {
+            samples: 9,
+            filter: Filter::Box,
+            sampler: Sampler::Ring,
+            blue_noise: false,
+            max_depth: 5,
+            min_throughput: 0.0,
+            mode: RenderMode::Whitted,
+            seed: 0,
+            threads: 0,
+            low_priority: false,
+            tile_size: 0,
+            tile_order: TileOrder::Scanline,
+            frustum_culling: true,
+            exposure: 0.0,
+            tone_mapper: ToneMapper::Clamp,
+            srgb: false,
+        }
+    }
+
+    /// Reads settings saved to a file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        text.parse()
+            .map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Saves the settings to a file, in the form `open` reads.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Post-processing for the tone mapping settings, with no color grades.
+    pub fn post_process(&self) -> PostProcess {
+        let mut post = PostProcess::new();
+        post.exposure = self.exposure;
+        post.tone_mapper = self.tone_mapper;
+        post.srgb = self.srgb;
+        post
+    }
+
+    /// Changes the setting with the given name to a value parsed from text.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        fn parse<T: FromStr>(value: &str) -> Result<T, String>
+        where
+            T::Err: fmt::Display,
+        {
+            value.parse().map_err(|e: T::Err| e.to_string())
+        }
+
+        match name {
+            "samples" => self.samples = parse(value)?,
+            "filter" => self.filter = parse(value)?,
+            "sampler" => self.sampler = parse(value)?,
+            "blue_noise" => self.blue_noise = parse(value)?,
+            "max_depth" => self.max_depth = parse(value)?,
+            "min_throughput" => self.min_throughput = parse(value)?,
+            "mode" => self.mode = parse(value)?,
+            "seed" => self.seed = parse(value)?,
+            "threads" => self.threads = parse(value)?,
+            "low_priority" => self.low_priority = parse(value)?,
+            "tile_size" => self.tile_size = parse(value)?,
+            "tile_order" => self.tile_order = parse(value)?,
+            "frustum_culling" => self.frustum_culling = parse(value)?,
+            "exposure" => self.exposure = parse(value)?,
+            "tone_mapper" => self.tone_mapper = parse(value)?,
+            "srgb" => self.srgb = parse(value)?,
+            _ => return Err(format!("unknown setting '{}'", name)),
+        }
+        Ok(())
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings::new()
+    }
+}
+
+impl FromStr for RenderSettings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut settings = RenderSettings::new();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected name = value", number + 1))?;
+            settings
+                .set(name.trim(), value.trim())
+                .map_err(|e| format!("line {}: {}", number + 1, e))?;
+        }
+        Ok(settings)
+    }
+}
+
+impl fmt::Display for RenderSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "samples = {}", self.samples)?;
+        writeln!(f, "filter = {}", self.filter)?;
+        writeln!(f, "sampler = {}", self.sampler)?;
+        writeln!(f, "blue_noise = {}", self.blue_noise)?;
+        writeln!(f, "max_depth = {}", self.max_depth)?;
+        writeln!(f, "min_throughput = {}", self.min_throughput)?;
+        writeln!(f, "mode = {}", self.mode)?;
+        writeln!(f, "seed = {}", self.seed)?;
+        writeln!(f, "threads = {}", self.threads)?;
+        writeln!(f, "low_priority = {}", self.low_priority)?;
+        writeln!(f, "tile_size = {}", self.tile_size)?;
+        writeln!(f, "tile_order = {}", self.tile_order)?;
+        writeln!(f, "frustum_culling = {}", self.frustum_culling)?;
+        writeln!(f, "exposure = {}", self.exposure)?;
+        writeln!(f, "tone_mapper = {}", self.tone_mapper)?;
+        writeln!(f, "srgb = {}", self.srgb)
+    }
+}
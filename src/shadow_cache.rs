@@ -0,0 +1,160 @@
+use crate::tuple::Tuple;
+
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::sync::Mutex;
+
+/// Number of cell levels. Each level's cells are `LEVEL_SCALE` times wider than
+/// the level below it.
+const LEVELS: u8 = 3;
+
+const LEVEL_SCALE: f64 = 4.0;
+
+/// Number of agreeing shadow rays before a cell is trusted to be uniform.
+const TRUSTED_SAMPLES: u32 = 8;
+
+/// Number of independently locked partitions, to reduce contention between
+/// render threads.
+const SHARDS: usize = 64;
+
+/// Memoizes shadow ray results for static scenes.
+///
+/// Space is divided into a hierarchy of grid cells per light. Once enough shadow
+/// rays from a cell agree, the cell is treated as fully lit (or fully shadowed)
+/// and no more rays are traced from it. Cells with disagreeing results lie on a
+/// shadow boundary, and the finer cells inside them are consulted instead.
+///
+/// Cached results depend on the order points are shaded in, so renders using a
+/// cache are not reproducible across thread counts.
+pub struct ShadowCache {
+    cell_size: f64,
+    shards: Vec<Mutex<CellMap>>,
+}
+
+type CellMap = HashMap<CellKey, Cell, BuildHasherDefault<CellHasher>>;
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+struct CellKey {
+    light: usize,
+    level: u8,
+    x: i64,
+    y: i64,
+    z: i64,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Cell {
+    /// Every shadow ray traced from the cell had the same result.
+    Uniform { shadowing: f64, samples: u32 },
+
+    /// The cell is partially shadowed.
+    Boundary,
+}
+
+impl ShadowCache {
+    /// Creates a cache whose finest cells are `cell_size` units wide.
+    pub fn new(cell_size: f64) -> Self {
+        ShadowCache {
+            cell_size,
+            shards: (0..SHARDS).map(|_| Mutex::new(CellMap::default())).collect(),
+        }
+    }
+
+    /// Forgets every cached result, for when the scene changes.
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().clear();
+        }
+    }
+
+    /// How much of a light reaches a point, using `trace` to cast a shadow ray
+    /// only when the cached cells can't answer.
+    pub fn shadowing<F>(&self, point: Tuple, light: usize, trace: F) -> f64
+    where
+        F: FnOnce() -> f64,
+    {
+        for level in (0..LEVELS).rev() {
+            let key = self.key(point, light, level);
+            match self.shard(&key).lock().unwrap().get(&key) {
+                Some(Cell::Uniform { shadowing, samples }) if *samples >= TRUSTED_SAMPLES => {
+                    return *shadowing
+                }
+                Some(Cell::Boundary) => continue,
+                _ => break,
+            }
+        }
+
+        let shadowing = trace();
+        for level in 0..LEVELS {
+            let key = self.key(point, light, level);
+            let mut cells = self.shard(&key).lock().unwrap();
+            let cell = cells.entry(key).or_insert(Cell::Uniform {
+                shadowing,
+                samples: 0,
+            });
+            *cell = match *cell {
+                Cell::Uniform {
+                    shadowing: s,
+                    samples,
+                } if s == shadowing => Cell::Uniform {
+                    shadowing,
+                    samples: samples + 1,
+                },
+                _ => Cell::Boundary,
+            };
+        }
+        shadowing
+    }
+
+    fn key(&self, point: Tuple, light: usize, level: u8) -> CellKey {
+        let size = self.cell_size * LEVEL_SCALE.powi(level as i32);
+        CellKey {
+            light,
+            level,
+            x: (point.x() / size).floor() as i64,
+            y: (point.y() / size).floor() as i64,
+            z: (point.z() / size).floor() as i64,
+        }
+    }
+
+    fn shard(&self, key: &CellKey) -> &Mutex<CellMap> {
+        let mut hasher = CellHasher::default();
+        key.hash(&mut hasher);
+        &self.shards[(hasher.finish() >> 58) as usize % SHARDS]
+    }
+}
+
+/// A fast multiplicative hasher for cell keys. The default SipHash costs about
+/// as much as tracing a shadow ray through a small scene.
+#[derive(Default)]
+struct CellHasher {
+    hash: u64,
+}
+
+impl Hasher for CellHasher {
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_u64(*byte as u64);
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ n).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    fn write_i64(&mut self, n: i64) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_u8(&mut self, n: u8) {
+        self.write_u64(n as u64);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::algorithm::orthonormal_basis;
+use crate::light::Light;
+use crate::object::ObjPool;
+use crate::ray::Ray;
+use crate::tuple::Tuple;
+use crate::util::*;
+
+/// Offsets of the directions a soft shadow averages the map over, a grid
+/// filling about the unit disc.
+const FILTER: [(f64, f64); 21] = [
+    (-0.5, -1.0),
+    (0.0, -1.0),
+    (0.5, -1.0),
+    (-1.0, -0.5),
+    (-0.5, -0.5),
+    (0.0, -0.5),
+    (0.5, -0.5),
+    (1.0, -0.5),
+    (-1.0, 0.0),
+    (-0.5, 0.0),
+    (0.0, 0.0),
+    (0.5, 0.0),
+    (1.0, 0.0),
+    (-1.0, 0.5),
+    (-0.5, 0.5),
+    (0.0, 0.5),
+    (0.5, 0.5),
+    (1.0, 0.5),
+    (-0.5, 1.0),
+    (0.0, 1.0),
+    (0.5, 1.0),
+];
+
+/// Depth maps of the scene as seen from each light, used to approximate
+/// shadows without tracing a shadow ray per shading point.
+///
+/// Only point and spot lights get a map. Shadows from other lights are still
+/// traced.
+///
+/// Shadows are hard unless the lights are given a radius, which softens them
+/// like percentage-closer soft shadows: the map is searched around each point
+/// for what blocks the light, and the shadow's edge is blurred by a penumbra
+/// as wide as a light of that size would cast from the blockers' average
+/// distance. Penumbrae widen away from their blockers, as an area light's do,
+/// for a fraction of the cost of sampling one.
+pub struct ShadowMaps {
+    resolution: usize,
+    light_radius: f64,
+    maps: Vec<Option<CubeDepthMap>>,
+}
+
+impl ShadowMaps {
+    /// Renders a cube depth map, with faces of `resolution` by `resolution`
+    /// texels, for every point and spot light.
+    pub fn new(obj_pool: &ObjPool, lights: &[Light], resolution: usize) -> Self {
+        let maps = lights
+            .iter()
+            .map(|light| match light {
+                Light::Point { position, .. } | Light::Spot { position, .. } => {
+                    Some(CubeDepthMap::new(obj_pool, *position, resolution.max(1)))
+                }
+                _ => None,
+            })
+            .collect();
+        ShadowMaps {
+            resolution,
+            light_radius: 0.0,
+            maps,
+        }
+    }
+
+    /// Resolution of each cube face.
+    pub fn resolution(&self) -> usize {
+        self.resolution
+    }
+
+    /// Radius of the sphere every light is softened as, 0.0 for hard shadows.
+    pub fn light_radius(&self) -> f64 {
+        self.light_radius
+    }
+
+    pub fn set_light_radius(&mut self, light_radius: f64) {
+        self.light_radius = light_radius.max(0.0);
+    }
+
+    /// Fraction of a light reaching a point, or `None` if the light has no map.
+    pub fn shadowing(&self, light: usize, point: Tuple) -> Option<f64> {
+        let map = self.maps.get(light)?.as_ref()?;
+        if self.light_radius > 0.0 {
+            Some(map.soft_shadowing(point, self.light_radius))
+        } else {
+            Some(map.shadowing(point))
+        }
+    }
+}
+
+/// Distances to the nearest shadow casting surface in every direction around a
+/// point, stored on the six faces of a cube.
+struct CubeDepthMap {
+    position: Tuple,
+    resolution: usize,
+    depths: Vec<f64>,
+}
+
+impl CubeDepthMap {
+    fn new(obj_pool: &ObjPool, position: Tuple, resolution: usize) -> Self {
+        let mut depths = Vec::with_capacity(6 * resolution * resolution);
+        for face in 0..6 {
+            for row in 0..resolution {
+                for column in 0..resolution {
+                    let s = 2.0 * (column as f64 + 0.5) / (resolution as f64) - 1.0;
+                    let t = 2.0 * (row as f64 + 0.5) / (resolution as f64) - 1.0;
+                    let direction = face_direction(face, s, t).normalize();
+                    depths.push(nearest_caster(obj_pool, Ray::new(position, direction)));
+                }
+            }
+        }
+        CubeDepthMap {
+            position,
+            resolution,
+            depths,
+        }
+    }
+
+    fn shadowing(&self, point: Tuple) -> f64 {
+        let to_point = point - self.position;
+        let distance = to_point.magnitude();
+        if distance > self.depth(to_point) + self.bias(distance) {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// Percentage-closer soft shadows from a spherical light of some radius
+    /// around the map's position.
+    fn soft_shadowing(&self, point: Tuple, light_radius: f64) -> f64 {
+        let to_point = point - self.position;
+        let distance = to_point.magnitude();
+        let bias = self.bias(distance);
+        let texel_angle = 2.0 / self.resolution as f64;
+
+        // Blockers can be anywhere between the point and the light's disc,
+        // which from the light's center is within about the angle the light
+        // spans from the point.
+        let search = (light_radius / distance).max(texel_angle);
+        let (mut blocker_sum, mut blockers) = (0.0, 0);
+        for depth in self.depths_around(to_point, search) {
+            if depth + bias < distance {
+                blocker_sum += depth;
+                blockers += 1;
+            }
+        }
+        if blockers == 0 {
+            return 1.0;
+        }
+        let blocker = blocker_sum / blockers as f64;
+
+        // Similar triangles from the light's edges past the blockers give how
+        // wide the penumbra is on the receiving surface.
+        let penumbra = light_radius * (distance - blocker) / blocker.max(EPSILON);
+        let filter = (penumbra / distance).max(texel_angle);
+        let samples = FILTER.len() as f64;
+        // Filtered texels see the receiving surface at other depths when it
+        // slopes away from the light, so allow more for them the farther
+        // they reach.
+        let lit = self
+            .depths_around(to_point, filter)
+            .filter(|depth| distance <= depth + bias + distance * filter)
+            .count();
+        lit as f64 / samples
+    }
+
+    /// Depths in the directions of `FILTER`'s offsets around a direction,
+    /// scaled to reach an angle away from it.
+    fn depths_around(&self, direction: Tuple, angle: f64) -> impl Iterator<Item = f64> + '_ {
+        let direction = direction.normalize();
+        let (u, v) = orthonormal_basis(direction);
+        FILTER
+            .iter()
+            .map(move |(x, y)| self.depth(direction + u * (x * angle) + v * (y * angle)))
+    }
+
+    /// Depth stored in the texel a direction from the map's position falls in.
+    fn depth(&self, direction: Tuple) -> f64 {
+        let (face, s, t) = direction_face(direction);
+        let texel = |c: f64| {
+            let i = ((c + 1.0) / 2.0 * self.resolution as f64) as usize;
+            i.min(self.resolution - 1)
+        };
+        self.depths[(face * self.resolution + texel(t)) * self.resolution + texel(s)]
+    }
+
+    /// Neighboring points share a texel, so allow for the depth changing
+    /// across it.
+    fn bias(&self, distance: f64) -> f64 {
+        distance * 4.0 / (self.resolution as f64) + EPSILON
+    }
+}
+
+/// Distance along a ray to the first surface that casts a shadow.
+fn nearest_caster(obj_pool: &ObjPool, ray: Ray) -> f64 {
+    obj_pool
+        .intersect(&ray)
+        .iter()
+        .filter(|x| x.t > EPSILON && obj_pool.material(x.obj).casts_shadow)
+        .map(|x| x.t)
+        .next()
+        .unwrap_or(f64::INFINITY)
+}
+
+/// Faces are numbered 2 * axis + 1 when facing the negative direction of the
+/// axis. The face coordinates (s, t) run along the next two axes in order.
+fn face_direction(face: usize, s: f64, t: f64) -> Tuple {
+    let axis = face / 2;
+    let sign = if face.is_multiple_of(2) { 1.0 } else { -1.0 };
+    let mut elms = [0.0; 4];
+    elms[axis] = sign;
+    elms[(axis + 1) % 3] = s;
+    elms[(axis + 2) % 3] = t;
+    Tuple::from_slice(&elms)
+}
+
+/// Inverse of `face_direction`.
+fn direction_face(direction: Tuple) -> (usize, f64, f64) {
+    let d = direction.elms;
+    let axis = (0..3)
+        .max_by(|a, b| d[*a].abs().partial_cmp(&d[*b].abs()).unwrap())
+        .unwrap();
+    let major = d[axis].abs();
+    let face = 2 * axis + if d[axis] >= 0.0 { 0 } else { 1 };
+    (face, d[(axis + 1) % 3] / major, d[(axis + 2) % 3] / major)
+}
@@ -1,8 +1,11 @@
+use crate::bounds::Bounds;
 use crate::object::{Intersection, Obj};
 use crate::ray::*;
 use crate::tuple::*;
 use crate::util::*;
 
+use std::f64::consts::PI;
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Shape {
     /// The XZ plane.
@@ -25,9 +28,93 @@ pub enum Shape {
         y_max: f64,
         closed: bool,
     },
+
+    /// A flat triangle. Build with `Shape::triangle`, which precomputes the
+    /// edges and normal.
+    Triangle {
+        p1: Tuple,
+        p2: Tuple,
+        p3: Tuple,
+        e1: Tuple,
+        e2: Tuple,
+        normal: Tuple,
+    },
+
+    /// A triangle whose normal is interpolated between normals given at its
+    /// corners, so meshes of them shade smoothly. Build with
+    /// `Shape::smooth_triangle`.
+    SmoothTriangle {
+        p1: Tuple,
+        p2: Tuple,
+        p3: Tuple,
+        e1: Tuple,
+        e2: Tuple,
+        n1: Tuple,
+        n2: Tuple,
+        n3: Tuple,
+    },
+}
+
+/// Parts of a shape's surface, each with its own texture coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UvRegion {
+    Side,
+    TopCap,
+    BottomCap,
+
+    /// A face cut by a CSG difference, textured as a flat cap. Its texture
+    /// coordinates are distances in the difference's space, rather than
+    /// running from 0 to 1.
+    Cut,
+}
+
+/// Where a point is on a shape's surface, for texturing it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SurfaceUv {
+    pub region: UvRegion,
+
+    /// Texture coordinates within the region, from (0, 0) at the top left of
+    /// an image mapped onto it to (1, 1) at the bottom right.
+    pub u: f64,
+    pub v: f64,
 }
 
 impl Shape {
+    pub fn triangle(p1: Tuple, p2: Tuple, p3: Tuple) -> Self {
+        let e1 = p2 - p1;
+        let e2 = p3 - p1;
+        let normal = e2.cross(e1).normalize();
+        Shape::Triangle {
+            p1,
+            p2,
+            p3,
+            e1,
+            e2,
+            normal,
+        }
+    }
+
+    /// Triangle with the normals n1, n2, and n3 at the points p1, p2, and p3.
+    pub fn smooth_triangle(
+        p1: Tuple,
+        p2: Tuple,
+        p3: Tuple,
+        n1: Tuple,
+        n2: Tuple,
+        n3: Tuple,
+    ) -> Self {
+        Shape::SmoothTriangle {
+            p1,
+            p2,
+            p3,
+            e1: p2 - p1,
+            e2: p3 - p1,
+            n1,
+            n2,
+            n3,
+        }
+    }
+
     pub fn intersects(&self, ray: Ray, id: Obj, xs: &mut Vec<Intersection>) {
         match self {
             Shape::Plane => {
@@ -179,7 +266,7 @@ impl Shape {
                     fn check_cap(ray: Ray, t: f64, y: f64) -> bool {
                         let x = ray.origin.x() + t * ray.direction.x();
                         let z = ray.origin.z() + t * ray.direction.z();
-                        x * x + z * z <= y.abs()
+                        x * x + z * z <= y * y
                     }
 
                     let t = (*y_min - ray.origin.y()) / ray.direction.y();
@@ -192,10 +279,84 @@ impl Shape {
                     }
                 }
             }
+
+            Shape::Triangle { p1, e1, e2, .. } | Shape::SmoothTriangle { p1, e1, e2, .. } => {
+                // Möller–Trumbore, which also finds the hit's barycentric
+                // coordinates u and v.
+                let dir_cross_e2 = ray.direction.cross(*e2);
+                let det = e1.dot(dir_cross_e2);
+                if det.abs() < EPSILON {
+                    // ray is parallel to the triangle
+                    return;
+                }
+
+                let f = 1.0 / det;
+                let p1_to_origin = ray.origin - *p1;
+                let u = f * p1_to_origin.dot(dir_cross_e2);
+                if !(0.0..=1.0).contains(&u) {
+                    return;
+                }
+
+                let origin_cross_e1 = p1_to_origin.cross(*e1);
+                let v = f * ray.direction.dot(origin_cross_e1);
+                if v < 0.0 || u + v > 1.0 {
+                    return;
+                }
+
+                let t = f * e2.dot(origin_cross_e1);
+                xs.push(Intersection::new_shape_uv(t, id, u, v));
+            }
         }
     }
 
-    pub fn normal_at(&self, object_point: Tuple) -> Tuple {
+    /// Texture coordinates of a point on the shape, for the shapes that have
+    /// them: cylinders and cones.
+    ///
+    /// Around the side, u runs once around the y axis and v from the top down
+    /// to the bottom, so an image wraps around it upright like a label, its
+    /// middle facing -z. Sides without a top or bottom repeat v every unit
+    /// instead. Each cap fits an image to the disk, the right way round from
+    /// outside, with the image's top toward +z on the top cap and toward -z
+    /// on the bottom one.
+    pub fn uv_at(&self, object_point: Tuple) -> Option<SurfaceUv> {
+        let (y_min, y_max, radius) = match self {
+            Shape::Cylinder { y_min, y_max, .. } => (*y_min, *y_max, 1.0),
+            Shape::Cone { y_min, y_max, .. } => (*y_min, *y_max, object_point.y().abs()),
+            _ => return None,
+        };
+        let (x, y, z) = (object_point.x(), object_point.y(), object_point.z());
+
+        // Caps are told from the side as `normal_at` tells them.
+        let on_cap = x * x + z * z < radius * radius;
+        let cap = |region, flip: f64| SurfaceUv {
+            region,
+            u: 0.5 + x / (2.0 * radius),
+            v: 0.5 + flip * z / (2.0 * radius),
+        };
+        if on_cap && y >= y_max - EPSILON {
+            return Some(cap(UvRegion::TopCap, -1.0));
+        }
+        if on_cap && y <= y_min + EPSILON {
+            return Some(cap(UvRegion::BottomCap, 1.0));
+        }
+
+        // The seam is at the back, +z, so the middle of an image faces -z.
+        let u = 0.5 + x.atan2(-z) / (2.0 * PI);
+        let v = if y_min.is_finite() && y_max.is_finite() && y_max > y_min {
+            (y_max - y) / (y_max - y_min)
+        } else {
+            (-y).rem_euclid(1.0)
+        };
+        Some(SurfaceUv {
+            region: UvRegion::Side,
+            u,
+            v,
+        })
+    }
+
+    /// Normal at a point on the shape, where `hit` is the intersection that
+    /// found the point.
+    pub fn normal_at(&self, object_point: Tuple, hit: &Intersection) -> Tuple {
         match self {
             Shape::Plane => Tuple::vector(0.0, 1.0, 0.0),
 
@@ -245,6 +406,49 @@ impl Shape {
                     Tuple::vector(p.x(), y, p.z()).normalize()
                 }
             }
+
+            Shape::Triangle { normal, .. } => *normal,
+
+            Shape::SmoothTriangle { n1, n2, n3, .. } => {
+                *n2 * hit.u + *n3 * hit.v + *n1 * (1.0 - hit.u - hit.v)
+            }
+        }
+    }
+
+    /// Normal of the shape's true surface at a point, which differs from
+    /// `normal_at` only for smooth triangles, whose shading normals bend away
+    /// from the flat face they lie on.
+    pub fn geometric_normal_at(&self, object_point: Tuple, hit: &Intersection) -> Tuple {
+        match self {
+            Shape::SmoothTriangle { e1, e2, .. } => e2.cross(*e1).normalize(),
+            _ => self.normal_at(object_point, hit),
         }
     }
+
+    /// The smallest axis aligned box containing the shape, in object space.
+    /// Infinite along the axes the shape goes on forever.
+    pub fn bounds(&self) -> Bounds {
+        let inf = f64::INFINITY;
+        let (min, max) = match *self {
+            Shape::Plane => (Tuple::point(-inf, 0.0, -inf), Tuple::point(inf, 0.0, inf)),
+            Shape::Sphere | Shape::Cube => {
+                (Tuple::point(-1.0, -1.0, -1.0), Tuple::point(1.0, 1.0, 1.0))
+            }
+            Shape::Cylinder { y_min, y_max, .. } => (
+                Tuple::point(-1.0, y_min, -1.0),
+                Tuple::point(1.0, y_max, 1.0),
+            ),
+            Shape::Cone { y_min, y_max, .. } => {
+                let radius = y_min.abs().max(y_max.abs());
+                (
+                    Tuple::point(-radius, y_min, -radius),
+                    Tuple::point(radius, y_max, radius),
+                )
+            }
+            Shape::Triangle { p1, p2, p3, .. } | Shape::SmoothTriangle { p1, p2, p3, .. } => {
+                return Bounds::from_points([p1, p2, p3]);
+            }
+        };
+        Bounds::new(min, max)
+    }
 }
@@ -0,0 +1,47 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::tuple::Tuple;
+
+/// An image projected like a slide in a projector, from the origin toward -z
+/// with +y up. Used by projector patterns and spot light cookies.
+pub struct Slide {
+    image: Canvas,
+    half_width: f64,
+    half_height: f64,
+}
+
+impl Slide {
+    /// `field_of_view` is the angle the image's longer side spans.
+    pub fn new(image: Canvas, field_of_view: f64) -> Self {
+        let half_view = (field_of_view / 2.0).tan();
+        let aspect_ratio = image.width as f64 / image.height.max(1) as f64;
+        let (half_width, half_height) = if aspect_ratio >= 1.0 {
+            (half_view, half_view / aspect_ratio)
+        } else {
+            (half_view * aspect_ratio, half_view)
+        };
+        Slide {
+            image,
+            half_width,
+            half_height,
+        }
+    }
+
+    /// Color the image projects onto a point, or `None` if the point is
+    /// outside the image's frustum or behind the projector.
+    pub fn color_at(&self, point: Tuple) -> Option<Color> {
+        let depth = -point.z();
+        if depth <= 0.0 {
+            return None;
+        }
+
+        // Where the point lands on the image plane one unit in front. As with
+        // the camera, +x is on the image's left.
+        let u = (1.0 - point.x() / depth / self.half_width) / 2.0;
+        let v = (1.0 - point.y() / depth / self.half_height) / 2.0;
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+        Some(self.image.sample(u, v, false))
+    }
+}
@@ -0,0 +1,90 @@
+use crate::material::Material;
+use crate::object::{Obj, ObjPool};
+
+/// How objects other than the solo object are shown.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SoloMode {
+    /// Leave them out of the render entirely.
+    Hide,
+
+    /// Shade them with a plain gray material.
+    Clay,
+}
+
+/// Render-time switches for isolating lights and objects while debugging a
+/// scene, without editing it.
+pub struct Solo {
+    /// Only this light, by index, illuminates the scene.
+    pub light: Option<usize>,
+
+    /// Lights, by index, that are switched off.
+    pub muted_lights: Vec<usize>,
+
+    /// Only this object, including anything inside it, is shown normally.
+    pub object: Option<Obj>,
+
+    pub mode: SoloMode,
+
+    /// Objects left out of the render.
+    pub muted_objects: Vec<Obj>,
+
+    clay: Material,
+}
+
+impl Solo {
+    pub fn new() -> Self {
+        Solo {
+            light: None,
+            muted_lights: Vec::new(),
+            object: None,
+            mode: SoloMode::Clay,
+            muted_objects: Vec::new(),
+            clay: Material::clay(),
+        }
+    }
+
+    /// Whether a light contributes to the render.
+    pub fn light_enabled(&self, light: usize) -> bool {
+        self.light.is_none_or(|solo| solo == light) && !self.muted_lights.contains(&light)
+    }
+
+    /// Whether any object may be left out of the render.
+    pub fn hides_objects(&self) -> bool {
+        !self.muted_objects.is_empty() || (self.object.is_some() && self.mode == SoloMode::Hide)
+    }
+
+    /// Whether any object may be shaded with clay instead of its own
+    /// material.
+    pub fn recolors_objects(&self) -> bool {
+        self.object.is_some() && self.mode == SoloMode::Clay
+    }
+
+    /// Whether a shape is part of the render.
+    pub fn object_visible(&self, obj_pool: &ObjPool, obj: Obj) -> bool {
+        let muted = self
+            .muted_objects
+            .iter()
+            .any(|m| obj_pool.includes(obj, *m));
+        let hidden = self.mode == SoloMode::Hide && !self.is_solo(obj_pool, obj);
+        !muted && !hidden
+    }
+
+    /// The material a shape is shaded with.
+    pub fn material<'a>(&'a self, obj_pool: &'a ObjPool, obj: Obj) -> &'a Material {
+        if self.mode == SoloMode::Clay && !self.is_solo(obj_pool, obj) {
+            &self.clay
+        } else {
+            obj_pool.material(obj)
+        }
+    }
+
+    fn is_solo(&self, obj_pool: &ObjPool, obj: Obj) -> bool {
+        self.object.is_none_or(|solo| obj_pool.includes(obj, solo))
+    }
+}
+
+impl Default for Solo {
+    fn default() -> Self {
+        Solo::new()
+    }
+}
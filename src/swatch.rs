@@ -0,0 +1,178 @@
+use crate::background::{Background, Environment};
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::light::Light;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::object::ObjPool;
+use crate::pattern::{PatternBuilder, PatternSpace};
+use crate::settings::RenderSettings;
+use crate::shape::Shape;
+use crate::tuple::Tuple;
+use crate::world::World;
+
+use std::f64::consts::PI;
+
+/// Width of the studio environment image. It's blurry anyway, so a small image
+/// keeps building the ambient light quick.
+const STUDIO_WIDTH: usize = 256;
+
+/// Softboxes lighting the studio: where each one is, how wide it is in
+/// radians, and how bright. The key light is up and to the left of the camera,
+/// the fill to the right, and the rim behind the swatch.
+const SOFTBOXES: [([f64; 3], f64, f64); 3] = [
+    ([-0.6, 0.6, -0.5], 0.35, 6.0),
+    ([0.8, 0.3, -0.3], 0.45, 1.5),
+    ([0.0, 0.8, 0.6], 0.3, 4.0),
+];
+
+/// Distance of the point lights standing in for the softboxes. Far enough
+/// that they light the swatch from the softboxes' directions.
+const SOFTBOX_DISTANCE: f64 = 100.0;
+
+/// A photo studio as an equirectangular environment image, `width` pixels
+/// across: a dim gray room lit by three round softboxes, brighter than white.
+pub fn studio_environment(width: usize) -> Canvas {
+    let height = (width / 2).max(1);
+    let softboxes: Vec<(Tuple, f64, f64)> = SOFTBOXES
+        .iter()
+        .map(|&([x, y, z], size, brightness)| {
+            (Tuple::vector(x, y, z).normalize(), size, brightness)
+        })
+        .collect();
+
+    let mut image = Canvas::new(width, height);
+    for y in 0..height {
+        let latitude = PI * (0.5 - (y as f64 + 0.5) / height as f64);
+        for x in 0..width {
+            let longitude = 2.0 * PI * ((x as f64 + 0.5) / width as f64 - 0.5);
+            let direction = Tuple::vector(
+                -latitude.cos() * longitude.sin(),
+                latitude.sin(),
+                -latitude.cos() * longitude.cos(),
+            );
+
+            // Darker toward the floor, lighter toward the ceiling.
+            let t = 0.5 + 0.5 * direction.y();
+            let mut color =
+                Color::new(0.05, 0.05, 0.055) * (1.0 - t) + Color::new(0.2, 0.2, 0.21) * t;
+            for (center, size, brightness) in softboxes.iter() {
+                let angle = direction.dot(*center).clamp(-1.0, 1.0).acos();
+                let edge = ((size - angle) / (0.25 * size)).clamp(0.0, 1.0);
+                let falloff = edge * edge * (3.0 - 2.0 * edge);
+                color = color + Color::new(1.0, 1.0, 1.0) * (brightness * falloff);
+            }
+            image.write_pixel(x, y, color);
+        }
+    }
+    image
+}
+
+/// Renders swatches showing off a material: a sphere of it sitting on a
+/// checkered floor in a studio, lit by the studio's environment image and by
+/// point lights in line with its softboxes so highlights match reflections.
+/// The lights turn and brighten along with the environment, so they only make
+/// sense with the studio's own image.
+pub struct MaterialPreview {
+    /// Width and height of each swatch, in pixels.
+    pub size: usize,
+
+    /// How swatches are rendered and tone mapped.
+    pub settings: RenderSettings,
+
+    /// The surroundings. Defaults to `studio_environment`.
+    pub environment: Environment,
+
+    /// The floor's two checker colors.
+    pub floor: (Color, Color),
+}
+
+impl MaterialPreview {
+    pub fn new(size: usize) -> Self {
+        MaterialPreview {
+            size,
+            settings: RenderSettings::new(),
+            environment: Environment::new(studio_environment(STUDIO_WIDTH)),
+            floor: (Color::new(0.8, 0.8, 0.8), Color::new(0.25, 0.25, 0.25)),
+        }
+    }
+
+    /// The preview scene with a sphere of the given material, before any
+    /// camera.
+    pub fn world(&self, material: Material) -> World {
+        let mut obj_pool = ObjPool::new();
+        obj_pool.add_shape(Shape::Sphere, Matrix::translation(0.0, 1.0, 0.0), material);
+
+        let mut floor = Material::new();
+        floor.specular = 0.0;
+        floor.pattern = PatternBuilder::new()
+            .checkers(self.floor.0, self.floor.1)
+            .space(PatternSpace::World)
+            .transform(Matrix::scaling(0.5, 0.5, 0.5))
+            .build();
+        obj_pool.add_ground_disk(floor);
+
+        let rotation = self.environment.rotation();
+        let exposure = 2f64.powf(self.environment.exposure);
+        let lights = SOFTBOXES
+            .iter()
+            .map(|&([x, y, z], _, brightness)| {
+                let direction = rotation * Tuple::vector(x, y, z).normalize();
+                let position = direction * SOFTBOX_DISTANCE;
+                let position = Tuple::point(position.x(), position.y(), position.z());
+                // The key light comes out at full intensity.
+                let intensity = exposure * brightness / SOFTBOXES[0].2;
+                Light::new_point(position, Color::new(intensity, intensity, intensity))
+            })
+            .collect();
+
+        let mut world = World::new(obj_pool, lights);
+        world.background = Background::Environment(self.environment.clone());
+        world.ambient_light = world.background.irradiance();
+        world
+    }
+
+    /// The camera framing the sphere, slightly from above.
+    pub fn camera(&self) -> Camera {
+        let mut camera = Camera::new(self.size, self.size, PI / 5.0);
+        camera.set_settings(self.settings);
+        camera.set_transform(Matrix::view_transform(
+            Tuple::point(0.0, 2.2, -5.5),
+            Tuple::point(0.0, 0.9, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0),
+        ));
+        camera
+    }
+
+    /// Renders one swatch, tone mapped by the settings.
+    pub fn render(&self, material: Material) -> Canvas {
+        let image = self.camera().render(&self.world(material));
+        self.settings.post_process().apply(&image)
+    }
+
+    /// Renders a swatch for each material and lays them out in rows of
+    /// `columns`, left to right and top to bottom, for looking over a whole
+    /// material library at once. Unused cells are left black.
+    pub fn render_grid(
+        &self,
+        materials: impl IntoIterator<Item = Material>,
+        columns: usize,
+    ) -> Canvas {
+        let columns = columns.max(1);
+        let swatches: Vec<Canvas> = materials.into_iter().map(|m| self.render(m)).collect();
+        let rows = swatches.len().div_ceil(columns);
+
+        let mut grid = Canvas::new(columns * self.size, rows * self.size);
+        for (i, swatch) in swatches.iter().enumerate() {
+            let left = (i % columns) * self.size;
+            let top = (i / columns) * self.size;
+            for y in 0..swatch.height {
+                for x in 0..swatch.width {
+                    grid.write_pixel(left + x, top + y, swatch.pixel_at(x, y));
+                }
+            }
+        }
+        grid
+    }
+}
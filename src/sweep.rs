@@ -0,0 +1,327 @@
+use crate::camera::Camera;
+use crate::canvas::{Canvas, Downsample};
+use crate::color::Color;
+use crate::material::Material;
+use crate::object::Obj;
+use crate::post::PostProcess;
+use crate::world::World;
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Material parameters a sweep can vary.
+const MATERIAL_PARAMETERS: [&str; 7] = [
+    "ambient",
+    "diffuse",
+    "specular",
+    "shininess",
+    "reflective",
+    "transparency",
+    "refractive_index",
+];
+
+/// Values a sweep steps through unless told otherwise.
+pub const DEFAULT_STEPS: usize = 8;
+
+/// Frames per second of a sweep encoded as a video, slow enough to read each
+/// value's label.
+pub const VIDEO_FPS: f64 = 2.0;
+
+/// Factor thumbnails are rendered larger by, then shrunk by, to smooth their
+/// edges at little more than one sample per pixel.
+const THUMBNAIL_SUPERSAMPLE: usize = 2;
+
+/// Height of the strip under each thumbnail that its label is written in.
+const LABEL_HEIGHT: usize = 16;
+
+/// Size of each pixel of a label's digits.
+const LABEL_SCALE: usize = 2;
+
+/// What a sweep changes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SweepTarget {
+    /// An object's own material, by the object's id.
+    Object(Obj),
+
+    /// A light, by its index in the scene.
+    Light(usize),
+}
+
+/// A numeric parameter of a material or light, stepped evenly from one value
+/// to another, to compare its effect side by side while tuning it.
+///
+/// Sweeps are written `object:ID.PARAMETER=START..END` for the parameters of
+/// an object's material, such as `object:3.reflective=0..1`, or
+/// `light:INDEX.intensity=START..END` to scale a light's intensity. Parsed
+/// sweeps take `DEFAULT_STEPS` steps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sweep {
+    pub target: SweepTarget,
+    pub parameter: String,
+    pub start: f64,
+    pub end: f64,
+
+    /// Number of values, including both ends.
+    pub steps: usize,
+}
+
+impl Sweep {
+    pub fn new(target: SweepTarget, parameter: &str, start: f64, end: f64, steps: usize) -> Self {
+        Sweep {
+            target,
+            parameter: parameter.to_string(),
+            start,
+            end,
+            steps,
+        }
+    }
+
+    /// The values swept through, from `start` to `end`.
+    pub fn values(&self) -> Vec<f64> {
+        let steps = self.steps.max(1);
+        (0..steps)
+            .map(|i| match steps {
+                1 => self.start,
+                _ => self.start + (self.end - self.start) * i as f64 / (steps - 1) as f64,
+            })
+            .collect()
+    }
+
+    /// Renders a thumbnail, at most `size` pixels across, for each value, and
+    /// lays them out left to right with the value written under each one. The
+    /// parameter is put back as it was afterwards.
+    pub fn render(
+        &self,
+        world: &mut World,
+        camera: &Camera,
+        post: &PostProcess,
+        size: usize,
+    ) -> Result<Canvas, Box<dyn Error>> {
+        let thumbnails = self.thumbnails(world, camera, post, size)?;
+        let (width, height) = (thumbnails[0].width, thumbnails[0].height);
+        let mut strip = Canvas::new(width * thumbnails.len(), height);
+        for (i, thumbnail) in thumbnails.iter().enumerate() {
+            for y in 0..height {
+                for x in 0..width {
+                    strip.write_pixel(i * width + x, y, thumbnail.pixel_at(x, y));
+                }
+            }
+        }
+        Ok(strip)
+    }
+
+    /// Renders a thumbnail, at most `size` pixels across, for each value, with
+    /// the value written under it, such as for the frames of a video stepping
+    /// through them. The parameter is put back as it was afterwards.
+    pub fn thumbnails(
+        &self,
+        world: &mut World,
+        camera: &Camera,
+        post: &PostProcess,
+        size: usize,
+    ) -> Result<Vec<Canvas>, Box<dyn Error>> {
+        let camera = camera.probe(size * THUMBNAIL_SUPERSAMPLE);
+        let width = camera.hsize().div_ceil(THUMBNAIL_SUPERSAMPLE);
+        let height = camera.vsize().div_ceil(THUMBNAIL_SUPERSAMPLE);
+
+        let original = self.original(world)?;
+        let mut thumbnails = Vec::new();
+        for value in self.values() {
+            self.set(world, original, value)?;
+            let image = camera
+                .render(world)
+                .downsample(THUMBNAIL_SUPERSAMPLE, Downsample::Mitchell);
+            let image = post.apply(&image);
+            let mut thumbnail = Canvas::new(width, height + LABEL_HEIGHT);
+            for y in 0..height {
+                for x in 0..width {
+                    thumbnail.write_pixel(x, y, image.pixel_at(x, y));
+                }
+            }
+            draw_label(&mut thumbnail, 2, height + 2, &format_value(value));
+            thumbnails.push(thumbnail);
+        }
+        self.restore(world, original)?;
+        Ok(thumbnails)
+    }
+
+    /// The parameter's value before the sweep.
+    fn original(&self, world: &mut World) -> Result<Original, String> {
+        match self.target {
+            SweepTarget::Object(obj) => {
+                let value = *self.material_parameter(world, obj)?;
+                Ok(Original::Material(obj, value))
+            }
+            SweepTarget::Light(light) => match world.lights.get(light) {
+                Some(l) => Ok(Original::Light(light, l.intensity())),
+                None => Err(format!("no light {}", light)),
+            },
+        }
+    }
+
+    /// Sets a material parameter to a value, or a light's intensity to its
+    /// original intensity scaled by the value.
+    fn set(&self, world: &mut World, original: Original, value: f64) -> Result<(), String> {
+        match original {
+            Original::Material(obj, _) => *self.material_parameter(world, obj)? = value,
+            Original::Light(light, intensity) => {
+                world.lights[light].set_intensity(intensity * value)
+            }
+        }
+        world.scene_changed();
+        Ok(())
+    }
+
+    fn restore(&self, world: &mut World, original: Original) -> Result<(), String> {
+        match original {
+            Original::Material(_, value) => self.set(world, original, value),
+            Original::Light(..) => self.set(world, original, 1.0),
+        }
+    }
+
+    fn material_parameter<'a>(
+        &self,
+        world: &'a mut World,
+        obj: Obj,
+    ) -> Result<&'a mut f64, String> {
+        if obj >= world.obj_pool.len() {
+            return Err(format!("no object {}", obj));
+        }
+        let material = world
+            .obj_pool
+            .material_mut(obj)
+            .ok_or_else(|| format!("object {} has no material of its own", obj))?;
+        material_field(material, &self.parameter)
+            .ok_or_else(|| format!("unknown material parameter '{}'", self.parameter))
+    }
+}
+
+/// A swept parameter's value from before the sweep, to put back afterwards:
+/// an object's material parameter, or a light's intensity.
+#[derive(Copy, Clone)]
+enum Original {
+    Material(Obj, f64),
+    Light(usize, Color),
+}
+
+impl FromStr for Sweep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || {
+            format!(
+                "invalid sweep '{}', expected object:ID.PARAMETER=START..END or \
+                 light:INDEX.intensity=START..END",
+                s
+            )
+        };
+        let (name, range) = s.split_once('=').ok_or_else(usage)?;
+        let (target, parameter) = name.split_once('.').ok_or_else(usage)?;
+        let (kind, index) = target.split_once(':').ok_or_else(usage)?;
+        let index = index.parse().map_err(|_| usage())?;
+        let target = match kind {
+            "object" => SweepTarget::Object(index),
+            "light" => SweepTarget::Light(index),
+            _ => return Err(usage()),
+        };
+        match target {
+            SweepTarget::Object(_) if !MATERIAL_PARAMETERS.contains(&parameter) => {
+                return Err(format!(
+                    "unknown material parameter '{}', expected one of {}",
+                    parameter,
+                    MATERIAL_PARAMETERS.join(", ")
+                ));
+            }
+            SweepTarget::Light(_) if parameter != "intensity" => {
+                return Err(format!(
+                    "unknown light parameter '{}', expected intensity",
+                    parameter
+                ));
+            }
+            _ => (),
+        }
+        let (start, end) = range.split_once("..").ok_or_else(usage)?;
+        let start = start.parse().map_err(|_| usage())?;
+        let end = end.parse().map_err(|_| usage())?;
+        Ok(Sweep::new(target, parameter, start, end, DEFAULT_STEPS))
+    }
+}
+
+impl fmt::Display for Sweep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.target {
+            SweepTarget::Object(obj) => write!(f, "object:{}", obj)?,
+            SweepTarget::Light(light) => write!(f, "light:{}", light)?,
+        }
+        write!(f, ".{}={}..{}", self.parameter, self.start, self.end)
+    }
+}
+
+fn material_field<'a>(material: &'a mut Material, parameter: &str) -> Option<&'a mut f64> {
+    match parameter {
+        "ambient" => Some(&mut material.ambient),
+        "diffuse" => Some(&mut material.diffuse),
+        "specular" => Some(&mut material.specular),
+        "shininess" => Some(&mut material.shininess),
+        "reflective" => Some(&mut material.reflective),
+        "transparency" => Some(&mut material.transparency),
+        "refractive_index" => Some(&mut material.refractive_index),
+        _ => None,
+    }
+}
+
+/// A value written as briefly as it can be to three decimal places.
+fn format_value(value: f64) -> String {
+    let text = format!("{:.3}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_string(),
+        _ => text.to_string(),
+    }
+}
+
+/// Writes a number in white, with its top left corner at (x, y). Characters
+/// other than digits, '.', and '-' are left blank.
+fn draw_label(canvas: &mut Canvas, x: usize, y: usize, text: &str) {
+    let white = Color::new(1.0, 1.0, 1.0);
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i * 4 * LABEL_SCALE;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..LABEL_SCALE {
+                    for dx in 0..LABEL_SCALE {
+                        let px = left + column * LABEL_SCALE + dx;
+                        let py = y + row * LABEL_SCALE + dy;
+                        if px < canvas.width && py < canvas.height {
+                            canvas.write_pixel(px, py, white);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Rows of a character in a 3 by 5 pixel font, the leftmost pixel in the
+/// highest bit.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => [0; 5],
+    }
+}
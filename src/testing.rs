@@ -0,0 +1,92 @@
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::light::Light;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::object::ObjPool;
+use crate::shape::Shape;
+use crate::tuple::Tuple;
+use crate::world::World;
+
+use std::f64::consts::PI;
+
+/// Tolerance for comparing colors, matching the precision of the values in
+/// The Ray Tracer Challenge's tests.
+pub const COLOR_TOLERANCE: f64 = 1e-4;
+
+/// The book's default world: a white point light up and to the left, and two
+/// spheres at the origin, an outer greenish one of radius 1 and an inner white
+/// one of radius 0.5.
+pub fn default_world() -> World {
+    let light = Light::new_point(Tuple::point(-10.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
+
+    let mut obj_pool = ObjPool::new();
+    let mut material = Material::new();
+    material.color = Color::new(0.8, 1.0, 0.6);
+    material.diffuse = 0.7;
+    material.specular = 0.2;
+    obj_pool.add_shape(Shape::Sphere, Matrix::identity(), material);
+    obj_pool.add_shape(
+        Shape::Sphere,
+        Matrix::scaling(0.5, 0.5, 0.5),
+        Material::new(),
+    );
+
+    World::new(obj_pool, vec![light])
+}
+
+/// A camera making square images `size` pixels across, with a 90 degree field
+/// of view, 5 units back from the origin on -z and looking at it. Its center
+/// pixel sees the front of the default world's outer sphere when `size` is odd.
+/// It takes one sample per pixel on one thread, so the same world always
+/// renders exactly the same pixels.
+pub fn fixture_camera(size: usize) -> Camera {
+    let mut camera = Camera::new(size, size, PI / 2.0);
+    camera.set_samples(1);
+    camera.set_threads(1);
+    camera.set_transform(Matrix::view_transform(
+        Tuple::point(0.0, 0.0, -5.0),
+        Tuple::point(0.0, 0.0, 0.0),
+        Tuple::vector(0.0, 1.0, 0.0),
+    ));
+    camera
+}
+
+/// Renders a world with `fixture_camera`. A size of 1 gives just the color
+/// straight ahead; 11 is small enough to render in a moment while still
+/// showing edges, highlights, and shadows.
+pub fn render_fixture(world: &World, size: usize) -> Canvas {
+    fixture_camera(size).render(world)
+}
+
+/// Whether each channel of two colors is within `tolerance` of the other's.
+pub fn colors_close(a: Color, b: Color, tolerance: f64) -> bool {
+    (a.red - b.red).abs() <= tolerance
+        && (a.green - b.green).abs() <= tolerance
+        && (a.blue - b.blue).abs() <= tolerance
+}
+
+/// Panics, showing both colors, unless they're within `tolerance`.
+pub fn assert_color_near(actual: Color, expected: Color, tolerance: f64) {
+    assert!(
+        colors_close(actual, expected, tolerance),
+        "color {:?} is not within {} of {:?}",
+        actual,
+        tolerance,
+        expected
+    );
+}
+
+/// Panics unless a pixel of an image is within `tolerance` of `expected`.
+pub fn assert_pixel_near(image: &Canvas, x: usize, y: usize, expected: Color, tolerance: f64) {
+    assert!(
+        colors_close(image.pixel_at(x, y), expected, tolerance),
+        "pixel ({}, {}) is {:?}, not within {} of {:?}",
+        x,
+        y,
+        image.pixel_at(x, y),
+        tolerance,
+        expected
+    );
+}
@@ -68,6 +68,30 @@ impl Tuple {
     pub fn reflect(self, normal: Tuple) -> Tuple {
         self - normal * 2.0 * self.dot(normal)
     }
+
+    /// The smaller of each component of two tuples.
+    pub fn min(self, rhs: Self) -> Self {
+        let mut elms = [0.0; 4];
+        for ((a, b), c) in self.elms.iter().zip(&rhs.elms).zip(&mut elms) {
+            *c = a.min(*b);
+        }
+        Tuple { elms }
+    }
+
+    /// The larger of each component of two tuples.
+    pub fn max(self, rhs: Self) -> Self {
+        let mut elms = [0.0; 4];
+        for ((a, b), c) in self.elms.iter().zip(&rhs.elms).zip(&mut elms) {
+            *c = a.max(*b);
+        }
+        Tuple { elms }
+    }
+
+    /// Two unit vectors perpendicular to this unit vector and to each other,
+    /// such as the directions along a surface with this normal.
+    pub fn tangents(self) -> (Tuple, Tuple) {
+        orthonormal_basis(self)
+    }
 }
 
 impl PartialEq for Tuple {
@@ -75,8 +99,7 @@ impl PartialEq for Tuple {
         self.elms
             .iter()
             .zip(&other.elms)
-            .map(|(a, b)| close_eq(*a, *b))
-            .all(|p| p)
+            .all(|(a, b)| close_eq(*a, *b))
     }
 }
 
@@ -0,0 +1,190 @@
+use crate::canvas::Canvas;
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Writes an animation's frames into one video file, so it can be played
+/// without assembling an image sequence with another tool first.
+///
+/// The format comes from the file's extension: `.y4m` for uncompressed
+/// YUV4MPEG2 video, which players such as mpv and VLC play directly and
+/// encoders take as input, `.apng` for an animated PNG, which web browsers
+/// play, when built with the png feature, or `.gif` for an animated GIF that
+/// loops, when built with the gif feature. GIFs are small and play almost
+/// anywhere, but each frame is reduced to 256 colors, so they suit quick
+/// previews at small sizes. Frames are clamped to their displayable range, as
+/// for image output.
+pub struct VideoWriter {
+    width: usize,
+    height: usize,
+    frames: usize,
+    written: usize,
+    encoder: Encoder,
+}
+
+enum Encoder {
+    Y4m(BufWriter<File>),
+    #[cfg(feature = "png")]
+    Apng(png::Writer<BufWriter<File>>),
+    #[cfg(feature = "gif")]
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+
+        /// Time each frame is shown, in hundredths of a second.
+        delay: u16,
+    },
+}
+
+impl VideoWriter {
+    /// Creates a video of a number of frames of the given size, played at
+    /// some frames per second.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        width: usize,
+        height: usize,
+        fps: f64,
+        frames: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        if !(fps > 0.0 && fps.is_finite()) {
+            return Err(format!("cannot play video at {} frames per second", fps).into());
+        }
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        let encoder = match extension.as_deref() {
+            Some("y4m") => {
+                let mut file = BufWriter::new(File::create(path)?);
+                let (numerator, denominator) = frame_rate(fps);
+                writeln!(
+                    file,
+                    "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+                    width, height, numerator, denominator
+                )?;
+                Encoder::Y4m(file)
+            }
+            #[cfg(feature = "png")]
+            Some("apng") => {
+                let file = BufWriter::new(File::create(path)?);
+                let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(frames as u32, 0)?;
+                let (numerator, denominator) = frame_rate(fps);
+                let delay = |v: u64| v.min(u16::MAX as u64) as u16;
+                encoder.set_frame_delay(delay(denominator), delay(numerator))?;
+                Encoder::Apng(encoder.write_header()?)
+            }
+            #[cfg(not(feature = "png"))]
+            Some("apng") => return Err("animated PNG support requires the png feature".into()),
+            #[cfg(feature = "gif")]
+            Some("gif") => {
+                if width > u16::MAX as usize || height > u16::MAX as usize {
+                    return Err(format!("a {}x{} GIF is too large", width, height).into());
+                }
+                let file = BufWriter::new(File::create(path)?);
+                let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &[])?;
+                encoder.set_repeat(gif::Repeat::Infinite)?;
+                let delay = (100.0 / fps).round().clamp(1.0, u16::MAX as f64) as u16;
+                Encoder::Gif { encoder, delay }
+            }
+            #[cfg(not(feature = "gif"))]
+            Some("gif") => return Err("animated GIF support requires the gif feature".into()),
+            _ => {
+                return Err(format!(
+                    "unknown video format for {}; use .y4m, .apng, or .gif",
+                    path.display()
+                )
+                .into())
+            }
+        };
+        Ok(VideoWriter {
+            width,
+            height,
+            frames,
+            written: 0,
+            encoder,
+        })
+    }
+
+    /// Appends the next frame.
+    pub fn write_frame(&mut self, image: &Canvas) -> Result<(), Box<dyn Error>> {
+        if image.width != self.width || image.height != self.height {
+            return Err(format!(
+                "cannot add a {}x{} frame to a {}x{} video",
+                image.width, image.height, self.width, self.height
+            )
+            .into());
+        }
+        if self.written == self.frames {
+            return Err(format!("video already has all {} frames", self.frames).into());
+        }
+        let rgb = image.to_rgb8();
+        match &mut self.encoder {
+            Encoder::Y4m(file) => {
+                file.write_all(b"FRAME\n")?;
+                file.write_all(&ycbcr_planes(&rgb))?;
+            }
+            #[cfg(feature = "png")]
+            Encoder::Apng(writer) => writer.write_image_data(&rgb)?,
+            #[cfg(feature = "gif")]
+            Encoder::Gif { encoder, delay } => {
+                let mut frame =
+                    gif::Frame::from_rgb_speed(self.width as u16, self.height as u16, &rgb, 10);
+                frame.delay = *delay;
+                encoder.write_frame(&frame)?;
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Finishes the file, once every frame has been written.
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        if self.written != self.frames {
+            return Err(format!("video has {} of its {} frames", self.written, self.frames).into());
+        }
+        match self.encoder {
+            Encoder::Y4m(mut file) => file.flush()?,
+            #[cfg(feature = "png")]
+            Encoder::Apng(writer) => writer.finish()?,
+            #[cfg(feature = "gif")]
+            Encoder::Gif { encoder, .. } => {
+                encoder.into_inner()?.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Frames per second as a fraction in lowest terms, to a thousandth of a
+/// frame.
+fn frame_rate(fps: f64) -> (u64, u64) {
+    let numerator = ((fps * 1000.0).round() as u64).max(1);
+    let denominator = 1000;
+    let mut a = numerator;
+    let mut b = denominator;
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    (numerator / a, denominator / a)
+}
+
+/// Converts RGB bytes to the Y, Cb, and Cr planes video players expect, by
+/// BT.601 in its studio range, without subsampling the chroma.
+fn ycbcr_planes(rgb: &[u8]) -> Vec<u8> {
+    let pixels = rgb.len() / 3;
+    let mut planes = vec![0; 3 * pixels];
+    for (i, pixel) in rgb.chunks_exact(3).enumerate() {
+        let r = pixel[0] as f64 / 255.0;
+        let g = pixel[1] as f64 / 255.0;
+        let b = pixel[2] as f64 / 255.0;
+        let y = 16.0 + 65.481 * r + 128.553 * g + 24.966 * b;
+        let cb = 128.0 - 37.797 * r - 74.203 * g + 112.0 * b;
+        let cr = 128.0 + 112.0 * r - 93.786 * g - 18.214 * b;
+        planes[i] = y.round() as u8;
+        planes[pixels + i] = cb.round() as u8;
+        planes[2 * pixels + i] = cr.round() as u8;
+    }
+    planes
+}
@@ -0,0 +1,216 @@
+use crate::object::{Obj, ObjPool};
+use crate::tuple::Tuple;
+
+use std::error::Error;
+use std::io::Write;
+
+/// Empty voxels kept around an object's bounds, so its surface is enclosed by
+/// samples outside it.
+const PADDING: usize = 2;
+
+/// Values sampled at the centers of a regular 3D grid of cubic voxels.
+#[derive(Clone, Debug)]
+pub struct VoxelGrid {
+    /// Center of the first voxel.
+    pub origin: Tuple,
+
+    /// Width of a voxel along each axis.
+    pub voxel_size: f64,
+
+    /// Voxels along x, y, and z.
+    pub dimensions: [usize; 3],
+
+    /// One per voxel, with x varying fastest, then y, then z.
+    pub values: Vec<f64>,
+}
+
+impl VoxelGrid {
+    /// Samples which voxels of a grid around an object are inside it, 1.0 if
+    /// inside and 0.0 if not. The object must be a closed solid; see
+    /// `ObjPool::contains`. `resolution` is the number of voxels along the
+    /// longest side of the object's bounds.
+    pub fn occupancy(
+        obj_pool: &ObjPool,
+        obj: Obj,
+        resolution: usize,
+    ) -> Result<VoxelGrid, Box<dyn Error>> {
+        if obj >= obj_pool.len() {
+            return Err(format!("no object {}", obj).into());
+        }
+        let bounds = obj_pool
+            .bounds(obj)
+            .ok_or_else(|| format!("object {} goes on forever", obj))?;
+        let (min, size) = (bounds.min, bounds.size());
+        let longest = size.x().max(size.y()).max(size.z());
+        if longest <= 0.0 || resolution == 0 {
+            return Err(format!("object {} has no volume to sample", obj).into());
+        }
+
+        let voxel_size = longest / resolution as f64;
+        let count = |extent: f64| (extent / voxel_size).ceil().max(1.0) as usize + 2 * PADDING;
+        let dimensions = [count(size.x()), count(size.y()), count(size.z())];
+        let padding = (PADDING as f64 - 0.5) * voxel_size;
+        let origin = min - Tuple::vector(padding, padding, padding);
+
+        let mut grid = VoxelGrid {
+            origin,
+            voxel_size,
+            dimensions,
+            values: Vec::with_capacity(dimensions.iter().product()),
+        };
+        let mut xs = Vec::new();
+        for z in 0..dimensions[2] {
+            for y in 0..dimensions[1] {
+                for x in 0..dimensions[0] {
+                    let inside = obj_pool.contains(obj, grid.center(x, y, z), &mut xs);
+                    grid.values.push(if inside { 1.0 } else { 0.0 });
+                }
+            }
+        }
+        Ok(grid)
+    }
+
+    /// Converts an occupancy grid into signed distances from the surface,
+    /// negative inside the object. Distances are measured between voxel
+    /// centers, taking the surface to be halfway between voxels inside and
+    /// outside, so they're accurate to about half a voxel.
+    pub fn signed_distance(&self) -> VoxelGrid {
+        let inside: Vec<bool> = self.values.iter().map(|&v| v > 0.5).collect();
+        let to_inside = self.distance_transform(|i| inside[i]);
+        let to_outside = self.distance_transform(|i| !inside[i]);
+        let half = 0.5 * self.voxel_size;
+        let values = inside
+            .iter()
+            .enumerate()
+            .map(|(i, &inside)| {
+                if inside {
+                    -(to_outside[i].sqrt() * self.voxel_size - half)
+                } else {
+                    to_inside[i].sqrt() * self.voxel_size - half
+                }
+            })
+            .collect();
+        VoxelGrid {
+            values,
+            ..self.clone()
+        }
+    }
+
+    /// Center of a voxel in world space.
+    pub fn center(&self, x: usize, y: usize, z: usize) -> Tuple {
+        self.origin + Tuple::vector(x as f64, y as f64, z as f64) * self.voxel_size
+    }
+
+    /// Value of a voxel.
+    pub fn value(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.values[self.index(x, y, z)]
+    }
+
+    /// Writes the grid as a legacy VTK file of structured points, which
+    /// ParaView, VisIt, and most meshing and simulation tools can read.
+    pub fn write_vtk<W: Write>(&self, mut writer: W, name: &str) -> Result<(), Box<dyn Error>> {
+        let [nx, ny, nz] = self.dimensions;
+        writeln!(writer, "# vtk DataFile Version 3.0")?;
+        writeln!(writer, "{}", name)?;
+        writeln!(writer, "BINARY")?;
+        writeln!(writer, "DATASET STRUCTURED_POINTS")?;
+        writeln!(writer, "DIMENSIONS {} {} {}", nx, ny, nz)?;
+        writeln!(
+            writer,
+            "ORIGIN {} {} {}",
+            self.origin.x(),
+            self.origin.y(),
+            self.origin.z()
+        )?;
+        let s = self.voxel_size;
+        writeln!(writer, "SPACING {} {} {}", s, s, s)?;
+        writeln!(writer, "POINT_DATA {}", self.values.len())?;
+        writeln!(writer, "SCALARS {} float 1", name)?;
+        writeln!(writer, "LOOKUP_TABLE default")?;
+        for value in &self.values {
+            // VTK's binary data is big endian.
+            writer.write_all(&(*value as f32).to_be_bytes())?;
+        }
+        writeln!(writer)?;
+        Ok(())
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.dimensions[1] + y) * self.dimensions[0] + x
+    }
+
+    /// Squared distance, in voxels, from each voxel to the nearest one chosen
+    /// by `target`. Computed exactly one axis at a time, as in Felzenszwalb and
+    /// Huttenlocher, "Distance Transforms of Sampled Functions" (2012).
+    fn distance_transform(&self, target: impl Fn(usize) -> bool) -> Vec<f64> {
+        let mut distances: Vec<f64> = (0..self.values.len())
+            .map(|i| if target(i) { 0.0 } else { f64::INFINITY })
+            .collect();
+        let [nx, ny, nz] = self.dimensions;
+        let mut line = Vec::new();
+        let mut transformed = Vec::new();
+        for axis in 0..3 {
+            let (length, stride) = match axis {
+                0 => (nx, 1),
+                1 => (ny, nx),
+                _ => (nz, nx * ny),
+            };
+            for start in 0..self.values.len() {
+                // Each line along the axis starts where that axis' coordinate
+                // is zero.
+                if (start / stride) % length != 0 {
+                    continue;
+                }
+                line.clear();
+                line.extend((0..length).map(|i| distances[start + i * stride]));
+                distance_transform_1d(&line, &mut transformed);
+                for (i, d) in transformed.iter().enumerate() {
+                    distances[start + i * stride] = *d;
+                }
+            }
+        }
+        distances
+    }
+}
+
+/// The lower envelope of parabolas rooted at each sample of `f`, sampled at
+/// the same points: for each point, the least of (point - q)^2 + f[q].
+fn distance_transform_1d(f: &[f64], d: &mut Vec<f64>) {
+    let n = f.len();
+    d.clear();
+    let sources: Vec<usize> = (0..n).filter(|&q| f[q].is_finite()).collect();
+    if sources.is_empty() {
+        d.resize(n, f64::INFINITY);
+        return;
+    }
+
+    // Parabolas making up the envelope, and where each takes over.
+    let mut roots = vec![sources[0]];
+    let mut boundaries = vec![f64::NEG_INFINITY];
+    for &q in &sources[1..] {
+        // The first parabola takes over from minus infinity, so it's never
+        // hidden and the envelope never empties.
+        loop {
+            let r = *roots.last().unwrap();
+            let s = ((f[q] + (q * q) as f64) - (f[r] + (r * r) as f64)) / (2 * (q - r)) as f64;
+            if s <= *boundaries.last().unwrap() {
+                roots.pop();
+                boundaries.pop();
+            } else {
+                roots.push(q);
+                boundaries.push(s);
+                break;
+            }
+        }
+    }
+
+    let mut k = 0;
+    for p in 0..n {
+        while k + 1 < boundaries.len() && boundaries[k + 1] < p as f64 {
+            k += 1;
+        }
+        let r = roots[k];
+        let offset = p as f64 - r as f64;
+        d.push(offset * offset + f[r]);
+    }
+}
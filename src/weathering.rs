@@ -0,0 +1,34 @@
+use crate::color::Color;
+use crate::occlusion::AmbientOcclusion;
+
+/// Dirt that collects where a surface is hard to reach, such as in crevices,
+/// inside corners, and where objects meet. How sheltered each point is comes
+/// from occlusion rays cast while shading, so dirt follows the shape of CSG
+/// and mesh models without any texture painting.
+pub struct Weathering {
+    pub dirt: Color,
+
+    /// The rays measuring how sheltered a point is.
+    pub occlusion: AmbientOcclusion,
+
+    /// How quickly shelter turns into dirt. At 1.0 a fully enclosed point is
+    /// entirely dirt, and higher values dirty more exposed points too.
+    pub strength: f64,
+}
+
+impl Weathering {
+    pub fn new(dirt: Color, distance: f64) -> Self {
+        Weathering {
+            dirt,
+            occlusion: AmbientOcclusion::new(16, distance),
+            strength: 1.0,
+        }
+    }
+
+    /// Dirties a surface color, given the fraction of the point's
+    /// surroundings left open by its occlusion rays.
+    pub fn apply(&self, color: Color, exposure: f64) -> Color {
+        let amount = ((1.0 - exposure) * self.strength).clamp(0.0, 1.0);
+        color * (1.0 - amount) + self.dirt * amount
+    }
+}
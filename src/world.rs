@@ -1,118 +1,1200 @@
+use crate::algorithm::cosine_hemisphere;
+use crate::background::Background;
 use crate::color::Color;
+use crate::compiled::CompiledScene;
+use crate::invariants;
+use crate::irradiance::Irradiance;
 use crate::light::*;
+use crate::material::Material;
 use crate::object::*;
+use crate::occlusion::AmbientOcclusion;
+use crate::pattern::{Pattern, PatternSpace, Surface};
 use crate::ray::*;
+use crate::rng::Pcg32;
+use crate::shadow_cache::ShadowCache;
+use crate::shadow_map::ShadowMaps;
+use crate::shape::SurfaceUv;
+use crate::solo::Solo;
 use crate::tuple::Tuple;
 use crate::util::*;
 
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Rays traced by a thread at a time in a batch.
+const BATCH_CHUNK: usize = 256;
+
 pub struct World {
     pub obj_pool: ObjPool,
     pub lights: Vec<Light>,
+
+    /// Seen by rays that miss every object.
+    pub background: Background,
+
+    /// Memoized shadow ray results, for static scenes with many lights.
+    pub shadow_cache: Option<ShadowCache>,
+
+    /// Depth maps used instead of shadow rays for fast draft renders.
+    pub shadow_maps: Option<ShadowMaps>,
+
+    /// Lights and objects isolated or switched off for debugging.
+    pub solo: Solo,
+
+    /// Shades ambient light by how exposed each point is.
+    pub ambient_occlusion: Option<AmbientOcclusion>,
+
+    /// Light from the surroundings, such as an environment image's, giving
+    /// the ambient light its color from each direction. The scene's ambient
+    /// lights scale it.
+    pub ambient_light: Option<Irradiance>,
+
+    /// Shades every object with this material instead of its own, keeping
+    /// the lights and geometry, such as `Material::clay` for lighting studies
+    /// or to tell whether an artifact comes from a shape or its material.
+    /// Bump maps still shape the surfaces, as they're part of the geometry's
+    /// normals.
+    pub material_override: Option<Material>,
+
+    /// How rays treat surfaces that materials' cutouts partly cut away.
+    pub cutout_mode: CutoutMode,
+
+    /// How refraction tells whether rays are entering or leaving objects.
+    pub backface_policy: BackfacePolicy,
+
+    /// The scene flattened for rendering by `compile`, used instead of the
+    /// object pool's groups to find what rays hit.
+    pub compiled: Option<CompiledScene>,
+
+    /// Seed for the random directions shading samples, such as ambient
+    /// occlusion's and dome lights'. Keep it the same as the rendering
+    /// camera's seed.
+    pub seed: u64,
 }
 
 impl World {
     pub fn new(obj_pool: ObjPool, lights: Vec<Light>) -> Self {
-        World { obj_pool, lights }
+        World {
+            obj_pool,
+            lights,
+            background: Background::default(),
+            shadow_cache: None,
+            shadow_maps: None,
+            solo: Solo::new(),
+            ambient_occlusion: None,
+            ambient_light: None,
+            material_override: None,
+            cutout_mode: CutoutMode::Blend,
+            backface_policy: BackfacePolicy::Ordering,
+            compiled: None,
+            seed: 0,
+        }
     }
 
-    pub fn color_at(&self, ray: &Ray, depth: u8) -> Color {
-        let xs = self.obj_pool.intersect(ray);
+    /// Replaces shadow rays with shadow maps of the given resolution, trading
+    /// accuracy for speed in draft renders.
+    pub fn build_shadow_maps(&mut self, resolution: usize) {
+        self.shadow_maps = Some(ShadowMaps::new(&self.obj_pool, &self.lights, resolution));
+    }
 
-        let hit = xs.iter().filter(|x| x.t > 0.0).nth(0);
+    /// Flattens the scene into a form fixed for rendering, with a bounding
+    /// volume hierarchy for rays to find what they hit quickly. See
+    /// `CompiledScene`. Scenes that change compile again in `scene_changed`.
+    pub fn compile(&mut self) {
+        self.compiled = Some(CompiledScene::new(&self.obj_pool, &self.lights));
+    }
 
-        match hit {
-            None => Color::new(0.0, 0.0, 0.0),
-            Some(x) => {
-                let comps = prepare_computations(x, &ray, &self.obj_pool, &xs);
-                self.shade_hit(&comps, depth)
+    /// Refreshes the data derived from the scene after objects or lights move.
+    pub fn scene_changed(&mut self) {
+        if self.compiled.is_some() {
+            self.compile();
+        }
+        if let Some(cache) = &self.shadow_cache {
+            cache.clear();
+        }
+        if let Some(maps) = &self.shadow_maps {
+            let light_radius = maps.light_radius();
+            self.build_shadow_maps(maps.resolution());
+            if let Some(maps) = &mut self.shadow_maps {
+                maps.set_light_radius(light_radius);
             }
         }
     }
 
-    pub fn shade_hit(&self, comps: &Computations, depth: u8) -> Color {
-        let light_sources =
-            PointLighting::new(comps.over_point, &self.obj_pool, self.lights.iter());
+    /// Intersections of a ray with the objects that are part of the render.
+    pub fn intersect(&self, ray: &Ray) -> Vec<Intersection> {
+        let mut xs = Vec::new();
+        self.intersect_into(ray, &mut xs);
+        xs
+    }
 
-        let material = &self.obj_pool.material[comps.object];
-        let color = if let Some(pattern) = &material.pattern {
-            let object_point = self.obj_pool.world_to_object(comps.object, comps.point);
-            pattern.color_at_object(object_point)
-        } else {
-            material.color
+    /// Fills a reusable buffer with the sorted intersections of a ray with the
+    /// objects that are part of the render.
+    pub fn intersect_into(&self, ray: &Ray, xs: &mut Vec<Intersection>) {
+        self.intersect_into_culled(ray, xs, &[]);
+    }
+
+    /// Like `intersect_into`, skipping objects marked by `ObjPool::cull` as
+    /// out of the ray's reach. A compiled scene skips them by its own bounds
+    /// instead.
+    pub fn intersect_into_culled(&self, ray: &Ray, xs: &mut Vec<Intersection>, culled: &[bool]) {
+        invariants::check_point(ray.origin, "ray origin");
+        invariants::check_vector(ray.direction, "ray direction");
+        match &self.compiled {
+            Some(scene) => scene.intersect_into(&self.obj_pool, ray, xs),
+            None => self.obj_pool.intersect_into_culled(ray, xs, culled),
+        }
+        if invariants::ENABLED {
+            for x in xs.iter() {
+                invariants::check_t(x.t, &format!("intersection with object {}", x.obj));
+            }
+        }
+        if self.solo.hides_objects() {
+            xs.retain(|x| self.solo.object_visible(&self.obj_pool, x.obj));
+        }
+    }
+
+    /// The material an object is shaded with.
+    pub fn material(&self, obj: Obj) -> &Material {
+        match (&self.material_override, &self.compiled) {
+            (Some(material), _) => material,
+            (None, Some(scene)) if !self.solo.recolors_objects() => {
+                self.obj_pool.slot_material(scene.material(obj))
+            }
+            (None, _) => self.solo.material(&self.obj_pool, obj),
+        }
+    }
+
+    /// The scene's lights of a kind, with their indices, as the compiled
+    /// scene lists them if there is one.
+    fn lights_of(&self, kind: LightKind) -> impl Iterator<Item = (usize, &Light)> + '_ {
+        let (listed, all) = match &self.compiled {
+            Some(scene) => (Some(scene.lights(kind).iter().copied()), None),
+            None => (None, Some(0..self.lights.len())),
+        };
+        listed
+            .into_iter()
+            .flatten()
+            .chain(all.into_iter().flatten())
+            .map(move |index| (index, &self.lights[index]))
+            .filter(move |(_, light)| light.kind() == kind)
+    }
+
+    /// Where a ray first hits the objects that are part of the render, ready
+    /// for shading. The intersection buffer is scratch space, as for
+    /// `color_at`, left holding the ray's intersections.
+    pub fn hit(&self, ray: &Ray, xs: &mut Vec<Intersection>) -> Option<Computations> {
+        self.intersect_into(ray, xs);
+        self.first_hit(ray, xs)
+    }
+
+    /// Where a ray first hits among its intersections, ready for shading.
+    fn first_hit(&self, ray: &Ray, xs: &[Intersection]) -> Option<Computations> {
+        let stochastic = self.cutout_mode == CutoutMode::Stochastic;
+        let hit = self.visible_hit(ray, xs, stochastic)?;
+        Some(prepare_computations(
+            &hit,
+            ray,
+            &self.obj_pool,
+            xs,
+            self.backface_policy,
+        ))
+    }
+
+    /// The first of a ray's intersections in front of its origin where the
+    /// surface is there, passing through where cutouts cut it away. Partly
+    /// covered surfaces are hit at random, as often as they're covered, when
+    /// `stochastic`, and otherwise always, for `shade_hit` to blend with what's
+    /// behind them.
+    fn visible_hit(
+        &self,
+        ray: &Ray,
+        xs: &[Intersection],
+        stochastic: bool,
+    ) -> Option<Intersection> {
+        xs.iter()
+            .filter(|x| x.t > 0.0)
+            .find(|x| {
+                if self.material(x.obj).cutout.is_none() {
+                    return true;
+                }
+                let comps = prepare_computations(x, ray, &self.obj_pool, xs, self.backface_policy);
+                let coverage = self.coverage(&comps);
+                if coverage <= 0.0 {
+                    false
+                } else if stochastic && coverage < 1.0 {
+                    cutout_random(ray, x) < coverage
+                } else {
+                    true
+                }
+            })
+            .copied()
+    }
+
+    /// How much of the surface at a hit is there, from 0.0 where its
+    /// material's cutout cuts it away to 1.0 where it's solid.
+    pub fn coverage(&self, comps: &Computations) -> f64 {
+        match &self.material(comps.object).cutout {
+            Some(cutout) => clamp(self.pattern_color(cutout, comps).luminance(), 0.0, 1.0),
+            None => 1.0,
+        }
+    }
+
+    /// Where each of many rays first hits the objects that are part of the
+    /// render, for applications tracing their own queries, such as physics,
+    /// sound, or visibility. Like `object_at`, rays pass through where cutouts
+    /// cut surfaces away entirely. Large batches are split among every core,
+    /// and each thread reuses its buffers from ray to ray.
+    pub fn intersect_batch(&self, rays: &[Ray]) -> Vec<Option<HitInfo>> {
+        let trace = |rays: &[Ray]| -> Vec<Option<HitInfo>> {
+            let mut xs = Vec::new();
+            rays.iter()
+                .map(|ray| {
+                    self.intersect_into(ray, &mut xs);
+                    let hit = self.visible_hit(ray, &xs, false)?;
+                    let point = ray.position(hit.t);
+                    Some(HitInfo {
+                        t: hit.t,
+                        object: hit.obj,
+                        point,
+                        normal: self.obj_pool.normal_at(&hit, point),
+                        u: hit.u,
+                        v: hit.v,
+                    })
+                })
+                .collect()
         };
 
-        let surface = color * phong(material, light_sources, &comps.normalv, &comps.eyev);
-        let reflected = self.reflected_color(&comps, depth);
-        let refracted = self.refracted_color(&comps, depth);
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        if threads == 1 || rays.len() < 2 * BATCH_CHUNK {
+            return trace(rays);
+        }
+        let next_chunk = AtomicUsize::new(0);
+        let chunks: Vec<&[Ray]> = rays.chunks(BATCH_CHUNK).collect();
+        let mut hits: Vec<Vec<Option<HitInfo>>> = vec![Vec::new(); chunks.len()];
+        let results = Mutex::new(&mut hits);
+        thread::scope(|s| {
+            for _ in 0..threads.min(chunks.len()) {
+                s.spawn(|| loop {
+                    let index = next_chunk.fetch_add(1, Ordering::Relaxed);
+                    let chunk = match chunks.get(index) {
+                        Some(chunk) => chunk,
+                        None => break,
+                    };
+                    let chunk_hits = trace(chunk);
+                    results.lock().unwrap()[index] = chunk_hits;
+                });
+            }
+        });
+        hits.into_iter().flatten().collect()
+    }
+
+    /// Whether any object that is part of the render lies on the line between
+    /// two points, for visibility queries that don't need to know what or
+    /// where. Any surface blocks the line, whatever its material, except where
+    /// cutouts cut it away entirely. Points on surfaces don't block
+    /// themselves: surfaces within `EPSILON` of either end are ignored.
+    pub fn is_occluded(&self, from: Tuple, to: Tuple) -> bool {
+        let line = to - from;
+        let distance = line.magnitude();
+        if distance <= 2.0 * EPSILON {
+            return false;
+        }
+        let ray = Ray::new(from, line * (1.0 / distance));
+        let hides_objects = self.solo.hides_objects();
+        let blocks = |x: &Intersection| {
+            if x.t <= EPSILON || (hides_objects && !self.solo.object_visible(&self.obj_pool, x.obj))
+            {
+                return false;
+            }
+            if self.material(x.obj).cutout.is_none() {
+                return true;
+            }
+            let comps = prepare_computations(x, &ray, &self.obj_pool, &[], self.backface_policy);
+            self.coverage(&comps) > 0.0
+        };
+        let mut xs = Vec::new();
+        let max_t = distance - EPSILON;
+        match &self.compiled {
+            Some(scene) => scene.any_hit(&self.obj_pool, &ray, max_t, &mut xs, blocks),
+            None => self.obj_pool.any_hit(&ray, max_t, &mut xs, blocks),
+        }
+    }
+
+    /// The object a ray hits first, if any, passing through where cutouts cut
+    /// surfaces away entirely.
+    pub fn object_at(&self, ray: &Ray) -> Option<Obj> {
+        let xs = self.intersect(ray);
+        self.visible_hit(ray, &xs, false).map(|x| x.obj)
+    }
+
+    /// Color seen along a ray, following reflections and refractions up to
+    /// `depth` times. The intersection buffer is scratch space reused by every
+    /// ray traced, so one buffer can serve a whole render thread.
+    pub fn color_at(&self, ray: &Ray, depth: u8, xs: &mut Vec<Intersection>) -> Color {
+        self.color_at_depth(ray, Depth::fixed(depth), xs)
+    }
 
-        if material.reflective > 0.0 && material.transparency > 0.0 {
-            let reflectance = schlick(comps.eyev, comps.normalv, comps.n1, comps.n2);
+    /// Color seen along a ray, following reflections and refractions as far
+    /// as `depth` allows.
+    pub fn color_at_depth(&self, ray: &Ray, depth: Depth, xs: &mut Vec<Intersection>) -> Color {
+        match self.hit(ray, xs) {
+            None => self.background.color_at(ray.direction),
+            Some(comps) => self.shade_hit(&comps, depth, xs),
+        }
+    }
+
+    /// Like `color_at_depth`, for a camera ray that can't reach the objects
+    /// culled for its tile by `ObjPool::cull`, which its first hit skips.
+    pub fn color_at_primary(
+        &self,
+        ray: &Ray,
+        depth: Depth,
+        xs: &mut Vec<Intersection>,
+        culled: &[bool],
+    ) -> Color {
+        self.intersect_into_culled(ray, xs, culled);
+        match self.first_hit(ray, xs) {
+            None => self.background.color_at(ray.direction),
+            Some(comps) => self.shade_hit(&comps, depth, xs),
+        }
+    }
+
+    /// Color of the light leaving a hit toward the ray's origin, following
+    /// reflections and refractions as far as `depth` allows.
+    pub fn shade_hit(
+        &self,
+        comps: &Computations,
+        depth: Depth,
+        xs: &mut Vec<Intersection>,
+    ) -> Color {
+        let lights = self
+            .lights_of(LightKind::Direct)
+            .filter(|(i, _)| self.solo.light_enabled(*i));
+
+        let material = self.material(comps.object);
+        let color = self.surface_color(comps, xs);
+
+        let exposure = match &self.ambient_occlusion {
+            Some(ao) => self.exposure(ao, comps, xs),
+            None => 1.0,
+        };
+        let ambient = self.ambient_fill(comps.normalv) * exposure;
+        let light_sources = PointLighting::new(comps.over_point, self, lights, xs);
+        let lighting = phong(
+            material,
+            ambient,
+            light_sources,
+            &comps.normalv,
+            &comps.eyev,
+        ) + self.dome_lighting(comps, xs) * material.diffuse;
+        let surface = color * lighting + self.emission(comps);
+        let reflected = self.reflected_color(comps, depth, xs);
+        let refracted = self.refracted_color(comps, depth, xs);
+
+        let color = if material.reflective > 0.0 && material.transparency > 0.0 {
+            let reflectance = reflectance(comps, material);
             surface + reflected * reflectance + refracted * (1.0 - reflectance)
         } else {
             surface + reflected + refracted
+        };
+
+        // Blend a partly cut away surface with what's seen through it.
+        let coverage = match self.cutout_mode {
+            CutoutMode::Blend => self.coverage(comps),
+            CutoutMode::Stochastic => 1.0,
+        };
+        let color = if coverage < 1.0 {
+            let through = Ray::new(comps.under_point, -comps.eyev);
+            color * coverage + self.color_at_depth(&through, depth, xs) * (1.0 - coverage)
+        } else {
+            color
+        };
+        color.checked("Whitted shading")
+    }
+
+    /// Color seen along a ray, by Monte Carlo path tracing. Light bounces
+    /// diffusely between surfaces, so emissive materials and the background
+    /// light the scene along with the lights, and ambient terms are ignored.
+    ///
+    /// Each call follows one random path, so average many samples per pixel.
+    /// Paths end after `max_depth` bounces, or earlier by Russian roulette
+    /// once they carry little light.
+    pub fn color_at_pathtraced<R: Rng>(
+        &self,
+        ray: &Ray,
+        max_depth: u8,
+        rng: &mut R,
+        xs: &mut Vec<Intersection>,
+    ) -> Color {
+        self.trace_path(ray, max_depth, rng, xs).0
+    }
+
+    /// Like `color_at_pathtraced`, also returning the path's length: how many
+    /// surfaces it hit before it escaped the scene or ended.
+    pub fn trace_path<R: Rng>(
+        &self,
+        ray: &Ray,
+        max_depth: u8,
+        rng: &mut R,
+        xs: &mut Vec<Intersection>,
+    ) -> (Color, usize) {
+        self.trace_path_with(ray, max_depth, rng, xs, &[], |_| {})
+    }
+
+    /// Like `trace_path`, also adding where the path went to `vertices`,
+    /// starting from the ray's origin. Takes the same random numbers, so the
+    /// path and its light are the same as without recording it.
+    pub fn trace_path_recorded<R: Rng>(
+        &self,
+        ray: &Ray,
+        max_depth: u8,
+        rng: &mut R,
+        xs: &mut Vec<Intersection>,
+        vertices: &mut Vec<PathVertex>,
+    ) -> (Color, usize) {
+        self.trace_path_with(ray, max_depth, rng, xs, &[], |vertex| vertices.push(vertex))
+    }
+
+    /// Like `trace_path`, for a camera ray that can't reach the objects
+    /// culled for its tile by `ObjPool::cull`, which its first hit skips.
+    /// Records the path into `vertices` if given, as `trace_path_recorded`
+    /// does.
+    pub fn trace_primary_path<R: Rng>(
+        &self,
+        ray: &Ray,
+        max_depth: u8,
+        rng: &mut R,
+        xs: &mut Vec<Intersection>,
+        culled: &[bool],
+        mut vertices: Option<&mut Vec<PathVertex>>,
+    ) -> (Color, usize) {
+        self.trace_path_with(ray, max_depth, rng, xs, culled, |vertex| {
+            if let Some(vertices) = &mut vertices {
+                vertices.push(vertex);
+            }
+        })
+    }
+
+    fn trace_path_with<R: Rng>(
+        &self,
+        ray: &Ray,
+        max_depth: u8,
+        rng: &mut R,
+        xs: &mut Vec<Intersection>,
+        culled: &[bool],
+        mut record: impl FnMut(PathVertex),
+    ) -> (Color, usize) {
+        let vertex = |point, kind| PathVertex { point, kind };
+        record(vertex(ray.origin, VertexKind::Camera));
+        let black = Color::new(0.0, 0.0, 0.0);
+        let mut radiance = black;
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *ray;
+        let mut length = 0;
+
+        for bounce in 0..=max_depth {
+            let culled = if bounce == 0 { culled } else { &[] };
+            self.intersect_into_culled(&ray, xs, culled);
+            let comps = match self.visible_hit(&ray, xs, true) {
+                Some(x) => prepare_computations(&x, &ray, &self.obj_pool, xs, self.backface_policy),
+                None => {
+                    radiance = radiance + throughput * self.background.color_at(ray.direction);
+                    record(vertex(ray.origin + ray.direction, VertexKind::Escaped));
+                    break;
+                }
+            };
+            length += 1;
+            let material = self.material(comps.object);
+            let color = self.surface_color(&comps, xs);
+
+            // Light arriving straight from the lights, and from the surface.
+            let lights = self
+                .lights_of(LightKind::Direct)
+                .filter(|(i, _)| self.solo.light_enabled(*i));
+            let light_sources = PointLighting::new(comps.over_point, self, lights, xs);
+            let direct = color
+                * (phong(material, black, light_sources, &comps.normalv, &comps.eyev)
+                    + self.dome_lighting(&comps, xs) * material.diffuse);
+            radiance = radiance + (throughput * (direct + self.emission(&comps))).checked("path");
+
+            if bounce == max_depth {
+                record(vertex(comps.point, VertexKind::Absorbed));
+                break;
+            }
+
+            // Pick how the path continues in proportion to how much light each
+            // way carries, then weight by the odds of that pick.
+            let diffuse = color * material.diffuse;
+            let (reflective, transparency) =
+                if material.reflective > 0.0 && material.transparency > 0.0 {
+                    let reflectance = reflectance(&comps, material);
+                    (reflectance, 1.0 - reflectance)
+                } else {
+                    (material.reflective, material.transparency)
+                };
+            let weights = [diffuse.luminance().max(0.0), reflective, transparency];
+            let total: f64 = weights.iter().sum();
+            if total <= 0.0 {
+                record(vertex(comps.point, VertexKind::Absorbed));
+                break;
+            }
+            let mut pick = rng.gen::<f64>() * total;
+            let event = weights
+                .iter()
+                .position(|&weight| {
+                    pick -= weight;
+                    pick < 0.0
+                })
+                .unwrap_or(0);
+            let odds = weights[event] / total;
+
+            match event {
+                0 => {
+                    let direction = cosine_hemisphere(comps.normalv, rng);
+                    ray = Ray::new(comps.over_point, direction);
+                    throughput = throughput * diffuse * (1.0 / odds);
+                    record(vertex(comps.point, VertexKind::Diffuse));
+                }
+                1 => {
+                    ray = Ray::new(comps.over_point, comps.reflectv);
+                    throughput = throughput * (reflective / odds);
+                    record(vertex(comps.point, VertexKind::Reflection));
+                }
+                _ => {
+                    ray = match refracted_ray(&comps, material) {
+                        Some(ray) => ray,
+                        None => {
+                            record(vertex(comps.point, VertexKind::Absorbed));
+                            break;
+                        }
+                    };
+                    throughput = throughput * (transparency / odds);
+                    record(vertex(comps.point, VertexKind::Refraction));
+                }
+            }
+
+            // Russian roulette: end dim paths at random, and brighten the
+            // survivors to make up for them.
+            if bounce >= 3 {
+                let survival = throughput
+                    .red
+                    .max(throughput.green)
+                    .max(throughput.blue)
+                    .min(0.95);
+                if rng.gen::<f64>() >= survival {
+                    break;
+                }
+                throughput = throughput * (1.0 / survival);
+            }
         }
+
+        (radiance, length)
     }
 
-    pub fn reflected_color(&self, comps: &Computations, depth: u8) -> Color {
-        let material = &self.obj_pool.material[comps.object];
+    /// The object's color at a hit, from its material's pattern and
+    /// weathering.
+    pub fn surface_color(&self, comps: &Computations, xs: &mut Vec<Intersection>) -> Color {
+        let material = self.material(comps.object);
+        let color = match &material.pattern {
+            Some(pattern) => self.pattern_color(pattern, comps),
+            None => material.color,
+        };
+        let color = match &material.weathering {
+            Some(weathering) => {
+                weathering.apply(color, self.exposure(&weathering.occlusion, comps, xs))
+            }
+            None => color,
+        };
+        color.checked("surface color")
+    }
 
-        if depth == 0 || close_eq(material.reflective, 0.0) {
+    /// Light the surface at a hit gives off by itself.
+    pub fn emission(&self, comps: &Computations) -> Color {
+        let material = self.material(comps.object);
+        if comps.inside && !material.two_sided_emission {
             return Color::new(0.0, 0.0, 0.0);
         }
+        let emission = match &material.emission_pattern {
+            Some(pattern) => material.emission * self.pattern_color(pattern, comps),
+            None => material.emission,
+        };
+        emission.checked("emission")
+    }
+
+    /// Color of a pattern at a hit, looked up in the pattern's space, or at
+    /// the hit's texture coordinates on the cap of a cut.
+    fn pattern_color(&self, pattern: &Pattern, comps: &Computations) -> Color {
+        let object_point = match self.obj_pool.cut_uv(comps.object, comps.point) {
+            Some(uv) => Tuple::point(uv.u, uv.v, 0.0),
+            None => match pattern.space() {
+                PatternSpace::Object => self.obj_pool.world_to_object(comps.object, comps.point),
+                PatternSpace::World => comps.point,
+                PatternSpace::Frame(frame) => self.obj_pool.world_to_object(frame, comps.point),
+            },
+        };
+        let surface = SurfacePoint {
+            obj_pool: &self.obj_pool,
+            comps,
+        };
+        pattern.color_at_surface(object_point, &surface)
+    }
+
+    /// Fraction of a light that reaches a point, from 0.0 when fully blocked to
+    /// 1.0 when unobstructed.
+    fn shadowing(
+        &self,
+        point: Tuple,
+        light: usize,
+        light_source: &LightSource,
+        xs: &mut Vec<Intersection>,
+    ) -> f64 {
+        if !light_source.distance.is_finite() {
+            return 1.0;
+        }
+
+        if let Some(shadowing) = self
+            .shadow_maps
+            .as_ref()
+            .and_then(|maps| maps.shadowing(light, point))
+        {
+            return shadowing;
+        }
+
+        let mut trace = || {
+            let shadow_ray = Ray::new(point, light_source.direction);
+            self.transmittance(&shadow_ray, light_source.distance, xs)
+        };
+
+        match &self.shadow_cache {
+            Some(cache) => cache.shadowing(point, light, trace),
+            None => trace(),
+        }
+    }
+
+    /// Ambient light a surface reflects toward the viewer, before ambient
+    /// occlusion darkens it. Shading adds this to the lights' contribution.
+    pub fn ambient_reflected(&self, comps: &Computations, xs: &mut Vec<Intersection>) -> Color {
+        let material = self.material(comps.object);
+        let ambient = self.ambient_fill(comps.normalv);
+        self.surface_color(comps, xs) * ambient * material.ambient
+    }
+
+    /// Ambient light reaching a surface facing along a normal: the sum of the
+    /// scene's ambient lights that aren't switched off, or white if it has
+    /// none, shaded by the surroundings' light if there is any.
+    pub fn ambient_fill(&self, normal: Tuple) -> Color {
+        let mut ambient_lights = self.lights_of(LightKind::Ambient).peekable();
+        let fill = if ambient_lights.peek().is_none() {
+            Color::new(1.0, 1.0, 1.0)
+        } else {
+            ambient_lights
+                .filter(|(i, _)| self.solo.light_enabled(*i))
+                .fold(Color::new(0.0, 0.0, 0.0), |fill, (_, light)| {
+                    fill + light.intensity()
+                })
+        };
+        match &self.ambient_light {
+            Some(irradiance) => irradiance.at(normal) * fill,
+            None => fill,
+        }
+    }
+
+    /// Light reaching a surface from the scene's dome lights, averaged over
+    /// the directions it faces and shadowed by whatever is in the way, so that
+    /// a surface under an unobstructed dome of one color gets that color.
+    pub fn dome_lighting(&self, comps: &Computations, xs: &mut Vec<Intersection>) -> Color {
+        let mut lighting = Color::new(0.0, 0.0, 0.0);
+        for (index, light) in self.lights_of(LightKind::Dome) {
+            let (sky, ground, horizon, samples, intensity) = match light {
+                Light::Dome {
+                    sky,
+                    ground,
+                    horizon,
+                    samples,
+                    intensity,
+                } if *samples > 0 && self.solo.light_enabled(index) => {
+                    (*sky, *ground, *horizon, *samples, *intensity)
+                }
+                _ => continue,
+            };
+
+            // Seeded by the render's seed and the point, like ambient
+            // occlusion, so renders repeat.
+            let point = comps.over_point;
+            let key = (
+                index,
+                point.x().to_bits(),
+                point.y().to_bits(),
+                point.z().to_bits(),
+            );
+            let mut rng = Pcg32::for_key(self.seed, key);
+            let mut seen = Color::new(0.0, 0.0, 0.0);
+            for _ in 0..samples {
+                // Cosine weighted directions weight the light by how squarely
+                // it falls on the surface.
+                let direction = cosine_hemisphere(comps.normalv, &mut rng);
+                let transmittance = self.transmittance(&Ray::new(point, direction), f64::MAX, xs);
+                if transmittance > 0.0 {
+                    seen = seen + dome_color(direction, sky, ground, horizon) * transmittance;
+                }
+            }
+            lighting = lighting + intensity * seen * (1.0 / samples as f64);
+        }
+        lighting.checked("dome lighting")
+    }
+
+    /// Fraction of occlusion rays from a point that escape, from 0.0 when the
+    /// point is enclosed to 1.0 when it is fully exposed.
+    pub fn exposure(
+        &self,
+        ao: &AmbientOcclusion,
+        comps: &Computations,
+        xs: &mut Vec<Intersection>,
+    ) -> f64 {
+        if ao.samples == 0 {
+            return 1.0;
+        }
+
+        let unoccluded: f64 = ao
+            .directions(self.seed, comps.over_point, comps.normalv)
+            .map(|direction| {
+                let ray = Ray::new(comps.over_point, direction);
+                self.transmittance(&ray, ao.distance, xs)
+            })
+            .sum();
+        unoccluded / ao.samples as f64
+    }
+
+    /// Fraction of light passing along a ray up to a distance. Every surface
+    /// on the way dims it by the surface's transparency, and the first opaque
+    /// one ends the search.
+    fn transmittance(&self, ray: &Ray, distance: f64, xs: &mut Vec<Intersection>) -> f64 {
+        let mut transmittance = 1.0;
+        let hides_objects = self.solo.hides_objects();
+        let blocks = |x: &Intersection| {
+            if hides_objects && !self.solo.object_visible(&self.obj_pool, x.obj) {
+                return false;
+            }
+            let material = self.material(x.obj);
+            if !material.casts_shadow {
+                return false;
+            }
+            if material.cutout.is_none() {
+                transmittance *= material.transparency;
+                return transmittance == 0.0;
+            }
+            let comps = prepare_computations(x, ray, &self.obj_pool, &[], self.backface_policy);
+            let coverage = self.coverage(&comps);
+            match self.cutout_mode {
+                CutoutMode::Blend => {
+                    transmittance *= 1.0 - coverage * (1.0 - material.transparency);
+                }
+                CutoutMode::Stochastic => {
+                    if cutout_random(ray, x) < coverage {
+                        transmittance *= material.transparency;
+                    }
+                }
+            }
+            transmittance == 0.0
+        };
+        match &self.compiled {
+            Some(scene) => scene.any_hit(&self.obj_pool, ray, distance, xs, blocks),
+            None => self.obj_pool.any_hit(ray, distance, xs, blocks),
+        };
+        transmittance
+    }
+
+    pub fn reflected_color(
+        &self,
+        comps: &Computations,
+        depth: Depth,
+        xs: &mut Vec<Intersection>,
+    ) -> Color {
+        let material = self.material(comps.object);
+
+        let depth = match depth.next(material.reflective) {
+            Some(depth) if !close_eq(material.reflective, 0.0) => depth,
+            _ => return Color::new(0.0, 0.0, 0.0),
+        };
 
         let reflected_ray = Ray::new(comps.over_point, comps.reflectv);
-        let color = self.color_at(&reflected_ray, depth - 1);
+        let color = self.color_at_depth(&reflected_ray, depth, xs);
 
         color * material.reflective
     }
 
-    pub fn refracted_color(&self, comps: &Computations, depth: u8) -> Color {
-        let material = &self.obj_pool.material[comps.object];
+    pub fn refracted_color(
+        &self,
+        comps: &Computations,
+        depth: Depth,
+        xs: &mut Vec<Intersection>,
+    ) -> Color {
+        let material = self.material(comps.object);
 
-        if depth == 0 || close_eq(material.transparency, 0.0) {
-            return Color::new(0.0, 0.0, 0.0);
+        let depth = match depth.next(material.transparency) {
+            Some(depth) if !close_eq(material.transparency, 0.0) => depth,
+            _ => return Color::new(0.0, 0.0, 0.0),
+        };
+
+        match refracted_ray(comps, material) {
+            Some(ray) => self.color_at_depth(&ray, depth, xs) * material.transparency,
+            None => Color::new(0.0, 0.0, 0.0),
         }
+    }
+}
 
-        // testing for "total internal reflection" using Snell's law and some trig.
-        let n_ratio = comps.n1 / comps.n2;
-        let cos_i = comps.eyev.dot(comps.normalv);
-        let sin2_t = n_ratio * n_ratio * (1.0 - cos_i * cos_i);
-        if sin2_t > 1.0 {
-            return Color::new(0.0, 0.0, 0.0);
+/// How rays treat surfaces that materials' cutouts partly cut away, such as
+/// the soft edges of leaves on cards. Where surfaces are cut away entirely,
+/// rays always pass straight through, and where they're solid, rays always
+/// hit them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CutoutMode {
+    /// Blends each partly covered surface with what's seen through it by its
+    /// coverage, tracing a ray on through it. Exact and free of noise, for
+    /// final renders, but a ray through stacked layers, such as foliage,
+    /// splits at every layer.
+    Blend,
+
+    /// Lets rays through partly covered surfaces at random, as often as
+    /// they're cut away, so rays never split. Much cheaper through stacked
+    /// layers, and averages out to the blended image over a pixel's samples,
+    /// at the cost of noise. Path tracing always treats cutouts this way.
+    Stochastic,
+}
+
+impl FromStr for CutoutMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blend" => Ok(CutoutMode::Blend),
+            "stochastic" => Ok(CutoutMode::Stochastic),
+            _ => Err(format!(
+                "unknown cutout mode '{}', expected blend or stochastic",
+                s
+            )),
         }
+    }
+}
 
-        let cos_t = (1.0 - sin2_t).sqrt();
-        let direction = comps.normalv * (n_ratio * cos_i - cos_t) - comps.eyev * n_ratio;
+impl fmt::Display for CutoutMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CutoutMode::Blend => "blend",
+            CutoutMode::Stochastic => "stochastic",
+        };
+        write!(f, "{}", name)
+    }
+}
 
-        let refracted_ray = Ray::new(comps.under_point, direction);
+/// How refraction tells whether a ray is entering or leaving a transparent
+/// object at a hit, to know which refractive indices it passes between.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BackfacePolicy {
+    /// Goes by the order of the surfaces along the ray: every other surface
+    /// of an object is taken as leaving it. Right for closed objects, but an
+    /// open mesh, a ray grazing an edge, or the separate surfaces of a CSG
+    /// object throw the count off for the rest of the ray.
+    Ordering,
 
-        self.color_at(&refracted_ray, depth - 1) * material.transparency
+    /// Goes by which side of each surface the ray crosses from, by its
+    /// geometric normal, keeping count of the materials the ray is inside.
+    /// Surfaces sharing a material bound one medium, like a mesh's triangles
+    /// or a CSG object's operands. Leaving a medium the ray never entered,
+    /// as through a hole in an open mesh, and grazing a surface change
+    /// nothing, so nested glass keeps the right indices.
+    Normal,
+}
+
+impl FromStr for BackfacePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ordering" => Ok(BackfacePolicy::Ordering),
+            "normal" => Ok(BackfacePolicy::Normal),
+            _ => Err(format!(
+                "unknown backface policy '{}', expected ordering or normal",
+                s
+            )),
+        }
     }
 }
 
+impl fmt::Display for BackfacePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BackfacePolicy::Ordering => "ordering",
+            BackfacePolicy::Normal => "normal",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Which way a ray crosses a surface, going by the surface's geometric
+/// normal.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Crossing {
+    /// Into the solid the surface bounds.
+    Entering,
+
+    /// Out of the solid.
+    Exiting,
+
+    /// Too nearly along the surface to tell.
+    Grazing,
+}
+
+impl Crossing {
+    /// How a ray in a direction crosses a surface with an outward facing
+    /// unit normal.
+    pub fn new(normal: Tuple, direction: Tuple) -> Self {
+        let cos = normal.dot(direction);
+        if cos < -EPSILON {
+            Crossing::Entering
+        } else if cos > EPSILON {
+            Crossing::Exiting
+        } else {
+            Crossing::Grazing
+        }
+    }
+}
+
+/// A point a traced light path passed through, recorded by
+/// `World::trace_path_recorded`.
+#[derive(Copy, Clone, Debug)]
+pub struct PathVertex {
+    pub point: Tuple,
+    pub kind: VertexKind,
+}
+
+/// What happened to a light path at one of its vertices.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VertexKind {
+    /// Where the path started, at the camera.
+    Camera,
+
+    /// Scattered off a surface in a random direction.
+    Diffuse,
+
+    /// Bounced off a mirror-like surface.
+    Reflection,
+
+    /// Bent into or out of a transparent surface.
+    Refraction,
+
+    /// Ended at a surface without going on: the path was as long as allowed,
+    /// the surface passes no light on, or light couldn't leave a transparent
+    /// surface.
+    Absorbed,
+
+    /// Left the scene, a unit along the last ray from the vertex before.
+    Escaped,
+}
+
+/// Random number in [0, 1) deciding whether a ray passes through a partly cut
+/// away surface. The same ray and hit always give the same number, so renders
+/// repeat, while the jittered rays of a pixel's samples each get their own.
+fn cutout_random(ray: &Ray, x: &Intersection) -> f64 {
+    let point = ray.position(x.t);
+    let key = [
+        point.x().to_bits(),
+        point.y().to_bits(),
+        point.z().to_bits(),
+    ];
+    Pcg32::for_key(x.obj as u64, key).gen()
+}
+
+/// How much further reflections and refractions are followed from a ray.
+/// Recursion always stops after a fixed number of bounces, and can also stop
+/// once the surfaces along the way pass on too little light for the rest to
+/// matter. Mirrors facing each other then still reflect deep into each other,
+/// while dimmer reflections stop after a bounce or two.
+#[derive(Copy, Clone, Debug)]
+pub struct Depth {
+    /// Bounces left.
+    pub remaining: u8,
+
+    /// Fraction of the light reaching the ray that is passed back to the
+    /// camera: the product of the reflectivity or transparency of each
+    /// surface on the way.
+    pub throughput: f64,
+
+    /// Throughput below which rays aren't followed further. Zero follows them
+    /// all until no bounces remain.
+    pub min_throughput: f64,
+}
+
+impl Depth {
+    /// Follows rays for `remaining` bounces, however little light they carry.
+    pub fn fixed(remaining: u8) -> Self {
+        Depth::adaptive(remaining, 0.0)
+    }
+
+    /// Follows rays for up to `remaining` bounces while their throughput is
+    /// at least `min_throughput`.
+    pub fn adaptive(remaining: u8, min_throughput: f64) -> Self {
+        Depth {
+            remaining,
+            throughput: 1.0,
+            min_throughput,
+        }
+    }
+
+    /// The depth of a ray continuing from a surface that passes on `weight`
+    /// of its light, or `None` if it isn't worth following.
+    pub fn next(&self, weight: f64) -> Option<Depth> {
+        let throughput = self.throughput * weight;
+        if self.remaining == 0 || throughput < self.min_throughput {
+            return None;
+        }
+        Some(Depth {
+            remaining: self.remaining - 1,
+            throughput,
+            min_throughput: self.min_throughput,
+        })
+    }
+}
+
+/// Fraction of light a transparent surface reflects rather than transmits.
+pub fn reflectance(comps: &Computations, material: &Material) -> f64 {
+    if material.thin_walled {
+        // Light reflects off both faces of a thin shell.
+        let r = schlick(
+            comps.eyev,
+            comps.normalv,
+            comps.n1,
+            material.refractive_index,
+        );
+        2.0 * r / (1.0 + r)
+    } else {
+        schlick(comps.eyev, comps.normalv, comps.n1, comps.n2)
+    }
+}
+
+/// The ray transmitted through a surface, or `None` when it is totally
+/// internally reflected.
+pub fn refracted_ray(comps: &Computations, material: &Material) -> Option<Ray> {
+    if material.thin_walled {
+        // A thin wall has no thickness to bend the ray through.
+        return Some(Ray::new(comps.under_point, -comps.eyev));
+    }
+
+    // testing for "total internal reflection" using Snell's law and some trig.
+    let n_ratio = comps.n1 / comps.n2;
+    let cos_i = comps.eyev.dot(comps.normalv);
+    let sin2_t = n_ratio * n_ratio * (1.0 - cos_i * cos_i);
+    if sin2_t > 1.0 {
+        return None;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let direction = comps.normalv * (n_ratio * cos_i - cos_t) - comps.eyev * n_ratio;
+
+    Some(Ray::new(comps.under_point, direction))
+}
+
+/// Where a ray hit a surface, as returned by `World::intersect_batch`.
+#[derive(Copy, Clone, Debug)]
+pub struct HitInfo {
+    /// Distance along the ray, in multiples of its direction.
+    pub t: f64,
+
+    /// The primitive shape hit.
+    pub object: Obj,
+
+    /// The hit, in world space.
+    pub point: Tuple,
+
+    /// Unit surface normal pointing out of the object, whichever side the ray
+    /// came from.
+    pub normal: Tuple,
+
+    /// Where a triangle was hit, in barycentric coordinates. Zero for other
+    /// shapes.
+    pub u: f64,
+    pub v: f64,
+}
+
+/// Everything shading needs to know about where a ray hit a surface. Made by
+/// `prepare_computations`, or `World::hit`, and passed to `World::shade_hit`,
+/// `World::surface_color`, and the like, so tools baking or debugging a scene,
+/// or tracing it their own way, can shade hits just as renders do.
+#[derive(Copy, Clone, Debug)]
 pub struct Computations {
+    /// Distance along the ray, in multiples of its direction.
     pub t: f64,
+
+    /// The primitive shape hit.
     pub object: Obj,
+
+    /// Where a triangle was hit, in barycentric coordinates. Zero for other
+    /// shapes.
+    pub u: f64,
+    pub v: f64,
+
+    /// The hit, in world space.
     pub point: Tuple,
+
+    /// The hit nudged off the surface to the side the ray came from, and to
+    /// the other side, to start reflected and refracted rays from without them
+    /// hitting the surface again.
     pub over_point: Tuple,
     pub under_point: Tuple,
+
+    /// Unit vector back toward where the ray came from.
     pub eyev: Tuple,
+
+    /// Unit surface normal, turned to face the ray.
     pub normalv: Tuple,
+
+    /// Whether the ray hit the surface from inside the object, in which case
+    /// the normal was turned around.
     pub inside: bool,
+
+    /// Direction of the ray mirrored about the normal.
     pub reflectv: Tuple,
-    pub n1: f64, // refractive index exited
-    pub n2: f64, // refractive index entered
+
+    /// Refractive index of the medium the ray is leaving.
+    pub n1: f64,
+
+    /// Refractive index of the medium the ray is entering.
+    pub n2: f64,
 }
 
+impl Computations {
+    /// Whether the ray is entering or leaving the solid it hit, going by the
+    /// surface's geometric normal rather than its shading normal. See
+    /// `ObjPool::geometric_normal_at`.
+    pub fn crossing(&self, object_pool: &ObjPool) -> Crossing {
+        let hit = Intersection::new_shape_uv(self.t, self.object, self.u, self.v);
+        let normal = object_pool.geometric_normal_at(&hit, self.point);
+        Crossing::new(normal, -self.eyev)
+    }
+}
+
+/// Prepares an intersection for shading. `intersections` are all of the ray's
+/// intersections, sorted as `World::intersect` returns them, which tell which
+/// objects the ray is inside of and so the refractive indices on either side.
 pub fn prepare_computations(
     x: &Intersection,
     ray: &Ray,
     object_pool: &ObjPool,
     intersections: &[Intersection],
+    backface_policy: BackfacePolicy,
 ) -> Computations {
+    invariants::check_unit(ray.direction, "direction of the ray being shaded");
     let point = ray.position(x.t);
     let eyev = -ray.direction;
-    let mut normalv = object_pool.normal_at(x.obj, point);
+    let mut normalv = object_pool.normal_at(x, point);
+    if invariants::ENABLED {
+        invariants::check_point(point, "hit point");
+        invariants::check_unit(normalv, &format!("normal of object {}", x.obj));
+    }
     let inside = normalv.dot(eyev) < 0.0;
     if inside {
         normalv = -normalv;
@@ -120,39 +1202,15 @@ pub fn prepare_computations(
     let over_point = point + normalv * EPSILON;
     let under_point = point - normalv * EPSILON;
     let reflectv = ray.direction.reflect(normalv);
-    let (n1, n2) = {
-        let mut n1 = 1.0;
-        let mut n2 = 1.0;
-
-        let mut containers: Vec<Obj> = Vec::new();
-        for x1 in intersections.iter() {
-            if x1.t == x.t {
-                n1 = containers
-                    .last()
-                    .map(|&o| object_pool.material[o].refractive_index)
-                    .unwrap_or(1.0);
-            }
-
-            if let Some(index) = containers.iter().position(|o| *o == x1.obj) {
-                containers.remove(index);
-            } else {
-                containers.push(x1.obj);
-            }
-
-            if x1.t == x.t {
-                n2 = containers
-                    .last()
-                    .map(|&o| object_pool.material[o].refractive_index)
-                    .unwrap_or(1.0);
-                break;
-            }
-        }
-
-        (n1, n2)
+    let (n1, n2) = match backface_policy {
+        BackfacePolicy::Ordering => indices_by_ordering(x, object_pool, intersections),
+        BackfacePolicy::Normal => indices_by_normal(x, ray, object_pool, intersections),
     };
     Computations {
         t: x.t,
         object: x.obj,
+        u: x.u,
+        v: x.v,
         point,
         over_point,
         under_point,
@@ -165,6 +1223,124 @@ pub fn prepare_computations(
     }
 }
 
+/// Refractive indices on either side of a hit, for `BackfacePolicy::Ordering`.
+fn indices_by_ordering(
+    x: &Intersection,
+    object_pool: &ObjPool,
+    intersections: &[Intersection],
+) -> (f64, f64) {
+    let mut n1 = 1.0;
+    let mut n2 = 1.0;
+
+    let mut containers: Vec<Obj> = Vec::new();
+    for x1 in intersections.iter() {
+        if x1.t == x.t {
+            n1 = containers
+                .last()
+                .map(|&o| object_pool.material(o).refractive_index)
+                .unwrap_or(1.0);
+        }
+
+        // Thin walled objects have no interior, so they never contain a ray.
+        if object_pool.material(x1.obj).thin_walled {
+            // nothing to enter or exit
+        } else if let Some(index) = containers.iter().position(|o| *o == x1.obj) {
+            containers.remove(index);
+        } else {
+            containers.push(x1.obj);
+        }
+
+        if x1.t == x.t {
+            n2 = containers
+                .last()
+                .map(|&o| object_pool.material(o).refractive_index)
+                .unwrap_or(1.0);
+            break;
+        }
+    }
+
+    (n1, n2)
+}
+
+/// Refractive indices on either side of a hit, for `BackfacePolicy::Normal`.
+fn indices_by_normal(
+    x: &Intersection,
+    ray: &Ray,
+    object_pool: &ObjPool,
+    intersections: &[Intersection],
+) -> (f64, f64) {
+    // Media the ray is inside, in the order it entered them, with how many
+    // more times it has entered each than left it.
+    let mut media: Vec<(&Material, usize)> = Vec::new();
+    let index = |media: &[(&Material, usize)]| {
+        media
+            .last()
+            .map_or(1.0, |(material, _)| material.refractive_index)
+    };
+
+    let mut n1 = 1.0;
+    for x1 in intersections.iter() {
+        if x1.t == x.t {
+            n1 = index(&media);
+        }
+
+        // Thin walled objects have no interior, so they never contain a ray.
+        let material = object_pool.material(x1.obj);
+        if !material.thin_walled {
+            let normal = object_pool.geometric_normal_at(x1, ray.position(x1.t));
+            let held = media.iter().position(|(m, _)| std::ptr::eq(*m, material));
+            match (Crossing::new(normal, ray.direction), held) {
+                (Crossing::Entering, Some(i)) => media[i].1 += 1,
+                (Crossing::Entering, None) => media.push((material, 1)),
+                (Crossing::Exiting, Some(i)) => {
+                    media[i].1 -= 1;
+                    if media[i].1 == 0 {
+                        media.remove(i);
+                    }
+                }
+                (Crossing::Exiting, None) | (Crossing::Grazing, _) => {}
+            }
+        }
+
+        if x1.t == x.t {
+            return (n1, index(&media));
+        }
+    }
+    (n1, index(&media))
+}
+
+/// The surface around a hit, as seen by patterns.
+struct SurfacePoint<'a> {
+    obj_pool: &'a ObjPool,
+    comps: &'a Computations,
+}
+
+impl Surface for SurfacePoint<'_> {
+    fn curvature(&self, radius: f64) -> f64 {
+        // Estimate the divergence of the object's normals along the surface,
+        // from the normals a radius away on either side. Only the hit object's
+        // normals count, so edges where two CSG operands meet look flat.
+        let comps = self.comps;
+        let hit = Intersection::new_shape_uv(comps.t, comps.object, comps.u, comps.v);
+        let normal_at = |point: Tuple| self.obj_pool.normal_at(&hit, point);
+        let (t1, t2) = normal_at(comps.point).tangents();
+        let divergence = |tangent: Tuple| {
+            let ahead = normal_at(comps.point + tangent * radius);
+            let behind = normal_at(comps.point - tangent * radius);
+            (ahead - behind).dot(tangent) / (2.0 * radius)
+        };
+        (divergence(t1) + divergence(t2)) / 2.0
+    }
+
+    fn variation(&self) -> f64 {
+        self.obj_pool.variation(self.comps.object)
+    }
+
+    fn uv(&self) -> Option<SurfaceUv> {
+        self.obj_pool.uv_at(self.comps.object, self.comps.point)
+    }
+}
+
 /// Schlick approximation of the Fresnel effect.
 pub fn schlick(eyev: Tuple, normalv: Tuple, n1: f64, n2: f64) -> f64 {
     let mut cos = eyev.dot(normalv);
@@ -191,68 +1367,156 @@ pub fn schlick(eyev: Tuple, normalv: Tuple, n1: f64, n2: f64) -> f64 {
 /// Iterates through lights sources from a world that are illuminating a point.
 struct PointLighting<'a, L>
 where
-    L: Iterator<Item = &'a Light>,
+    L: Iterator<Item = (usize, &'a Light)>,
 {
     point: Tuple,
-    object_pool: &'a ObjPool,
+    world: &'a World,
     lights: L,
+    xs: &'a mut Vec<Intersection>,
 }
 
 impl<'a, L> PointLighting<'a, L>
 where
-    L: Iterator<Item = &'a Light>,
+    L: Iterator<Item = (usize, &'a Light)>,
 {
-    /// Primary PointLighting constructor.
-    fn new(point: Tuple, object_pool: &'a ObjPool, lights: L) -> Self {
+    /// Primary PointLighting constructor. Lights are paired with their index in
+    /// the world. Shadow rays are traced using the intersection buffer.
+    fn new(point: Tuple, world: &'a World, lights: L, xs: &'a mut Vec<Intersection>) -> Self {
         PointLighting {
             point,
-            object_pool,
+            world,
             lights,
+            xs,
         }
     }
 }
 
 impl<'a, L> Iterator for PointLighting<'a, L>
 where
-    L: Iterator<Item = &'a Light>,
+    L: Iterator<Item = (usize, &'a Light)>,
 {
     type Item = LightSource;
 
     fn next(&mut self) -> Option<Self::Item> {
         // Find the next unblocked light.
-        while let Some(light) = self.lights.next() {
+        for (index, light) in self.lights.by_ref() {
             // Get LightSource
             let light_source = match light {
                 Light::Point {
                     position,
                     intensity,
+                    attenuation,
                 } => {
                     let direction = *position - self.point;
                     let distance = direction.magnitude();
                     let direction = direction.normalize();
-                    LightSource::new(*intensity, direction, distance)
+                    let intensity = *intensity * attenuation.factor(distance);
+                    LightSource::new(intensity, direction, distance)
                 }
                 Light::Directional {
                     direction,
                     intensity,
                 } => LightSource::new(*intensity, -*direction, f64::MAX),
+                Light::Spot {
+                    position,
+                    view,
+                    intensity,
+                    attenuation,
+                    cone_angle,
+                    cookie,
+                } => {
+                    let beam = spot_beam(*view * self.point, *cone_angle, cookie.as_ref());
+                    if beam.luminance() <= 0.0 {
+                        continue;
+                    }
+                    let direction = *position - self.point;
+                    let distance = direction.magnitude();
+                    let direction = direction.normalize();
+                    let intensity = *intensity * beam * attenuation.factor(distance);
+                    LightSource::new(intensity, direction, distance)
+                }
+                // Ambient and dome light come from no one direction, and are
+                // shaded apart.
+                Light::Ambient { .. } | Light::Dome { .. } => continue,
             };
 
-            // Trace a shadow ray. The light is blocked if there is an
-            // intersection between the point and the light.
-            let light_blocked = if light_source.distance.is_finite() {
-                let shadow_ray = Ray::new(self.point, light_source.direction);
-                let xs = self.object_pool.intersect(&shadow_ray);
-                xs.iter().any(|x| x.t > 0.0 && x.t < light_source.distance)
-            } else {
-                false
-            };
+            let shadowing = self
+                .world
+                .shadowing(self.point, index, &light_source, self.xs);
 
             // If light is not blocked, it is the next light.
-            if !light_blocked {
-                return Some(light_source);
+            if shadowing > 0.0 {
+                return Some(LightSource {
+                    intensity: light_source.intensity * shadowing,
+                    ..light_source
+                });
             }
         }
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::PatternBuilder;
+    use crate::testing::default_world;
+
+    /// The default world, with its outer sphere cut away entirely.
+    fn cut_away_world() -> World {
+        let mut world = default_world();
+        let black = Color::new(0.0, 0.0, 0.0);
+        let mut material = Material::new();
+        material.cutout = PatternBuilder::new().stripes(black, black).build();
+        world.obj_pool.set_material(0, material);
+        world
+    }
+
+    #[test]
+    fn cut_away_surfaces_do_not_occlude() {
+        // Through the outer sphere, above the inner one.
+        let from = Tuple::point(0.0, 0.75, -5.0);
+        let to = Tuple::point(0.0, 0.75, 5.0);
+        assert!(default_world().is_occluded(from, to));
+        assert!(!cut_away_world().is_occluded(from, to));
+    }
+
+    #[test]
+    fn compiled_scenes_occlude_the_same() {
+        let mut world = cut_away_world();
+        // Through the cut away outer sphere only, through the inner one,
+        // stopping short of it, and missing both.
+        let lines = [
+            ([0.0, 0.75, -5.0], [0.0, 0.75, 5.0], false),
+            ([0.0, 0.0, -5.0], [0.0, 0.0, 5.0], true),
+            ([0.0, 0.0, -5.0], [0.0, 0.0, -0.6], false),
+            ([-5.0, 3.0, 0.0], [5.0, 3.0, 0.0], false),
+        ];
+        let point = |[x, y, z]: [f64; 3]| Tuple::point(x, y, z);
+        for compiled in [false, true] {
+            if compiled {
+                world.compile();
+            }
+            for (from, to, occluded) in lines {
+                let result = world.is_occluded(point(from), point(to));
+                assert_eq!(result, occluded, "{:?} to {:?}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn batches_pass_through_cut_away_surfaces() {
+        let rays = [
+            Ray::new(Tuple::point(0.0, 0.75, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+            Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+        ];
+        let hits = default_world().intersect_batch(&rays);
+        assert_eq!(hits[0].as_ref().map(|hit| hit.object), Some(0));
+        assert_eq!(hits[1].as_ref().map(|hit| hit.t), Some(4.0));
+
+        let hits = cut_away_world().intersect_batch(&rays);
+        assert!(hits[0].is_none());
+        let hit = hits[1].as_ref().unwrap();
+        assert_eq!((hit.object, hit.t), (1, 4.5));
+    }
+}
@@ -0,0 +1,27 @@
+use raytracer::color::Color;
+use raytracer::testing::*;
+
+#[test]
+fn renders_the_default_world_with_the_fixture_camera() {
+    let image = render_fixture(&default_world(), 11);
+    assert_eq!((image.width, image.height), (11, 11));
+    assert_pixel_near(
+        &image,
+        5,
+        5,
+        Color::new(0.38066, 0.47583, 0.2855),
+        COLOR_TOLERANCE,
+    );
+}
+
+#[test]
+fn renders_the_same_fixture_every_time() {
+    let world = default_world();
+    let first = render_fixture(&world, 11);
+    let second = render_fixture(&world, 11);
+    for y in 0..11 {
+        for x in 0..11 {
+            assert_pixel_near(&second, x, y, first.pixel_at(x, y), 0.0);
+        }
+    }
+}